@@ -0,0 +1,203 @@
+use std::collections::HashMap;
+use serde::Serialize;
+use chrono::{DateTime, Utc};
+use winstructs::ntfs::mft_reference::MftReference;
+use crate::flags::Reason;
+use crate::record::UsnEntry;
+
+
+/// What happened to a file during an activity.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+pub enum ActivityAction {
+    Created,
+    Modified,
+    Renamed,
+    Deleted,
+    /// Only other changes, such as to the security or basic info.
+    Changed,
+}
+
+
+/// A rename or move from one name to another.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct RenamePair {
+    pub old_name: String,
+    pub old_parent: MftReference,
+    pub new_name: String,
+    pub new_parent: MftReference,
+    pub usn: u64,
+    pub timestamp: Option<DateTime<Utc>>,
+}
+
+
+/// The records of a file from its first change until the file was closed.
+#[derive(Serialize, Debug, Clone)]
+pub struct FileActivity {
+    pub file_reference: MftReference,
+    /// The parent and name as of the last record.
+    pub parent_reference: MftReference,
+    pub file_name: String,
+    pub source: String,
+    pub first_offset: u64,
+    pub first_usn: u64,
+    pub last_usn: u64,
+    pub first_timestamp: Option<DateTime<Utc>>,
+    pub last_timestamp: Option<DateTime<Utc>>,
+    /// Every reason of the activity's records.
+    pub reason: Reason,
+    pub actions: Vec<ActivityAction>,
+    pub renames: Vec<RenamePair>,
+    pub record_count: usize,
+    /// False if the activity ended without a close record.
+    pub closed: bool,
+    // The old name of a rename waiting for its new name
+    #[serde(skip)]
+    pending_rename: Option<(String, MftReference)>,
+}
+
+impl FileActivity {
+    fn new(entry: &UsnEntry) -> Self {
+        let record = &entry.record;
+
+        FileActivity {
+            file_reference: record.get_file_reference(),
+            parent_reference: record.get_parent_reference(),
+            file_name: record.get_file_name(),
+            source: entry.meta.source.clone(),
+            first_offset: entry.meta.offset,
+            first_usn: record.get_usn(),
+            last_usn: record.get_usn(),
+            first_timestamp: record.get_timestamp(),
+            last_timestamp: record.get_timestamp(),
+            reason: Reason::empty(),
+            actions: Vec::new(),
+            renames: Vec::new(),
+            record_count: 0,
+            closed: false,
+            pending_rename: None,
+        }
+    }
+
+    fn add(&mut self, entry: &UsnEntry) {
+        let record = &entry.record;
+        let reason = record.get_reason_code();
+        let file_name = record.get_file_name();
+
+        if reason.contains(Reason::USN_REASON_RENAME_OLD_NAME) {
+            self.pending_rename = Some((file_name.clone(), record.get_parent_reference()));
+        } else if reason.contains(Reason::USN_REASON_RENAME_NEW_NAME) {
+            // Later records also have the new name reason, but only the first
+            // completes the rename
+            if let Some((old_name, old_parent)) = self.pending_rename.take() {
+                self.renames.push(RenamePair {
+                    old_name,
+                    old_parent,
+                    new_name: file_name.clone(),
+                    new_parent: record.get_parent_reference(),
+                    usn: record.get_usn(),
+                    timestamp: record.get_timestamp()
+                });
+            }
+        }
+
+        // Version 4 records do not have a name
+        if record.get_major_version() != 4 {
+            self.parent_reference = record.get_parent_reference();
+            self.file_name = file_name;
+        }
+
+        self.last_usn = record.get_usn();
+        if let Some(timestamp) = record.get_timestamp() {
+            if self.first_timestamp.is_none() {
+                self.first_timestamp = Some(timestamp);
+            }
+            self.last_timestamp = Some(timestamp);
+        }
+
+        self.reason |= reason;
+        self.record_count += 1;
+        self.closed = reason.contains(Reason::USN_REASON_CLOSE);
+    }
+
+    /// Set the actions from the cumulative reason.
+    fn finish(mut self) -> Self {
+        let modified = Reason::USN_REASON_DATA_OVERWRITE | Reason::USN_REASON_DATA_EXTEND |
+            Reason::USN_REASON_DATA_TRUNCATION | Reason::USN_REASON_NAMED_DATA_OVERWRITE |
+            Reason::USN_REASON_NAMED_DATA_EXTEND | Reason::USN_REASON_NAMED_DATA_TRUNCATION;
+        let renamed = Reason::USN_REASON_RENAME_OLD_NAME | Reason::USN_REASON_RENAME_NEW_NAME;
+
+        let mut actions = Vec::new();
+        if self.reason.contains(Reason::USN_REASON_FILE_CREATE) {
+            actions.push(ActivityAction::Created);
+        }
+        if self.reason.intersects(modified) {
+            actions.push(ActivityAction::Modified);
+        }
+        if self.reason.intersects(renamed) {
+            actions.push(ActivityAction::Renamed);
+        }
+        if self.reason.contains(Reason::USN_REASON_FILE_DELETE) {
+            actions.push(ActivityAction::Deleted);
+        }
+        if actions.is_empty() {
+            actions.push(ActivityAction::Changed);
+        }
+
+        self.actions = actions;
+        self
+    }
+}
+
+
+/// ActivityAggregator coalesces the records of each file reference, from its
+/// first record until a record with `USN_REASON_CLOSE`, into one FileActivity.
+/// Records must be added in USN order, as they are in a $J.
+#[derive(Debug, Default)]
+pub struct ActivityAggregator {
+    open: HashMap<MftReference, FileActivity>,
+}
+
+impl ActivityAggregator {
+    pub fn new() -> Self {
+        ActivityAggregator::default()
+    }
+
+    /// Add a record. Returns the file's activity if the record closes it.
+    pub fn add(&mut self, entry: &UsnEntry) -> Option<FileActivity> {
+        let file_reference = entry.record.get_file_reference();
+
+        let activity = self.open.entry(file_reference)
+            .or_insert_with(|| FileActivity::new(entry));
+        activity.add(entry);
+
+        if activity.closed {
+            self.open.remove(&file_reference)
+                .map(FileActivity::finish)
+        } else {
+            None
+        }
+    }
+
+    /// Take the activities that were not closed, in the order they started.
+    pub fn finish(&mut self) -> Vec<FileActivity> {
+        let mut activities: Vec<FileActivity> = self.open.drain()
+            .map(|(_, activity)| activity.finish())
+            .collect();
+        activities.sort_by_key(|activity| activity.first_usn);
+
+        activities
+    }
+
+    /// Coalesce all the records of an iterator into activities, in the order they
+    /// were closed followed by those that were not.
+    pub fn aggregate<I: Iterator<Item = UsnEntry>>(records: I) -> Vec<FileActivity> {
+        let mut aggregator = ActivityAggregator::new();
+
+        let mut activities: Vec<FileActivity> = records
+            .filter_map(|entry| aggregator.add(&entry))
+            .collect();
+        activities.extend(aggregator.finish());
+
+        activities
+    }
+}
@@ -0,0 +1,157 @@
+use serde::Serialize;
+use chrono::{DateTime, Duration, Utc};
+use crate::record::UsnEntry;
+
+
+/// The kinds of anomalies found between two consecutive records of a source.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+pub enum AnomalyKind {
+    /// The USN went down, such as when old and new data of a wrapped journal are
+    /// next to each other.
+    UsnRegression,
+    /// The USN went down to near zero, such as when the journal was deleted and
+    /// recreated (`fsutil usn deletejournal`) and so has a new journal ID.
+    JournalRestart,
+    /// The USN went up by more than the gap threshold, such as when records were
+    /// removed.
+    UsnGap,
+    /// The timestamp went back by more than the timestamp tolerance, such as when
+    /// the clock was changed.
+    TimestampRegression,
+}
+
+
+/// An anomaly between a record and the record before it in the same source.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Anomaly {
+    pub kind: AnomalyKind,
+    pub source: String,
+    pub previous_offset: u64,
+    pub offset: u64,
+    pub previous_usn: u64,
+    pub usn: u64,
+    pub previous_timestamp: Option<DateTime<Utc>>,
+    pub timestamp: Option<DateTime<Utc>>,
+}
+
+
+/// The last record seen of a source.
+#[derive(Debug)]
+struct PreviousRecord {
+    source: String,
+    offset: u64,
+    usn: u64,
+    timestamp: Option<DateTime<Utc>>,
+}
+
+
+/// AnomalyDetector checks the records of a journal, in the order they were parsed,
+/// for signs that the journal was deleted, recreated or wrapped. Records are only
+/// compared to the record before them from the same source, so the detector is
+/// reset whenever the source changes. Records should not be filtered, as filtering
+/// leaves gaps.
+#[derive(Debug)]
+pub struct AnomalyDetector {
+    usn_gap: u64,
+    restart_usn: u64,
+    timestamp_tolerance: Duration,
+    previous: Option<PreviousRecord>,
+    // The last timestamp seen, as version 4 records do not have one
+    previous_timestamp: Option<DateTime<Utc>>,
+}
+
+impl Default for AnomalyDetector {
+    fn default() -> Self {
+        AnomalyDetector {
+            usn_gap: 0x0100_0000,
+            restart_usn: 0x0010_0000,
+            timestamp_tolerance: Duration::minutes(5),
+            previous: None,
+            previous_timestamp: None,
+        }
+    }
+}
+
+impl AnomalyDetector {
+    pub fn new() -> Self {
+        AnomalyDetector::default()
+    }
+
+    /// Report a USN gap if the USN goes up by more than this. Defaults to 16 MB.
+    pub fn usn_gap(mut self, usn_gap: u64) -> Self {
+        self.usn_gap = usn_gap;
+        self
+    }
+
+    /// Report a USN that goes down to below this as a journal restart. Defaults
+    /// to 1 MB.
+    pub fn restart_usn(mut self, restart_usn: u64) -> Self {
+        self.restart_usn = restart_usn;
+        self
+    }
+
+    /// Report a timestamp regression if the timestamp goes back by more than
+    /// this. Defaults to 5 minutes, as records are not always in time order.
+    pub fn timestamp_tolerance(mut self, timestamp_tolerance: Duration) -> Self {
+        self.timestamp_tolerance = timestamp_tolerance;
+        self
+    }
+
+    /// Check a record against the record before it and return any anomalies.
+    pub fn check(&mut self, entry: &UsnEntry) -> Vec<Anomaly> {
+        let mut anomalies = Vec::new();
+        let usn = entry.record.get_usn();
+        let timestamp = entry.record.get_timestamp();
+
+        let previous = match self.previous {
+            Some(ref previous) if previous.source == entry.meta.source => Some(previous),
+            _ => {
+                self.previous_timestamp = None;
+                None
+            }
+        };
+
+        if let Some(previous) = previous {
+            let make_anomaly = |kind| Anomaly {
+                kind,
+                source: previous.source.clone(),
+                previous_offset: previous.offset,
+                offset: entry.meta.offset,
+                previous_usn: previous.usn,
+                usn,
+                previous_timestamp: previous.timestamp,
+                timestamp
+            };
+
+            if usn < previous.usn {
+                if usn < self.restart_usn {
+                    anomalies.push(make_anomaly(AnomalyKind::JournalRestart));
+                } else {
+                    anomalies.push(make_anomaly(AnomalyKind::UsnRegression));
+                }
+            } else if usn - previous.usn > self.usn_gap {
+                anomalies.push(make_anomaly(AnomalyKind::UsnGap));
+            }
+
+            if let (Some(previous_timestamp), Some(timestamp)) = (self.previous_timestamp, timestamp) {
+                if previous_timestamp - timestamp > self.timestamp_tolerance {
+                    let mut anomaly = make_anomaly(AnomalyKind::TimestampRegression);
+                    anomaly.previous_timestamp = Some(previous_timestamp);
+                    anomalies.push(anomaly);
+                }
+            }
+        }
+
+        self.previous = Some(PreviousRecord {
+            source: entry.meta.source.clone(),
+            offset: entry.meta.offset,
+            usn,
+            timestamp
+        });
+        if timestamp.is_some() {
+            self.previous_timestamp = timestamp;
+        }
+
+        anomalies
+    }
+}
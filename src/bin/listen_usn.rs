@@ -2,6 +2,7 @@
 extern crate log;
 extern crate clap;
 extern crate chrono;
+use std::io;
 use std::thread;
 use std::sync::mpsc;
 use log::LevelFilter;
@@ -9,6 +10,9 @@ use std::process::exit;
 use serde_json::value::Value;
 use clap::{App, Arg, ArgMatches};
 use std::sync::mpsc::{Sender, Receiver};
+use rusty_usn::record::UsnEntry;
+use rusty_usn::flags::FlagFormat;
+use rusty_usn::output::OutputFormat;
 use rusty_usn::liveusn::listener::UsnVolumeListener;
 
 static VERSION: &'static str = "1.1.0";
@@ -27,6 +31,15 @@ fn make_app<'a, 'b>() -> App<'a, 'b> {
         .long("historical")
         .help("List historical records along with listening to new changes.");
 
+    let format_arg = Arg::with_name("format")
+        .short("f")
+        .long("format")
+        .value_name("FORMAT")
+        .takes_value(true)
+        .default_value("jsonl")
+        .possible_values(&["jsonl", "csv", "tsv"])
+        .help("The output format.");
+
     let verbose = Arg::with_name("debug")
         .short("-d")
         .long("debug")
@@ -38,9 +51,10 @@ fn make_app<'a, 'b>() -> App<'a, 'b> {
     App::new("listen_usn")
         .version(VERSION)
         .author("Matthew Seyer <https://github.com/forensicmatt/RustyUsn>")
-        .about("USN listener written in Rust. Output is JSONL.")
+        .about("USN listener written in Rust. Output is JSONL, CSV or TSV.")
         .arg(source_arg)
         .arg(historical_arg)
+        .arg(format_arg)
         .arg(verbose)
 }
 
@@ -98,7 +112,12 @@ fn process_volume(volume_str: &str, options: &ArgMatches) {
     info!("listening on {}", volume_str);
     let historical_flag = options.is_present("historical");
 
-    let (tx, rx): (Sender<Value>, Receiver<Value>) = mpsc::channel();
+    let output_format = OutputFormat::from_name(
+        options.value_of("format").expect("has default")
+    ).expect("used possible values");
+    let mut writer = output_format.get_writer(io::stdout(), FlagFormat::Text);
+
+    let (tx, rx): (Sender<(UsnEntry, Value)>, Receiver<(UsnEntry, Value)>) = mpsc::channel();
 
     let volume_listener = UsnVolumeListener::new(
         volume_str.to_string(),
@@ -112,11 +131,15 @@ fn process_volume(volume_str: &str, options: &ArgMatches) {
 
     loop{
         match rx.recv() {
-            Ok(entry) => {
-                let json_str = serde_json::to_string(
-                    &entry
-                ).unwrap();
-                println!("{}", json_str);
+            Ok((entry, additional)) => {
+                // Flush every entry so that changes are seen as they happen
+                let result = writer.write_entry(&entry, Some(additional))
+                    .and_then(|_| writer.flush());
+
+                if let Err(error) = result {
+                    eprintln!("Error writing entry: {}", error);
+                    exit(-1);
+                }
             },
             Err(_) => panic!("Worker threads disconnected before the solution was found!"),
         }
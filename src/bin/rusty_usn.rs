@@ -3,15 +3,38 @@ extern crate log;
 extern crate clap;
 extern crate chrono;
 use std::fs;
+use std::io;
+use std::ops::Range;
 use std::path::Path;
 use log::LevelFilter;
 use std::process::exit;
-use serde_json::value::Value;
+use serde::Serialize;
+use serde_json::json;
 use clap::{App, Arg, ArgMatches};
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+use winstructs::ntfs::mft_reference::MftReference;
 use rusty_usn::mapping::FolderMapping;
-use rusty_usn::usn::{UsnParserSettings, UsnParser};
+use rusty_usn::index::FileIndex;
+use rusty_usn::image::open_image;
+use rusty_usn::image::ewf::{is_ewf_file, EwfImage};
+use rusty_usn::image::ntfs::{NtfsStream, NtfsVolume};
+use rusty_usn::image::vss::VssVolume;
+use rusty_usn::ReadSeek;
+use mft::MftParser;
+use rusty_usn::usn::{ParseMode, UsnParserSettings, UsnParser};
+use rusty_usn::merge::{MergeOrder, MergedRecords};
+use rusty_usn::anomaly::AnomalyDetector;
+use rusty_usn::activity::{ActivityAggregator, FileActivity};
+use rusty_usn::history::RenameTracker;
+use rusty_usn::lifecycle::LifecycleTracker;
+use rusty_usn::confidence::ConfidenceScorer;
+use rusty_usn::logfile::LogFileParser;
+use rusty_usn::filter::RecordFilter;
+use rusty_usn::output::{DedupWriter, OutputFormat, OutputWriter};
 use rusty_usn::record::UsnEntry;
+use rusty_usn::error::UsnError;
 use rusty_usn::flags;
+use rusty_usn::utils;
 
 static VERSION: &'static str = "1.2.0";
 
@@ -24,6 +47,74 @@ fn is_a_non_negative_number(value: String) -> Result<(), String> {
 }
 
 
+fn is_a_confidence(value: String) -> Result<(), String> {
+    match value.parse::<f64>() {
+        Ok(confidence) if (0.0..=1.0).contains(&confidence) => Ok(()),
+        _ => Err("Expected value to be a number from 0.0 to 1.0.".to_owned()),
+    }
+}
+
+
+fn is_a_datetime(value: String) -> Result<(), String> {
+    parse_datetime(&value).map(|_| ())
+}
+
+
+fn is_a_reference(value: String) -> Result<(), String> {
+    parse_reference(&value).map(|_| ())
+}
+
+
+fn is_a_reason_list(value: String) -> Result<(), String> {
+    flags::Reason::from_name_list(&value).map(|_| ())
+}
+
+
+fn is_an_attribute_list(value: String) -> Result<(), String> {
+    flags::FileAttributes::from_name_list(&value).map(|_| ())
+}
+
+
+fn is_a_source_info_list(value: String) -> Result<(), String> {
+    flags::SourceInfo::from_name_list(&value).map(|_| ())
+}
+
+
+/// Parse a UTC datetime from RFC 3339 ('2019-03-20T21:35:52Z'), 
+/// '2019-03-20 21:35:52' or '2019-03-20'.
+fn parse_datetime(value: &str) -> Result<DateTime<Utc>, String> {
+    if let Ok(datetime) = DateTime::parse_from_rfc3339(value) {
+        return Ok(datetime.with_timezone(&Utc));
+    }
+
+    if let Ok(datetime) = NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S") {
+        return Ok(DateTime::from_utc(datetime, Utc));
+    }
+
+    if let Ok(date) = NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        return Ok(DateTime::from_utc(date.and_hms(0, 0, 0), Utc));
+    }
+
+    Err(format!("Expected value to be a datetime (example: '2019-03-20 21:35:52'), got '{}'.", value))
+}
+
+
+/// Parse a reference in the format of ENTRY-SEQUENCE (example: '61346-10').
+fn parse_reference(value: &str) -> Result<MftReference, String> {
+    let error_message = format!("Expected value to be ENTRY-SEQUENCE (example: '61346-10'), got '{}'.", value);
+
+    let mut parts = value.splitn(2, '-');
+    let entry = parts.next()
+        .and_then(|entry| entry.parse::<u64>().ok())
+        .ok_or_else(|| error_message.clone())?;
+    let sequence = parts.next()
+        .and_then(|sequence| sequence.parse::<u16>().ok())
+        .ok_or_else(|| error_message.clone())?;
+
+    Ok(MftReference::new(entry, sequence))
+}
+
+
 fn make_app<'a, 'b>() -> App<'a, 'b> {
     let source_arg = Arg::with_name("source")
         .short("s")
@@ -41,13 +132,276 @@ fn make_app<'a, 'b>() -> App<'a, 'b> {
         .help("The MFT to use for creating folder mapping.")
         .takes_value(true);
 
+    let image_arg = Arg::with_name("image")
+        .long("image")
+        .help("The source is a raw or EWF (E01) image of an NTFS volume. The $UsnJrnl:$J is read \
+        from the volume, and unless --mft is given, the volume's $MFT is used for the folder \
+        mapping. EWF images are found by their signature and opened from the first segment, \
+        without --image they are carved as a whole.");
+
+    let image_offset_arg = Arg::with_name("image_offset")
+        .long("image-offset")
+        .value_name("BYTES")
+        .takes_value(true)
+        .default_value("0")
+        .validator(is_a_non_negative_number)
+        .help("The offset in bytes of the NTFS volume in the image, for full disk images.");
+
+    let file_index_arg = Arg::with_name("file_index")
+        .long("file-index")
+        .help("Index every file of the MFT and add the file's current path (mft_full_path), \
+        the paths of all its hard links (mft_paths) and whether it still exists (mft_exists) \
+        to each record. Requires --mft or --image.");
+
+    let enrich_arg = Arg::with_name("enrich")
+        .long("enrich")
+        .help("Add the $STANDARD_INFORMATION and $FILE_NAME timestamps (mft_si_*, mft_fn_*), \
+        the logical size (mft_logical_size) and the allocation status (mft_allocated) of the \
+        record's MFT entry if it has the same sequence. Requires --mft or --image.");
+
+    let anomalies_arg = Arg::with_name("anomalies")
+        .long("anomalies")
+        .help("Write anomaly events as JSONL instead of records: USNs that go down \
+        (UsnRegression), go down to near zero (JournalRestart) or jump (UsnGap), and timestamps \
+        that go back (TimestampRegression). These are signs that the journal was deleted, \
+        recreated or wrapped. Filter options are not applied.");
+
+    let activity_arg = Arg::with_name("activity")
+        .long("activity")
+        .conflicts_with_all(&["anomalies", "merge"])
+        .help("Write file activity events as JSONL instead of records. The records of each file \
+        are coalesced from its first record until it is closed into one event with the first and \
+        last timestamps, the cumulative reason, the actions (Created, Modified, Renamed, Deleted) \
+        and old to new name pairs for renames. Filter options are not applied.");
+
+    let rename_history_arg = Arg::with_name("rename_history")
+        .long("rename-history")
+        .value_name("ENTRY-SEQUENCE")
+        .takes_value(true)
+        .validator(is_a_reference)
+        .conflicts_with_all(&["anomalies", "activity", "merge"])
+        .help("Write the rename and move history of this file reference as JSON instead of \
+        records: its first name and every rename, with the old and new name and parent. Use \
+        with --mft to add full names. (example: '61346-10')");
+
+    let lifecycle_arg = Arg::with_name("lifecycle")
+        .long("lifecycle")
+        .conflicts_with_all(&["anomalies", "activity", "rename_history", "merge"])
+        .help("Write one JSONL row per file lifetime, from its create to its delete record, \
+        instead of records: its names, created and deleted times, duration and whether its MFT \
+        entry has since been reused by a higher sequence number. Use with --mft to add the full \
+        name and to check the MFT for reused entries. Filter options are not applied.");
+
+    let logfile_arg = Arg::with_name("logfile")
+        .long("logfile")
+        .conflicts_with_all(&["anomalies", "activity", "rename_history", "lifecycle", "merge"])
+        .help("The source is an NTFS $LogFile. The USN records in the redo data of its log \
+        records are recovered, with the LSN of the log record (meta__lsn). With --image, the \
+        volume's $LogFile is read. For a directory source, files that end with '$LogFile' are \
+        parsed.");
+
+    let vss_arg = Arg::with_name("vss")
+        .long("vss")
+        .requires("image")
+        .conflicts_with_all(&["anomalies", "activity", "rename_history", "lifecycle", "logfile"])
+        .help("Also parse the $UsnJrnl:$J of every Volume Shadow Copy of the image's volume, and \
+        merge their records with the current journal's in --merge order (usn by default, \
+        timestamp order is approximate). \
+        Records from a shadow copy have the identifier of its store (meta__snapshot). Use with \
+        --dedup to merge the records that are in more than one.");
+
+    let merge_arg = Arg::with_name("merge")
+        .long("merge")
+        .value_name("ORDER")
+        .takes_value(true)
+        .possible_values(&["usn", "timestamp"])
+        .help("Merge the records of every $J of a directory source, such as the journals of \
+        several shadow copies, into one stream ordered by USN or timestamp. Each journal is \
+        expected to be in that order already, which a $J is by USN. Timestamps in a $J are not \
+        always increasing, so timestamp order is only approximate.");
+
+    let dedup_arg = Arg::with_name("dedup")
+        .long("dedup")
+        .requires("sightings")
+        .help("Write records that are found more than once, in one source or across the sources \
+        of a directory, only the first time they are found. Records are the same if they have the \
+        same USN, file reference, reason, timestamp and name. Once all sources are parsed, every \
+        source and offset of the records found more than once is written as JSONL to the \
+        --sightings file. The key of every record is kept in memory.");
+
+    let sightings_arg = Arg::with_name("sightings")
+        .long("sightings")
+        .value_name("FILE")
+        .takes_value(true)
+        .requires("dedup")
+        .help("Write the sightings of the records found more than once by --dedup to this file, \
+        which keeps them apart from the records. Required by --dedup.");
+
+    let parse_mode_arg = Arg::with_name("parse_mode")
+        .long("parse-mode")
+        .value_name("MODE")
+        .takes_value(true)
+        .default_value("carve")
+        .possible_values(&["carve", "structured"])
+        .help("How records are found. carve searches for record headers, use it for unallocated \
+        or carved data. structured walks the records of each 4096 byte page of a $J by their \
+        length and carves pages that are damaged. The number of records found by each mode is \
+        written to stderr.");
+
+    let report_arg = Arg::with_name("report")
+        .long("report")
+        .help("Write a summary of each source to stderr: the number of records found, and the \
+        number of record candidates rejected for each reason (misaligned length, bad minor \
+        version, bad name offset, UTF-16 failure, short read, etc.) with the offset of the \
+        first. Read errors, which stop the parsing of a source, are included.");
+
+    let skip_leading_zeros_arg = Arg::with_name("skip_leading_zeros")
+        .long("skip-leading-zeros")
+        .help("Binary search for the first page of the $J that is not all zeros and start \
+        parsing there. Do not use on unallocated or carved data, where zeros are not only at \
+        the start.");
+
     let thread_count = Arg::with_name("threads")
         .short("-t")
         .long("--threads")
         .default_value("0")
         .validator(is_a_non_negative_number)
-        .help("Sets the number of worker threads, defaults to number of CPU cores. \
-        If the --mft option is used, the tool can only run single threaded.");
+        .help("Sets the number of worker threads, defaults to number of CPU cores.");
+
+    let format_arg = Arg::with_name("format")
+        .short("f")
+        .long("format")
+        .value_name("FORMAT")
+        .takes_value(true)
+        .default_value("jsonl")
+        .possible_values(&["jsonl", "csv", "tsv", "bodyfile"])
+        .help("The output format. bodyfile is the Sleuth Kit 3.x bodyfile format for use with \
+        mactime, use with --mft to have full paths as names.");
+
+    let flag_format_arg = Arg::with_name("flag_format")
+        .long("flag-format")
+        .value_name("FLAG_FORMAT")
+        .takes_value(true)
+        .default_value("text")
+        .possible_values(&["text", "array", "short", "integer"])
+        .help("How reason, attribute and source info flags are written to JSONL. text is the \
+        names joined by ' | ', array is a list of the names, short is a list of the names \
+        without the USN_REASON_ style prefix and integer is the raw value. Bits that are not \
+        known flags are kept as a hex value.");
+
+    let reason_arg = Arg::with_name("reason")
+        .long("reason")
+        .value_name("REASONS")
+        .takes_value(true)
+        .validator(is_a_reason_list)
+        .help("Only output records with any of these reason flags. Comma separated \
+        list, the USN_REASON_ prefix is optional. (example: 'FILE_DELETE,RENAME_NEW_NAME')");
+
+    let attribute_arg = Arg::with_name("attribute")
+        .long("attribute")
+        .value_name("ATTRIBUTES")
+        .takes_value(true)
+        .validator(is_an_attribute_list)
+        .help("Only output records with any of these file attribute flags. Comma separated \
+        list, the FILE_ATTRIBUTE_ prefix is optional. (example: 'DIRECTORY')");
+
+    let source_info_arg = Arg::with_name("source_info")
+        .long("source-info")
+        .value_name("SOURCE_INFO")
+        .takes_value(true)
+        .validator(is_a_source_info_list)
+        .help("Only output records with any of these source info flags. Comma separated \
+        list, the USN_SOURCE_ prefix is optional.");
+
+    let after_arg = Arg::with_name("after")
+        .long("after")
+        .value_name("DATETIME")
+        .takes_value(true)
+        .validator(is_a_datetime)
+        .help("Only output records with a timestamp at or after this UTC time. \
+        (example: '2019-03-20 21:35:52')");
+
+    let before_arg = Arg::with_name("before")
+        .long("before")
+        .value_name("DATETIME")
+        .takes_value(true)
+        .validator(is_a_datetime)
+        .help("Only output records with a timestamp at or before this UTC time. \
+        (example: '2019-03-20 21:35:52')");
+
+    let confidence_arg = Arg::with_name("confidence")
+        .long("confidence")
+        .help("Add a confidence score from 0.0 to 1.0 to each record, the fraction of the \
+        plausibility checks it passed, and the failed_checks. The checks are a timestamp inside \
+        the plausible time window, no reserved flag bits, a record length that fits the name, a \
+        printable name, and plausible references. With --mft or --image, entries past the end \
+        of the MFT are not plausible.");
+
+    let min_confidence_arg = Arg::with_name("min_confidence")
+        .long("min-confidence")
+        .value_name("SCORE")
+        .takes_value(true)
+        .validator(is_a_confidence)
+        .help("Only output records with a confidence score of at least this score, to drop \
+        false positives when carving unallocated space. (example: '0.8')");
+
+    let plausible_after_arg = Arg::with_name("plausible_after")
+        .long("plausible-after")
+        .value_name("DATETIME")
+        .takes_value(true)
+        .validator(is_a_datetime)
+        .help("Timestamps before this UTC time fail the confidence timestamp check. Defaults to \
+        1999-01-01.");
+
+    let plausible_before_arg = Arg::with_name("plausible_before")
+        .long("plausible-before")
+        .value_name("DATETIME")
+        .takes_value(true)
+        .validator(is_a_datetime)
+        .help("Timestamps after this UTC time fail the confidence timestamp check. Defaults to a \
+        day from now.");
+
+    let min_usn_arg = Arg::with_name("min_usn")
+        .long("min-usn")
+        .value_name("USN")
+        .takes_value(true)
+        .validator(is_a_non_negative_number)
+        .help("Only output records with a USN greater than or equal to this USN.");
+
+    let max_usn_arg = Arg::with_name("max_usn")
+        .long("max-usn")
+        .value_name("USN")
+        .takes_value(true)
+        .validator(is_a_non_negative_number)
+        .help("Only output records with a USN less than or equal to this USN.");
+
+    let name_regex_arg = Arg::with_name("name_regex")
+        .long("name-regex")
+        .value_name("REGEX")
+        .takes_value(true)
+        .conflicts_with("name_glob")
+        .help("Only output records whose file name matches this regular expression.");
+
+    let name_glob_arg = Arg::with_name("name_glob")
+        .long("name-glob")
+        .value_name("GLOB")
+        .takes_value(true)
+        .help("Only output records whose file name matches this case insensitive \
+        glob. (example: '*.exe')");
+
+    let file_ref_arg = Arg::with_name("file_ref")
+        .long("file-ref")
+        .value_name("ENTRY-SEQUENCE")
+        .takes_value(true)
+        .validator(is_a_reference)
+        .help("Only output records for this file reference. (example: '61346-10')");
+
+    let parent_ref_arg = Arg::with_name("parent_ref")
+        .long("parent-ref")
+        .value_name("ENTRY-SEQUENCE")
+        .takes_value(true)
+        .validator(is_a_reference)
+        .help("Only output records whose parent is this reference. (example: '83529-2')");
 
     let verbose = Arg::with_name("debug")
         .short("-d")
@@ -60,14 +414,156 @@ fn make_app<'a, 'b>() -> App<'a, 'b> {
     App::new("rusty_usn")
         .version(VERSION)
         .author("Matthew Seyer <https://github.com/forensicmatt/RustyUsn>")
-        .about("USN Parser written in Rust. Output is JSONL.")
+        .about("USN Parser written in Rust. Output is JSONL, CSV, TSV or bodyfile.")
         .arg(source_arg)
         .arg(usn_arg)
+        .arg(image_arg)
+        .arg(image_offset_arg)
+        .arg(file_index_arg)
+        .arg(enrich_arg)
+        .arg(anomalies_arg)
+        .arg(activity_arg)
+        .arg(rename_history_arg)
+        .arg(lifecycle_arg)
+        .arg(logfile_arg)
+        .arg(vss_arg)
+        .arg(merge_arg)
+        .arg(dedup_arg)
+        .arg(sightings_arg)
+        .arg(parse_mode_arg)
+        .arg(report_arg)
+        .arg(skip_leading_zeros_arg)
         .arg(thread_count)
+        .arg(format_arg)
+        .arg(flag_format_arg)
+        .arg(reason_arg)
+        .arg(attribute_arg)
+        .arg(source_info_arg)
+        .arg(after_arg)
+        .arg(before_arg)
+        .arg(min_usn_arg)
+        .arg(max_usn_arg)
+        .arg(confidence_arg)
+        .arg(min_confidence_arg)
+        .arg(plausible_after_arg)
+        .arg(plausible_before_arg)
+        .arg(name_regex_arg)
+        .arg(name_glob_arg)
+        .arg(file_ref_arg)
+        .arg(parent_ref_arg)
         .arg(verbose)
 }
 
 
+fn make_record_filter(options: &ArgMatches, confidence_scorer: Option<&ConfidenceScorer>) -> RecordFilter {
+    let mut filter = RecordFilter::new();
+
+    if let Some(value) = options.value_of("reason") {
+        filter = filter.reason(
+            flags::Reason::from_name_list(value).expect("used validator")
+        );
+    }
+
+    if let Some(value) = options.value_of("attribute") {
+        filter = filter.file_attributes(
+            flags::FileAttributes::from_name_list(value).expect("used validator")
+        );
+    }
+
+    if let Some(value) = options.value_of("source_info") {
+        filter = filter.source_info(
+            flags::SourceInfo::from_name_list(value).expect("used validator")
+        );
+    }
+
+    if let Some(value) = options.value_of("after") {
+        filter = filter.after(parse_datetime(value).expect("used validator"));
+    }
+
+    if let Some(value) = options.value_of("before") {
+        filter = filter.before(parse_datetime(value).expect("used validator"));
+    }
+
+    if let Some(value) = options.value_of("min_usn") {
+        filter = filter.min_usn(value.parse::<u64>().expect("used validator"));
+    }
+
+    if let Some(value) = options.value_of("max_usn") {
+        filter = filter.max_usn(value.parse::<u64>().expect("used validator"));
+    }
+
+    if let Some(value) = options.value_of("file_ref") {
+        filter = filter.file_reference(parse_reference(value).expect("used validator"));
+    }
+
+    if let Some(value) = options.value_of("parent_ref") {
+        filter = filter.parent_reference(parse_reference(value).expect("used validator"));
+    }
+
+    if let (Some(value), Some(scorer)) = (options.value_of("min_confidence"), confidence_scorer) {
+        filter = filter.min_confidence(
+            scorer.clone(),
+            value.parse::<f64>().expect("used validator")
+        );
+    }
+
+    let name_filter = match (options.value_of("name_regex"), options.value_of("name_glob")) {
+        (Some(pattern), _) => filter.name_regex(pattern),
+        (None, Some(pattern)) => filter.name_glob(pattern),
+        (None, None) => Ok(filter)
+    };
+
+    match name_filter {
+        Ok(filter) => filter,
+        Err(error) => {
+            eprintln!("{}", error);
+            exit(-1);
+        }
+    }
+}
+
+
+/// Get the confidence scorer from the options. With --mft or --image, the last
+/// entry of the MFT is the highest plausible entry.
+fn make_confidence_scorer(options: &ArgMatches) -> ConfidenceScorer {
+    let mut scorer = ConfidenceScorer::new();
+
+    if let Some(value) = options.value_of("plausible_after") {
+        scorer = scorer.earliest(parse_datetime(value).expect("used validator"));
+    }
+
+    if let Some(value) = options.value_of("plausible_before") {
+        scorer = scorer.latest(parse_datetime(value).expect("used validator"));
+    }
+
+    let entry_count = if let Some(mft_path) = options.value_of("mft") {
+        match MftParser::from_path(mft_path) {
+            Ok(parser) => Some(parser.get_entry_count()),
+            Err(error) => {
+                eprintln!("Error opening MFT {}. {}", mft_path, error);
+                None
+            }
+        }
+    } else {
+        match (options.value_of("source"), get_image_offset(options)) {
+            (Some(image_path), Some(offset)) => match read_image_mft(image_path, offset) {
+                Ok(parser) => Some(parser.get_entry_count()),
+                Err(error) => {
+                    eprintln!("Error reading the $MFT of {}: {}", image_path, error);
+                    None
+                }
+            },
+            _ => None
+        }
+    };
+
+    match entry_count {
+        Some(entry_count) if entry_count > 0 => scorer.max_entry(entry_count - 1),
+        _ => scorer
+    }
+}
+
+
 fn set_debug_level(matches: &ArgMatches){
     // Get the possible logging level supplied by the user
     let message_level = match matches.is_present("debug") {
@@ -117,6 +613,14 @@ fn set_debug_level(matches: &ArgMatches){
 }
 
 
+fn write_entry(writer: &mut dyn OutputWriter, entry: &UsnEntry, additional: Option<serde_json::Value>) {
+    if let Err(error) = writer.write_entry(entry, additional) {
+        eprintln!("Error writing entry: {}", error);
+        exit(-1);
+    }
+}
+
+
 fn is_directory(source: &str)->bool{
     // Check if a source is a directory
     let metadata = match fs::metadata(source) {
@@ -132,7 +636,12 @@ fn is_directory(source: &str)->bool{
 }
 
 
-fn process_directory(directory: &str, options: &ArgMatches) {
+fn process_directory(directory: &str, options: &ArgMatches, confidence_scorer: Option<&ConfidenceScorer>, writer: &mut dyn OutputWriter) {
+    let suffix = match options.is_present("logfile") {
+        true => "$logfile",
+        false => "$j"
+    };
+
     for dir_reader in fs::read_dir(directory) {
         for entry_result in dir_reader {
             match entry_result {
@@ -140,15 +649,15 @@ fn process_directory(directory: &str, options: &ArgMatches) {
                     let path = entry.path();
                     if path.is_file() {
                         let path_string = path.into_os_string().into_string().unwrap();
-                        if path_string.to_lowercase().ends_with("$j"){
+                        if path_string.to_lowercase().ends_with(suffix){
                             process_file(
-                                &path_string, &options
+                                &path_string, &options, confidence_scorer, writer
                             );
                         }
                     } else if path.is_dir(){
                         let path_string = path.into_os_string().into_string().unwrap();
                         process_directory(
-                            &path_string, &options
+                            &path_string, &options, confidence_scorer, writer
                         );
                     }
                 },
@@ -161,14 +670,415 @@ fn process_directory(directory: &str, options: &ArgMatches) {
 }
 
 
-fn process_file(file_location: &str, options: &ArgMatches) {
+/// Find every file that ends with '$J' in a directory and its subdirectories.
+fn find_journals(directory: &str, file_locations: &mut Vec<String>) {
+    let dir_reader = match fs::read_dir(directory) {
+        Ok(dir_reader) => dir_reader,
+        Err(error) => {
+            eprintln!("Error reading {} [{:?}]", directory, error);
+            return;
+        }
+    };
+
+    let mut paths: Vec<_> = dir_reader
+        .filter_map(|entry_result| match entry_result {
+            Ok(entry) => Some(entry.path()),
+            Err(error) => {
+                eprintln!("Error reading {} [{:?}]", directory, error);
+                None
+            }
+        })
+        .collect();
+    paths.sort();
+
+    for path in paths {
+        let path_string = path.to_string_lossy().to_string();
+        if path.is_file() {
+            if path_string.to_lowercase().ends_with("$j") {
+                file_locations.push(path_string);
+            }
+        } else if path.is_dir() {
+            find_journals(&path_string, file_locations);
+        }
+    }
+}
+
+
+fn process_file(file_location: &str, options: &ArgMatches, confidence_scorer: Option<&ConfidenceScorer>, writer: &mut dyn OutputWriter) {
     info!("processing {}", file_location);
 
+    let image_offset = get_image_offset(options);
+
+    if options.is_present("anomalies") {
+        detect_anomalies(file_location, image_offset, options);
+        return;
+    }
+
+    let mut annotator = RecordAnnotator::from_options(options, file_location, image_offset, confidence_scorer);
+
+    if options.is_present("logfile") {
+        let filter = make_record_filter(options, confidence_scorer);
+        write_logfile_records(file_location, image_offset, &filter, &annotator, writer);
+        return;
+    }
+
+    annotator.add_journal(file_location, image_offset, options);
+
+    if options.is_present("activity") {
+        aggregate_activity(file_location, image_offset, options, &annotator);
+        return;
+    }
+
+    if options.is_present("lifecycle") {
+        write_lifecycle(file_location, image_offset, options, &annotator);
+        return;
+    }
+
+    if let Some(value) = options.value_of("rename_history") {
+        let file_reference = parse_reference(value).expect("used validator");
+        write_rename_history(file_location, image_offset, options, &annotator, file_reference);
+        return;
+    }
+
+    let config = get_parser_settings(options, make_record_filter(options, confidence_scorer));
+    let mut parser = match open_parser(file_location, image_offset, config) {
+        Some(parser) => parser,
+        None => return
+    };
+
+    for entry in parser.records() {
+        write_entry(writer, &entry, annotator.get_additional(&entry));
+    }
+
+    report_statistics(file_location, &parser, options);
+}
+
+
+/// Write the USN records recovered from a $LogFile, or from the $LogFile of the
+/// volume in an image. Full names are only from the MFT, as the log does not have
+/// the journal's history.
+fn write_logfile_records(file_location: &str, image_offset: Option<u64>, filter: &RecordFilter, annotator: &RecordAnnotator, writer: &mut dyn OutputWriter) {
+    let handle: Result<Box<dyn ReadSeek>, String> = match image_offset {
+        Some(offset) => open_image(file_location)
+            .and_then(|image| NtfsVolume::new(image, offset))
+            .and_then(|volume| volume.into_logfile_stream())
+            .map(|stream| Box::new(stream) as Box<dyn ReadSeek>)
+            .map_err(|error| error.to_string()),
+        None => fs::File::open(file_location)
+            .map(|file| Box::new(file) as Box<dyn ReadSeek>)
+            .map_err(|error| error.to_string())
+    };
+
+    let mut parser = match handle.and_then(|handle|
+        LogFileParser::from_read_seek(file_location.to_string(), handle)
+            .map_err(|error| error.to_string())
+    ) {
+        Ok(parser) => parser,
+        Err(error) => {
+            eprintln!("Error opening the $LogFile of {}: {}", file_location, error);
+            return;
+        }
+    };
+
+    for entry in parser.records().filter(|entry| filter.matches(entry)) {
+        write_entry(writer, &entry, annotator.get_additional(&entry));
+    }
+}
+
+
+/// Write the anomalies of a journal as JSONL. Every record is checked, as
+/// filtering would leave gaps.
+fn detect_anomalies(file_location: &str, image_offset: Option<u64>, options: &ArgMatches) {
+    let config = get_parser_settings(options, RecordFilter::new());
+    let mut parser = match open_parser(file_location, image_offset, config) {
+        Some(parser) => parser,
+        None => return
+    };
+
+    let mut detector = AnomalyDetector::new();
+    for entry in parser.records() {
+        for anomaly in detector.check(&entry) {
+            print_json(&anomaly, None);
+        }
+    }
+
+    report_statistics(file_location, &parser, options);
+}
+
+
+/// Write the file activity of a journal as JSONL, with the full name as of the
+/// activity's last record if there is a folder mapping.
+fn aggregate_activity(file_location: &str, image_offset: Option<u64>, options: &ArgMatches, annotator: &RecordAnnotator) {
+    let config = get_parser_settings(options, RecordFilter::new());
+    let mut parser = match open_parser(file_location, image_offset, config) {
+        Some(parser) => parser,
+        None => return
+    };
+
+    let flag_format = get_flag_format(options);
+    let write_activity = |activity: &FileActivity| {
+        let full_name = annotator.get_full_name(
+            &activity.parent_reference,
+            &activity.file_name,
+            activity.last_usn
+        );
+        let mut value = json!(activity);
+        value["reason"] = activity.reason.to_json_value(flag_format);
+        print_json(&value, full_name);
+    };
+
+    let mut aggregator = ActivityAggregator::new();
+    for entry in parser.records() {
+        if let Some(activity) = aggregator.add(&entry) {
+            write_activity(&activity);
+        }
+    }
+
+    for activity in aggregator.finish() {
+        write_activity(&activity);
+    }
+
+    report_statistics(file_location, &parser, options);
+}
+
+
+/// Write the lifetime of each file of a journal as JSONL, with the full name as
+/// of the lifetime's last record if there is a folder mapping.
+fn write_lifecycle(file_location: &str, image_offset: Option<u64>, options: &ArgMatches, annotator: &RecordAnnotator) {
+    let config = get_parser_settings(options, RecordFilter::new());
+    let mut parser = match open_parser(file_location, image_offset, config) {
+        Some(parser) => parser,
+        None => return
+    };
+
+    let lifetimes = LifecycleTracker::track(
+        parser.records(),
+        annotator.file_index.as_ref()
+    );
+
+    for lifetime in lifetimes {
+        let full_name = lifetime.names.last().and_then(|file_name|
+            annotator.get_full_name(&lifetime.parent_reference, file_name, lifetime.last_usn)
+        );
+        print_json(&lifetime, full_name);
+    }
+
+    report_statistics(file_location, &parser, options);
+}
+
+
+/// Write the rename history of a file reference as JSON. With a folder mapping,
+/// the full names as of each change are added.
+fn write_rename_history(file_location: &str, image_offset: Option<u64>, options: &ArgMatches, annotator: &RecordAnnotator, file_reference: MftReference) {
+    let config = get_parser_settings(options, RecordFilter::new().file_reference(file_reference));
+    let mut parser = match open_parser(file_location, image_offset, config) {
+        Some(parser) => parser,
+        None => return
+    };
+
+    let mut tracker = RenameTracker::new();
+    for entry in parser.records() {
+        tracker.add(&entry);
+    }
+
+    report_statistics(file_location, &parser, options);
+
+    let history = match tracker.finish().into_iter().next() {
+        Some(history) => history,
+        None => {
+            eprintln!("No records found for {}-{} in {}", file_reference.entry, file_reference.sequence, file_location);
+            return;
+        }
+    };
+
+    let mut value = match serde_json::to_value(&history) {
+        Ok(value) => value,
+        Err(error) => {
+            eprintln!("Error serializing value: {}", error);
+            return;
+        }
+    };
+
+    if let Some(full_name) = annotator.get_full_name(&history.first_parent, &history.first_name, history.first_usn) {
+        value["full_name"] = json!(full_name);
+
+        if let Some(changes) = value["changes"].as_array_mut() {
+            for (change_value, change) in changes.iter_mut().zip(history.changes.iter()) {
+                change_value["old_full_name"] = json!(
+                    annotator.get_full_name(&change.old_parent, &change.old_name, change.old_usn)
+                );
+                change_value["new_full_name"] = match (&change.new_name, change.new_parent) {
+                    (Some(new_name), Some(new_parent)) => json!(
+                        annotator.get_full_name(&new_parent, new_name, change.usn)
+                    ),
+                    _ => serde_json::Value::Null
+                };
+            }
+        }
+    }
+
+    println!("{}", value);
+}
+
+
+/// Write a value as a line of JSON, with a full_name field if given.
+fn print_json<T: Serialize>(value: &T, full_name: Option<String>) {
+    let mut value = match serde_json::to_value(value) {
+        Ok(value) => value,
+        Err(error) => {
+            eprintln!("Error serializing value: {}", error);
+            return;
+        }
+    };
+
+    if let (Some(value_map), Some(full_name)) = (value.as_object_mut(), full_name) {
+        value_map.insert("full_name".to_string(), json!(full_name));
+    }
+
+    println!("{}", value);
+}
+
+
+/// Merge the records of several journals into one stream ordered by USN or
+/// timestamp. The folder mapping gets the directory changes of every journal.
+fn process_merged(file_locations: &[String], order: MergeOrder, options: &ArgMatches, confidence_scorer: Option<&ConfidenceScorer>, writer: &mut dyn OutputWriter) {
+    info!("merging {} journals", file_locations.len());
+
+    let image_offset = get_image_offset(options);
+    let mut annotator = match file_locations.first() {
+        Some(file_location) => RecordAnnotator::from_options(options, file_location, image_offset, confidence_scorer),
+        None => return
+    };
+
+    for file_location in file_locations {
+        annotator.add_journal(file_location, image_offset, options);
+    }
+
+    // Each parser is kept with its location, as the journals that fail to open are skipped
+    let mut parsers: Vec<_> = file_locations.iter()
+        .filter_map(|file_location| open_parser(
+            file_location,
+            image_offset,
+            get_parser_settings(options, make_record_filter(options, confidence_scorer))
+        ).map(|parser| (file_location, parser)))
+        .collect();
+
+    let inputs = parsers.iter_mut()
+        .map(|(_, parser)| parser.records())
+        .collect();
+
+    for entry in MergedRecords::new(inputs, order) {
+        write_entry(writer, &entry, annotator.get_additional(&entry));
+    }
+
+    for (file_location, parser) in parsers.iter() {
+        report_statistics(file_location, parser, options);
+    }
+}
+
+
+/// Merge the records of the current $J and the $J of every shadow copy of the
+/// volume in an image. The folder mapping is from the current journal.
+fn process_shadow_copies(file_location: &str, order: MergeOrder, options: &ArgMatches, confidence_scorer: Option<&ConfidenceScorer>, writer: &mut dyn OutputWriter) {
+    let image_offset = get_image_offset(options).expect("vss requires image");
+
+    let vss_volume = open_image(file_location)
+        .and_then(|image| VssVolume::new(image, image_offset));
+    let identifiers: Vec<String> = match vss_volume {
+        Ok(volume) => volume.shadow_copies().iter()
+            .map(|shadow_copy| shadow_copy.identifier.to_string())
+            .collect(),
+        Err(error) => {
+            eprintln!("Error reading the shadow copies of {}: {}", file_location, error);
+            Vec::new()
+        }
+    };
+    info!("merging the journal of {} with {} shadow copies", file_location, identifiers.len());
+
+    let mut annotator = RecordAnnotator::from_options(options, file_location, Some(image_offset), confidence_scorer);
+    annotator.add_journal(file_location, Some(image_offset), options);
+
+    let mut snapshot_parsers = Vec::new();
+    for (index, identifier) in identifiers.iter().enumerate() {
+        match UsnParser::from_shadow_copy(file_location, image_offset, index) {
+            Ok(parser) => snapshot_parsers.push((
+                identifier,
+                parser.with_configuration(get_parser_settings(options, make_record_filter(options, confidence_scorer)))
+            )),
+            Err(error) => eprintln!("Error creating parser for shadow copy {}: {}", identifier, error)
+        }
+    }
+
+    let mut parser = open_parser(
+        file_location,
+        Some(image_offset),
+        get_parser_settings(options, make_record_filter(options, confidence_scorer))
+    );
+
+    // The shadow copies are oldest first, so ties are in the order they were written
+    let mut inputs: Vec<Box<dyn Iterator<Item = UsnEntry>>> = snapshot_parsers.iter_mut()
+        .map(|(_, parser)| Box::new(parser.records()) as Box<dyn Iterator<Item = UsnEntry>>)
+        .collect();
+    if let Some(ref mut parser) = parser {
+        inputs.push(Box::new(parser.records()));
+    }
+
+    for entry in MergedRecords::new(inputs, order) {
+        write_entry(writer, &entry, annotator.get_additional(&entry));
+    }
+
+    for (identifier, snapshot_parser) in snapshot_parsers.iter() {
+        report_statistics(&format!("{} ({})", file_location, identifier), snapshot_parser, options);
+    }
+    if let Some(ref parser) = parser {
+        report_statistics(file_location, parser, options);
+    }
+}
+
+
+/// Write the number of records found by each parse mode in structured mode, and
+/// the rejected record candidates and read errors with --report.
+fn report_statistics<T: ReadSeek>(file_location: &str, parser: &UsnParser<T>, options: &ArgMatches) {
+    if get_parse_mode(options) == ParseMode::Structured {
+        let statistics = parser.statistics();
+        eprintln!(
+            "{}: {} records found by walking pages, {} records carved from {} damaged pages",
+            file_location,
+            statistics.structured_records,
+            statistics.carved_records,
+            statistics.carved_pages
+        );
+    }
+
+    if options.is_present("report") {
+        let report = parser.report();
+        eprintln!(
+            "{}: {} records found, {} record candidates rejected",
+            file_location,
+            report.statistics.records(),
+            report.rejected()
+        );
+        for (reason, count) in &report.diagnostics {
+            eprintln!("  {:?}: {} (first at offset {})", reason, count.count, count.first_offset);
+        }
+    }
+}
+
+
+fn get_parse_mode(options: &ArgMatches) -> ParseMode {
+    ParseMode::from_name(
+        options.value_of("parse_mode").expect("has default")
+    ).expect("used possible values")
+}
+
+
+/// Get the parser settings from the options with the given filter.
+fn get_parser_settings(options: &ArgMatches, filter: RecordFilter) -> UsnParserSettings {
     let thread_option = options
             .value_of("threads")
             .and_then(|value| Some(value.parse::<usize>().expect("used validator")));
 
-    let mut threads = match (cfg!(feature = "multithreading"), thread_option) {
+    let threads = match (cfg!(feature = "multithreading"), thread_option) {
         (true, Some(number)) => number,
         (true, None) => 0,
         (false, _) => {
@@ -177,98 +1087,246 @@ fn process_file(file_location: &str, options: &ArgMatches) {
         }
     };
 
-    let mut folder_mapping: Option<FolderMapping> = None;
+    UsnParserSettings::new()
+        .thread_count(threads)
+        .filter(filter)
+        .skip_leading_zeros(options.is_present("skip_leading_zeros"))
+        .parse_mode(get_parse_mode(options))
+}
+
 
-    if options.is_present("mft") {
-        if threads != 1 {
-            threads = 1;
-            eprintln!("When using MFT to create folder map, threads can only be 1.");
+/// The MFT data used to add fields, such as full_name, to each record.
+struct RecordAnnotator {
+    folder_mapping: Option<FolderMapping>,
+    file_index: Option<FileIndex>,
+    add_paths: bool,
+    add_enrichment: bool,
+    confidence_scorer: Option<ConfidenceScorer>,
+}
+
+impl RecordAnnotator {
+    /// Load the folder mapping and file index from --mft, or from the volume at
+    /// `image_offset` of the image `file_location`. The confidence scorer is used
+    /// with --confidence.
+    fn from_options(options: &ArgMatches, file_location: &str, image_offset: Option<u64>, confidence_scorer: Option<&ConfidenceScorer>) -> Self {
+        let mut folder_mapping: Option<FolderMapping> = None;
+
+        if let Some(mft_path) = options.value_of("mft") {
+            folder_mapping = match FolderMapping::from_mft_path(mft_path){
+                Ok(mapping) => Some(mapping),
+                Err(err) => {
+                    eprintln!("Error creating folder mapping. {}", err);
+                    exit(-1);
+                }
+            };
+        } else if let Some(offset) = image_offset {
+            let mut mapping = FolderMapping::new();
+            mapping.build_folder_mapping(
+                &mut open_image_mft(file_location, offset)
+            );
+            folder_mapping = Some(mapping);
         }
 
-        let mft_path = options.value_of("mft").unwrap();
-        folder_mapping = match FolderMapping::from_mft_path(mft_path){
-            Ok(mapping) => Some(mapping),
-            Err(err) => {
-                eprintln!("Error creating folder mapping. {}", err);
-                exit(-1);
+        let add_paths = options.is_present("file_index");
+        let add_enrichment = options.is_present("enrich");
+        let mut file_index: Option<FileIndex> = None;
+
+        // The lifecycle report checks the index for reused entries
+        if add_paths || add_enrichment || options.is_present("lifecycle") {
+            if let Some(mft_path) = options.value_of("mft") {
+                file_index = match FileIndex::from_mft_path(mft_path){
+                    Ok(index) => Some(index),
+                    Err(err) => {
+                        eprintln!("Error creating file index. {}", err);
+                        exit(-1);
+                    }
+                };
+            } else if let Some(offset) = image_offset {
+                let mut index = FileIndex::new();
+                index.build_file_index(
+                    &mut open_image_mft(file_location, offset)
+                );
+                file_index = Some(index);
             }
+        }
+
+        let confidence_scorer = match options.is_present("confidence") {
+            true => confidence_scorer.cloned(),
+            false => None
         };
+
+        RecordAnnotator {
+            folder_mapping,
+            file_index,
+            add_paths,
+            add_enrichment,
+            confidence_scorer
+        }
     }
 
-    let config = UsnParserSettings::new().thread_count(threads);
+    /// Add the directory creates, deletes and renames of a journal to the folder
+    /// mapping.
+    fn add_journal(&mut self, file_location: &str, image_offset: Option<u64>, options: &ArgMatches) {
+        if let Some(ref mut mapping) = self.folder_mapping {
+            // Paths are enumerated in two passes so that records do not need to be
+            // stored. The first pass only keeps the directory creates, deletes and
+            // renames, from which the mapping at any USN can be found. The second
+            // pass can then resolve each record's path as of its USN.
+            let delta_filter = RecordFilter::new()
+                .file_attributes(flags::FileAttributes::FILE_ATTRIBUTE_DIRECTORY)
+                .reason(FolderMapping::delta_reasons());
+            let config = get_parser_settings(options, delta_filter);
 
-    let mut parser = match UsnParser::from_path(file_location) {
-        Ok(parser) => parser.with_configuration(config),
-        Err(error) => {
-            eprintln!("Error creating parser for {}: {}", file_location, error);
-            return;
-        }
-    };
+            let mut parser = match open_parser(file_location, image_offset, config) {
+                Some(parser) => parser,
+                None => return
+            };
 
-    if folder_mapping.is_some(){
-        // Because we are going to enumerate folder names, we must
-        // iterate records from the newest to oldest inorder to correctly
-        // enumerate the paths. This means we must store all the records 
-        // because they are parsed from oldest to newest. Unfortunately,
-        // this does take up more memory.
-        let mut mapping = folder_mapping.unwrap();
-        let mut entry_list: Vec::<UsnEntry> = Vec::new();
-        for record in parser.records(){
-            entry_list.push(record);
+            for entry in parser.records() {
+                mapping.add_journal_entry(&entry);
+            }
         }
-        entry_list.reverse();
+    }
 
-        for entry in entry_list {
-            let mut entry_json_value = entry.to_json_value().unwrap();
-            let json_map = entry_json_value.as_object_mut().unwrap();
+    /// Get the full name of a file as of a USN, if there is a folder mapping.
+    fn get_full_name(&self, parent_reference: &MftReference, file_name: &str, usn: u64) -> Option<String> {
+        self.folder_mapping.as_ref().map(|mapping| {
+            // Enumerate the path of the parent as of the USN
+            let full_path = mapping.enumerate_path_at(
+                parent_reference.entry,
+                parent_reference.sequence,
+                usn
+            );
 
-            let record = entry.record;
+            format!("{}/{}", full_path, file_name)
+        })
+    }
+
+    /// Get the additional fields of a record.
+    fn get_additional(&self, entry: &UsnEntry) -> Option<serde_json::Value> {
+        let record = &entry.record;
+        let mut additional = serde_json::Map::new();
+
+        if let Some(full_name) = self.get_full_name(
+            &record.get_parent_reference(),
+            &record.get_file_name(),
+            record.get_usn()
+        ) {
+            additional.insert("full_name".to_string(), json!(full_name));
+        }
 
-            let reason = record.get_reason_code();
-            let file_attributes = record.get_file_attributes();
+        if let Some(ref index) = self.file_index {
             let file_reference = record.get_file_reference();
-            let parent_reference = record.get_parent_reference();
-            let file_name = record.get_file_name();
-
-            if file_attributes.contains(flags::FileAttributes::FILE_ATTRIBUTE_DIRECTORY){
-                // Add mapping on a delete or rename old
-                if reason.contains(flags::Reason::USN_REASON_FILE_DELETE) ||
-                    reason.contains(flags::Reason::USN_REASON_RENAME_OLD_NAME) {
-                    mapping.add_mapping(
-                        file_reference,
-                        file_name.clone(),
-                        parent_reference
-                    );
+
+            if self.add_paths {
+                additional.insert("mft_full_path".to_string(), json!(index.full_path(&file_reference)));
+                additional.insert("mft_paths".to_string(), json!(index.full_paths(&file_reference)));
+                additional.insert("mft_exists".to_string(), json!(index.exists(&file_reference)));
+            }
+
+            if self.add_enrichment {
+                if let Some(enrichment) = index.get_enrichment(&file_reference) {
+                    if let Ok(serde_json::Value::Object(enrichment_map)) = serde_json::to_value(enrichment) {
+                        additional.extend(enrichment_map);
+                    }
                 }
             }
+        }
+
+        if let Some(ref scorer) = self.confidence_scorer {
+            let confidence = scorer.score(entry);
+            additional.insert("confidence".to_string(), json!(confidence.score));
+            additional.insert("failed_checks".to_string(), json!(confidence.failed_checks));
+        }
+
+        if additional.is_empty() {
+            None
+        } else {
+            Some(serde_json::Value::Object(additional))
+        }
+    }
+}
 
-            // Enumerate the path of this record from the FolderMapping
-            let full_path = match mapping.enumerate_path(
-                parent_reference.entry,
-                parent_reference.sequence
-            ){
-                Some(path) => path,
-                None => "[Unknown]".to_string()
-            };
 
-            // Create teh fullname string
-            let full_name = format!("{}/{}", full_path, file_name);
+/// How flags are written to JSONL.
+fn get_flag_format(options: &ArgMatches) -> flags::FlagFormat {
+    flags::FlagFormat::from_name(options.value_of("flag_format").expect("has default"))
+        .expect("used possible values")
+}
+
+
+/// Get the volume offset if the source is an image.
+fn get_image_offset(options: &ArgMatches) -> Option<u64> {
+    if options.is_present("image") {
+        options.value_of("image_offset")
+            .map(|value| value.parse::<u64>().expect("used validator"))
+    } else {
+        None
+    }
+}
 
-            // Add the fullname string to the json record
-            let fn_value = Value::String(full_name);
-            json_map.insert("full_name".to_string(), fn_value);
 
-            // Create a json string to print
-            let json_str = serde_json::to_string(&json_map).unwrap();
-            println!("{}", json_str);
+/// Read the $MFT of the NTFS volume at `offset` in an image.
+fn read_image_mft(file_location: &str, offset: u64) -> Result<MftParser<NtfsStream<Box<dyn ReadSeek>>>, UsnError> {
+    open_image(file_location)
+        .and_then(|image| NtfsVolume::new(image, offset))
+        .and_then(|volume| volume.into_mft_parser())
+}
+
+
+/// Open the $MFT of the NTFS volume at `offset` in an image, exiting if it can
+/// not be read.
+fn open_image_mft(file_location: &str, offset: u64) -> MftParser<NtfsStream<Box<dyn ReadSeek>>> {
+    match read_image_mft(file_location, offset) {
+        Ok(mft_parser) => mft_parser,
+        Err(error) => {
+            eprintln!("Error reading the $MFT of {}: {}", file_location, error);
+            exit(-1);
         }
-    } else{
-        for record in parser.records(){
-            let json_str = serde_json::to_string(
-                &record.to_json_value().unwrap()
-            ).unwrap();
+    }
+}
+
+
+type DataRegions = Option<Vec<Range<u64>>>;
+
+/// Open a parser for a $J file, or for the $UsnJrnl:$J of the volume in an image.
+/// An EWF image without --image is carved as a whole.
+fn open_parser(file_location: &str, image_offset: Option<u64>, config: UsnParserSettings) -> Option<UsnParser<Box<dyn ReadSeek>>> {
+    // The data regions let the parser skip sparse parts of the $J without reading them
+    let handle: Result<(Box<dyn ReadSeek>, DataRegions), String> = match image_offset {
+        Some(offset) => open_image(file_location)
+            .and_then(|image| NtfsVolume::new(image, offset))
+            .and_then(|volume| volume.into_usn_journal_stream())
+            .and_then(|stream| {
+                let data_regions = stream.data_regions()?;
+                Ok((Box::new(stream) as Box<dyn ReadSeek>, Some(data_regions)))
+            })
+            .map_err(|error| error.to_string()),
+        None if is_ewf_file(file_location) => EwfImage::from_path(file_location)
+            .map(|image| (Box::new(image) as Box<dyn ReadSeek>, None))
+            .map_err(|error| error.to_string()),
+        None => fs::File::open(file_location)
+            .map(|file| {
+                let data_regions = utils::get_file_data_regions(&file);
+                (Box::new(file) as Box<dyn ReadSeek>, data_regions)
+            })
+            .map_err(|error| error.to_string())
+    };
 
-            println!("{}", json_str);
+    let parser = handle.and_then(|(handle, data_regions)|
+        UsnParser::from_read_seek(file_location.to_string(), handle)
+            .map(|parser| match data_regions {
+                Some(data_regions) => parser.with_data_regions(data_regions),
+                None => parser
+            })
+            .map_err(|error| error.to_string())
+    );
+
+    match parser {
+        Ok(parser) => Some(parser.with_configuration(config)),
+        Err(error) => {
+            eprintln!("Error creating parser for {}: {}", file_location, error);
+            None
         }
     }
 }
@@ -304,9 +1362,69 @@ fn main() {
         }
     };
 
-    if is_directory(source_location) {
-        process_directory(source_location, &options);
+    let has_mft = options.is_present("mft") || options.is_present("image");
+    if !has_mft && (options.is_present("file_index") || options.is_present("enrich")) {
+        eprintln!("--file-index and --enrich require --mft or --image.");
+        exit(-1);
+    }
+
+    if options.is_present("anomalies") && options.is_present("merge") {
+        eprintln!("--anomalies can not be used with --merge.");
+        exit(-1);
+    }
+
+    if options.is_present("image") && is_directory(source_location) {
+        eprintln!("--image requires the source to be an image file.");
+        exit(-1);
+    }
+
+    let output_format = OutputFormat::from_name(
+        options.value_of("format").expect("has default")
+    ).expect("used possible values");
+
+    // The scorer reads the MFT, so it is made once for every parser
+    let confidence_scorer = match options.is_present("confidence") || options.is_present("min_confidence") {
+        true => Some(make_confidence_scorer(&options)),
+        false => None
+    };
+
+    let mut writer = output_format.get_writer(io::stdout(), get_flag_format(&options));
+    if options.is_present("dedup") {
+        let sightings_location = options.value_of("sightings").expect("dedup requires sightings");
+        let sightings_writer = match fs::File::create(sightings_location) {
+            Ok(file) => file,
+            Err(error) => {
+                eprintln!("Error creating {}: {}", sightings_location, error);
+                exit(-1);
+            }
+        };
+        writer = Box::new(DedupWriter::new(writer, Box::new(sightings_writer)));
+    }
+
+    if options.is_present("vss") {
+        let order = MergeOrder::from_name(options.value_of("merge").unwrap_or("usn"))
+            .expect("used possible values");
+
+        process_shadow_copies(source_location, order, &options, confidence_scorer.as_ref(), &mut *writer);
+    } else if let Some(order) = options.value_of("merge") {
+        let order = MergeOrder::from_name(order).expect("used possible values");
+
+        let mut file_locations = Vec::new();
+        if is_directory(source_location) {
+            find_journals(source_location, &mut file_locations);
+        } else {
+            file_locations.push(source_location.to_string());
+        }
+
+        process_merged(&file_locations, order, &options, confidence_scorer.as_ref(), &mut *writer);
+    } else if is_directory(source_location) {
+        process_directory(source_location, &options, confidence_scorer.as_ref(), &mut *writer);
     } else {
-        process_file(source_location, &options);
+        process_file(source_location, &options, confidence_scorer.as_ref(), &mut *writer);
+    }
+
+    if let Err(error) = writer.flush() {
+        eprintln!("Error flushing output: {}", error);
+        exit(-1);
     }
 }
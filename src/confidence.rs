@@ -0,0 +1,169 @@
+use serde::Serialize;
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use crate::record::{UsnEntry, UsnRecord};
+
+
+// Entry numbers are 48 bits, but no volume has anywhere near this many entries
+const MAX_PLAUSIBLE_ENTRY: u64 = 0xFFFF_FFFF;
+// The root directory's entry, no parent can have a lower entry
+const ROOT_ENTRY: u64 = 5;
+
+
+/// A plausibility check of a record.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+pub enum ConfidenceCheck {
+    /// The timestamp is inside the time window.
+    Timestamp,
+    /// The reason is not empty and no reserved reason, source info or file
+    /// attribute bits are set.
+    FlagBits,
+    /// The record length is the fixed portion plus the name, 8 byte aligned.
+    NameLength,
+    /// The name is not empty, decodes to the whole name length and has no control
+    /// characters or slashes.
+    PrintableName,
+    /// The file and parent sequences are not 0, and their entries are not past
+    /// the end of the MFT, nor the parent's before the root directory.
+    References,
+}
+
+
+/// How plausible a record is, the fraction of the checks that apply to the
+/// record's version which it passed.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Confidence {
+    pub score: f64,
+    pub failed_checks: Vec<ConfidenceCheck>,
+}
+
+
+/// ConfidenceScorer scores carved records on plausibility checks, as a record
+/// header signature and a valid name offset are found in plenty of data that is
+/// not a record.
+#[derive(Debug, Clone)]
+pub struct ConfidenceScorer {
+    earliest: DateTime<Utc>,
+    latest: DateTime<Utc>,
+    max_entry: u64,
+}
+
+impl Default for ConfidenceScorer {
+    /// Defaults to a time window from 1999, before the first journals, to a day
+    /// from now.
+    fn default() -> Self {
+        ConfidenceScorer {
+            earliest: DateTime::from_utc(NaiveDate::from_ymd(1999, 1, 1).and_hms(0, 0, 0), Utc),
+            latest: Utc::now() + Duration::days(1),
+            max_entry: MAX_PLAUSIBLE_ENTRY,
+        }
+    }
+}
+
+impl ConfidenceScorer {
+    pub fn new() -> Self {
+        ConfidenceScorer::default()
+    }
+
+    /// Timestamps before this time are not plausible.
+    pub fn earliest(mut self, earliest: DateTime<Utc>) -> Self {
+        self.earliest = earliest;
+        self
+    }
+
+    /// Timestamps after this time are not plausible.
+    pub fn latest(mut self, latest: DateTime<Utc>) -> Self {
+        self.latest = latest;
+        self
+    }
+
+    /// Entries past this entry are not plausible, such as the last entry of the
+    /// volume's MFT.
+    pub fn max_entry(mut self, max_entry: u64) -> Self {
+        self.max_entry = max_entry;
+        self
+    }
+
+    pub fn score(&self, entry: &UsnEntry) -> Confidence {
+        let record = &entry.record;
+
+        let checks = [
+            (ConfidenceCheck::Timestamp, self.check_timestamp(record)),
+            (ConfidenceCheck::FlagBits, Some(check_flag_bits(record))),
+            (ConfidenceCheck::NameLength, check_name_length(record)),
+            (ConfidenceCheck::PrintableName, check_printable_name(record)),
+            (ConfidenceCheck::References, Some(self.check_references(record))),
+        ];
+
+        let mut applied = 0;
+        let mut failed_checks = Vec::new();
+        for (check, passed) in checks.iter() {
+            match passed {
+                Some(true) => applied += 1,
+                Some(false) => {
+                    applied += 1;
+                    failed_checks.push(*check);
+                },
+                None => {}
+            }
+        }
+
+        Confidence {
+            score: (applied - failed_checks.len()) as f64 / applied as f64,
+            failed_checks
+        }
+    }
+
+    fn check_timestamp(&self, record: &UsnRecord) -> Option<bool> {
+        record.get_timestamp().map(|timestamp|
+            timestamp >= self.earliest && timestamp <= self.latest
+        )
+    }
+
+    fn check_references(&self, record: &UsnRecord) -> bool {
+        let file_reference = record.get_file_reference();
+        let parent_reference = record.get_parent_reference();
+
+        file_reference.sequence != 0 &&
+            parent_reference.sequence != 0 &&
+            file_reference.entry <= self.max_entry &&
+            parent_reference.entry <= self.max_entry &&
+            parent_reference.entry >= ROOT_ENTRY
+    }
+}
+
+
+fn check_flag_bits(record: &UsnRecord) -> bool {
+    let unknown_bits = record.get_unknown_bits();
+
+    !record.get_reason_code().is_empty() &&
+        unknown_bits.reason == 0 &&
+        unknown_bits.source_info == 0 &&
+        unknown_bits.file_attributes == 0
+}
+
+/// Version 4 records do not have a name, so the name checks do not apply.
+fn name_fields(record: &UsnRecord) -> Option<(u32, u16, u16)> {
+    match record {
+        UsnRecord::V2(record) => Some((record.record_length, record.file_name_offset, record.file_name_length)),
+        UsnRecord::V3(record) => Some((record.record_length, record.file_name_offset, record.file_name_length)),
+        UsnRecord::V4(_) => None,
+    }
+}
+
+fn check_name_length(record: &UsnRecord) -> Option<bool> {
+    name_fields(record).map(|(record_length, name_offset, name_length)| {
+        let expected_length = (u32::from(name_offset) + u32::from(name_length) + 7) & !7;
+        name_length % 2 == 0 && record_length == expected_length
+    })
+}
+
+fn check_printable_name(record: &UsnRecord) -> Option<bool> {
+    name_fields(record).map(|(_, _, name_length)| {
+        let file_name = record.get_file_name();
+
+        // Code units that could not be decoded are dropped from the name
+        !file_name.is_empty() &&
+            file_name.encode_utf16().count() * 2 == name_length as usize &&
+            !file_name.chars().any(|c| c.is_control() || c == '/' || c == char::REPLACEMENT_CHARACTER)
+    })
+}
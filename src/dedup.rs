@@ -0,0 +1,117 @@
+use std::collections::HashMap;
+use serde::Serialize;
+use chrono::{DateTime, Utc};
+use winstructs::ntfs::mft_reference::MftReference;
+use crate::record::UsnEntry;
+
+
+/// The fields that identify a record regardless of where it was found.
+#[derive(Debug, Clone, Hash, PartialEq, Eq)]
+pub struct DedupKey {
+    pub usn: u64,
+    pub file_reference: MftReference,
+    pub reason: u32,
+    pub timestamp: Option<DateTime<Utc>>,
+    pub file_name: String,
+}
+
+impl DedupKey {
+    pub fn from_entry(entry: &UsnEntry) -> Self {
+        let record = &entry.record;
+
+        DedupKey {
+            usn: record.get_usn(),
+            file_reference: record.get_file_reference(),
+            reason: record.get_reason_code().bits(),
+            timestamp: record.get_timestamp(),
+            file_name: record.get_file_name(),
+        }
+    }
+}
+
+
+/// A source and offset that a record was found at.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Sighting {
+    pub source: String,
+    pub offset: u64,
+    /// The shadow copy, if the record was found in one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub snapshot: Option<String>,
+}
+
+
+/// Every place a record was found, keyed by the source and offset it was first
+/// found at, which are the `meta__source` and `meta__offset` of the record.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct RecordSightings {
+    #[serde(rename = "meta__source")]
+    pub source: String,
+    #[serde(rename = "meta__offset")]
+    pub offset: u64,
+    #[serde(rename = "meta__snapshot", skip_serializing_if = "Option::is_none")]
+    pub snapshot: Option<String>,
+    pub sightings: Vec<Sighting>,
+}
+
+
+/// SightingTracker finds records that were found more than once, such as when
+/// carving a $J along with unallocated space or several shadow copies of the same
+/// $J. Records are the same if they have the same USN, file reference, reason,
+/// timestamp and name. Only their keys and sightings are kept, so each record can
+/// be written when it is first seen.
+#[derive(Debug, Default)]
+pub struct SightingTracker {
+    index: HashMap<DedupKey, usize>,
+    sightings: Vec<Vec<Sighting>>,
+}
+
+impl SightingTracker {
+    pub fn new() -> Self {
+        SightingTracker::default()
+    }
+
+    /// Add a record. Returns true if the record has not been seen before.
+    pub fn add(&mut self, entry: &UsnEntry) -> bool {
+        let sighting = Sighting {
+            source: entry.meta.source.clone(),
+            offset: entry.meta.offset,
+            snapshot: entry.meta.snapshot.clone(),
+        };
+        let key = DedupKey::from_entry(entry);
+
+        match self.index.get(&key) {
+            Some(position) => {
+                let sightings = &mut self.sightings[*position];
+                if !sightings.contains(&sighting) {
+                    sightings.push(sighting);
+                }
+                false
+            },
+            None => {
+                self.index.insert(key, self.sightings.len());
+                self.sightings.push(vec![sighting]);
+                true
+            }
+        }
+    }
+
+    /// Take the sightings of the records that were found more than once, in the
+    /// order they were first seen, leaving the tracker empty.
+    pub fn take_repeated(&mut self) -> Vec<RecordSightings> {
+        self.index.clear();
+        std::mem::take(&mut self.sightings).into_iter()
+            .filter(|sightings| sightings.len() > 1)
+            .map(|sightings| {
+                let first = sightings[0].clone();
+                RecordSightings {
+                    source: first.source,
+                    offset: first.offset,
+                    snapshot: first.snapshot,
+                    sightings
+                }
+            })
+            .collect()
+    }
+}
+
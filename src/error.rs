@@ -2,7 +2,9 @@ use std::fmt;
 use std::fmt::Display;
 use std::io;
 use serde_json::error::Error as SjError;
+use csv::Error as CsvError;
 use winstructs::err::Error as WinstructError;
+use mft::err::Error as MftError;
 
 #[derive(Debug)]
 pub enum ErrorKind {
@@ -14,7 +16,12 @@ pub enum ErrorKind {
     Utf16DecodeError,
     IoError,
     SerdeJsonError,
+    CsvError,
     ValueError,
+    InvalidFilter,
+    InvalidImage,
+    InvalidLogFile,
+    MftError,
 }
 
 /// USN Record Parsing Error
@@ -33,6 +40,30 @@ impl UsnError{
         }
     }
 
+    #[allow(dead_code)]
+    pub fn invalid_filter(msg: String) -> Self {
+        UsnError {
+            message: msg,
+            kind: ErrorKind::InvalidFilter,
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn invalid_image(msg: String) -> Self {
+        UsnError {
+            message: msg,
+            kind: ErrorKind::InvalidImage,
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn invalid_logfile(msg: String) -> Self {
+        UsnError {
+            message: msg,
+            kind: ErrorKind::InvalidLogFile,
+        }
+    }
+
     #[allow(dead_code)]
     pub fn utf16_decode_error(msg: String) -> Self {
         UsnError {
@@ -92,6 +123,15 @@ impl From<SjError> for UsnError {
     }
 }
 
+impl From<CsvError> for UsnError {
+    fn from(err: CsvError) -> Self {
+        UsnError {
+            message: format!("{}", err),
+            kind: ErrorKind::CsvError,
+        }
+    }
+}
+
 impl From<WinstructError> for UsnError {
     fn from(err: WinstructError) -> Self {
         UsnError {
@@ -101,6 +141,15 @@ impl From<WinstructError> for UsnError {
     }
 }
 
+impl From<MftError> for UsnError {
+    fn from(err: MftError) -> Self {
+        UsnError {
+            message: format!("{}", err),
+            kind: ErrorKind::MftError,
+        }
+    }
+}
+
 impl Display for UsnError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result { 
         writeln!(f, "{}", self.message)
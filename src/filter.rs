@@ -0,0 +1,202 @@
+use regex::Regex;
+use chrono::{DateTime, Utc};
+use winstructs::ntfs::mft_reference::MftReference;
+use crate::flags;
+use crate::confidence::ConfidenceScorer;
+use crate::error::UsnError;
+use crate::record::UsnEntry;
+
+
+/// RecordFilter is a set of conditions that a UsnEntry must meet. Conditions
+/// that are not set always match, so the default filter matches every entry.
+///
+/// Flag conditions match if the record has any of the given flags set.
+///
+#[derive(Debug, Clone, Default)]
+pub struct RecordFilter {
+    reason: Option<flags::Reason>,
+    file_attributes: Option<flags::FileAttributes>,
+    source_info: Option<flags::SourceInfo>,
+    after: Option<DateTime<Utc>>,
+    before: Option<DateTime<Utc>>,
+    min_usn: Option<u64>,
+    max_usn: Option<u64>,
+    name_regex: Option<Regex>,
+    file_reference: Option<MftReference>,
+    parent_reference: Option<MftReference>,
+    min_confidence: Option<(ConfidenceScorer, f64)>,
+}
+
+impl RecordFilter {
+    pub fn new() -> RecordFilter {
+        RecordFilter::default()
+    }
+
+    /// Match records with any of these reason flags.
+    pub fn reason(mut self, reason: flags::Reason) -> Self {
+        self.reason = Some(reason);
+        self
+    }
+
+    /// Match records with any of these file attribute flags.
+    pub fn file_attributes(mut self, file_attributes: flags::FileAttributes) -> Self {
+        self.file_attributes = Some(file_attributes);
+        self
+    }
+
+    /// Match records with any of these source info flags.
+    pub fn source_info(mut self, source_info: flags::SourceInfo) -> Self {
+        self.source_info = Some(source_info);
+        self
+    }
+
+    /// Match records with a timestamp at or after this time.
+    pub fn after(mut self, after: DateTime<Utc>) -> Self {
+        self.after = Some(after);
+        self
+    }
+
+    /// Match records with a timestamp at or before this time.
+    pub fn before(mut self, before: DateTime<Utc>) -> Self {
+        self.before = Some(before);
+        self
+    }
+
+    /// Match records with a USN greater than or equal to this USN.
+    pub fn min_usn(mut self, min_usn: u64) -> Self {
+        self.min_usn = Some(min_usn);
+        self
+    }
+
+    /// Match records with a USN less than or equal to this USN.
+    pub fn max_usn(mut self, max_usn: u64) -> Self {
+        self.max_usn = Some(max_usn);
+        self
+    }
+
+    /// Match records whose file name matches this regular expression.
+    pub fn name_regex(mut self, pattern: &str) -> Result<Self, UsnError> {
+        let name_regex = Regex::new(pattern).map_err(|error|
+            UsnError::invalid_filter(
+                format!("Invalid name regex {}: {}", pattern, error)
+            )
+        )?;
+
+        self.name_regex = Some(name_regex);
+        Ok(self)
+    }
+
+    /// Match records whose file name matches this glob pattern. `*` matches any
+    /// number of characters and `?` matches a single character. Like NTFS, the
+    /// match is case insensitive.
+    pub fn name_glob(self, pattern: &str) -> Result<Self, UsnError> {
+        let mut regex_pattern = String::from("(?i)^");
+        for character in pattern.chars() {
+            match character {
+                '*' => regex_pattern.push_str(".*"),
+                '?' => regex_pattern.push('.'),
+                other => regex_pattern.push_str(
+                    &regex::escape(&other.to_string())
+                )
+            }
+        }
+        regex_pattern.push('$');
+
+        self.name_regex(&regex_pattern)
+    }
+
+    /// Match records for this file reference.
+    pub fn file_reference(mut self, file_reference: MftReference) -> Self {
+        self.file_reference = Some(file_reference);
+        self
+    }
+
+    /// Match records whose parent is this reference.
+    pub fn parent_reference(mut self, parent_reference: MftReference) -> Self {
+        self.parent_reference = Some(parent_reference);
+        self
+    }
+
+    /// Match records with a confidence score from the scorer of at least
+    /// `min_confidence`, from 0.0 to 1.0.
+    pub fn min_confidence(mut self, scorer: ConfidenceScorer, min_confidence: f64) -> Self {
+        self.min_confidence = Some((scorer, min_confidence));
+        self
+    }
+
+    /// Check if an entry meets all the conditions of this filter.
+    pub fn matches(&self, entry: &UsnEntry) -> bool {
+        let record = &entry.record;
+
+        if let Some(reason) = self.reason {
+            if !record.get_reason_code().intersects(reason) {
+                return false;
+            }
+        }
+
+        if let Some(file_attributes) = self.file_attributes {
+            if !record.get_file_attributes().intersects(file_attributes) {
+                return false;
+            }
+        }
+
+        if let Some(source_info) = self.source_info {
+            if !record.get_source_info().intersects(source_info) {
+                return false;
+            }
+        }
+
+        if self.after.is_some() || self.before.is_some() {
+            // Records without a timestamp (V4) can not be placed in a time range
+            let timestamp = match record.get_timestamp() {
+                Some(timestamp) => timestamp,
+                None => return false
+            };
+
+            match self.after {
+                Some(after) if timestamp < after => return false,
+                _ => {}
+            }
+            match self.before {
+                Some(before) if timestamp > before => return false,
+                _ => {}
+            }
+        }
+
+        let usn = record.get_usn();
+        match self.min_usn {
+            Some(min_usn) if usn < min_usn => return false,
+            _ => {}
+        }
+        match self.max_usn {
+            Some(max_usn) if usn > max_usn => return false,
+            _ => {}
+        }
+
+        if let Some(ref name_regex) = self.name_regex {
+            if !name_regex.is_match(&record.get_file_name()) {
+                return false;
+            }
+        }
+
+        if let Some(file_reference) = self.file_reference {
+            if record.get_file_reference() != file_reference {
+                return false;
+            }
+        }
+
+        if let Some(parent_reference) = self.parent_reference {
+            if record.get_parent_reference() != parent_reference {
+                return false;
+            }
+        }
+
+        if let Some((ref scorer, min_confidence)) = self.min_confidence {
+            if scorer.score(entry).score < min_confidence {
+                return false;
+            }
+        }
+
+        true
+    }
+}
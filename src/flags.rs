@@ -1,8 +1,172 @@
 use std::fmt;
 use serde::ser;
+use serde_json::Value;
 
 
-bitflags! {
+/// How flags are serialized.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FlagFormat {
+    /// The flag names joined by ` | `, `(empty)` if none are set.
+    /// (example: `"USN_REASON_CLOSE | USN_REASON_DATA_EXTEND"`)
+    Text,
+    /// An array of the flag names. (example: `["USN_REASON_CLOSE","USN_REASON_DATA_EXTEND"]`)
+    Array,
+    /// An array of the flag names without their prefix. (example: `["CLOSE","DATA_EXTEND"]`)
+    Short,
+    /// The raw value. (example: `2147483650`)
+    Integer,
+}
+
+impl FlagFormat {
+    pub fn from_name(name: &str) -> Option<FlagFormat> {
+        match name.to_lowercase().as_str() {
+            "text" => Some(FlagFormat::Text),
+            "array" => Some(FlagFormat::Array),
+            "short" => Some(FlagFormat::Short),
+            "integer" => Some(FlagFormat::Integer),
+            _ => None
+        }
+    }
+}
+
+/// Declares a bitflags struct along with a table of its flag names. The prefix
+/// is the text common to all the flag names and is optional when looking up a
+/// flag by name.
+macro_rules! named_flags {
+    (
+        prefix = $prefix:expr;
+        pub struct $name:ident: u32 {
+            $(const $flag:ident = $value:expr;)*
+        }
+    ) => {
+        bitflags! {
+            pub struct $name: u32 {
+                $(const $flag = $value;)*
+            }
+        }
+
+        impl $name {
+            /// Every defined flag and its name.
+            pub const NAMED_FLAGS: &'static [(&'static str, $name)] = &[
+                $((stringify!($flag), $name::$flag),)*
+            ];
+
+            /// The prefix common to all the flag names.
+            pub const NAME_PREFIX: &'static str = $prefix;
+
+            /// Look up a flag by name. The lookup is case insensitive and the
+            /// name prefix is optional (`CLOSE` and `usn_reason_close` both match
+            /// `USN_REASON_CLOSE`).
+            pub fn from_name(name: &str) -> Option<$name> {
+                let name = name.trim().to_uppercase();
+                let short_name = name.trim_start_matches(Self::NAME_PREFIX);
+
+                Self::NAMED_FLAGS.iter()
+                    .find(|(flag_name, _)| flag_name[Self::NAME_PREFIX.len()..] == *short_name)
+                    .map(|(_, flag)| *flag)
+            }
+
+            /// The names of the defined flags that are set.
+            pub fn names(&self) -> Vec<&'static str> {
+                Self::NAMED_FLAGS.iter()
+                    .filter(|(_, flag)| self.contains(*flag))
+                    .map(|(flag_name, _)| *flag_name)
+                    .collect()
+            }
+
+            /// The names of the defined flags that are set, without the name prefix.
+            pub fn short_names(&self) -> Vec<&'static str> {
+                self.names().into_iter()
+                    .map(|flag_name| &flag_name[Self::NAME_PREFIX.len()..])
+                    .collect()
+            }
+
+            /// Convert from the raw value, keeping the bits that are not defined flags.
+            pub fn from_bits_retain(bits: u32) -> $name {
+                // Undefined bits are allowed for these flags, which is all that
+                // from_bits_unchecked requires.
+                unsafe { $name::from_bits_unchecked(bits) }
+            }
+
+            /// The bits that are set but are not defined flags.
+            pub fn unknown_bits(&self) -> u32 {
+                self.bits() & !$name::all().bits()
+            }
+
+            /// The names of the defined flags that are set, followed by the hex
+            /// value of any unknown bits.
+            pub fn display_names(&self) -> Vec<String> {
+                self.with_unknown_bits(self.names())
+            }
+
+            /// The names of the defined flags that are set without the name prefix,
+            /// followed by the hex value of any unknown bits.
+            pub fn display_short_names(&self) -> Vec<String> {
+                self.with_unknown_bits(self.short_names())
+            }
+
+            fn with_unknown_bits(&self, names: Vec<&'static str>) -> Vec<String> {
+                let mut names: Vec<String> = names.into_iter()
+                    .map(|name| name.to_string())
+                    .collect();
+                if self.unknown_bits() != 0 {
+                    names.push(format!("{:#x}", self.unknown_bits()));
+                }
+                names
+            }
+
+            /// The flags as a JSON value in the given format.
+            pub fn to_json_value(&self, flag_format: FlagFormat) -> Value {
+                match flag_format {
+                    FlagFormat::Text => {
+                        let names = self.display_names();
+                        if names.is_empty() {
+                            Value::from("(empty)")
+                        } else {
+                            Value::from(names.join(" | "))
+                        }
+                    },
+                    FlagFormat::Array => Value::from(self.display_names()),
+                    FlagFormat::Short => Value::from(self.display_short_names()),
+                    FlagFormat::Integer => Value::from(self.bits()),
+                }
+            }
+
+            /// Parse a comma separated list of flag names into a single set of flags.
+            pub fn from_name_list(names: &str) -> Result<$name, String> {
+                let mut flags = $name::empty();
+                for name in names.split(',') {
+                    match Self::from_name(name) {
+                        Some(flag) => flags |= flag,
+                        None => return Err(
+                            format!("Unknown {} flag: {}", stringify!($name), name)
+                        )
+                    }
+                }
+                Ok(flags)
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f,"{}",self.bits())
+            }
+        }
+
+        /// Flags are serialized in the `FlagFormat::Text` format.
+        impl ser::Serialize for $name {
+            fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+                where S: ser::Serializer
+            {
+                ser::Serialize::serialize(&self.to_json_value(FlagFormat::Text), serializer)
+            }
+        }
+    };
+}
+
+
+named_flags! {
+    prefix = "FILE_ATTRIBUTE_";
     pub struct FileAttributes: u32 {
         const FILE_ATTRIBUTE_ARCHIVE                = 0x0000_0020;
         const FILE_ATTRIBUTE_COMPRESSED             = 0x0000_0800;
@@ -15,17 +179,21 @@ bitflags! {
         const FILE_ATTRIBUTE_NOT_CONTENT_INDEXED    = 0x0000_2000;
         const FILE_ATTRIBUTE_NO_SCRUB_DATA          = 0x0002_0000;
         const FILE_ATTRIBUTE_OFFLINE                = 0x0000_1000;
+        const FILE_ATTRIBUTE_PINNED                 = 0x0008_0000;
         const FILE_ATTRIBUTE_READONLY               = 0x0000_0001;
         const FILE_ATTRIBUTE_RECALL_ON_DATA_ACCESS  = 0x0040_0000;
         const FILE_ATTRIBUTE_RECALL_ON_OPEN         = 0x0004_0000;
         const FILE_ATTRIBUTE_REPARSE_POINT          = 0x0000_0400;
         const FILE_ATTRIBUTE_SPARSE_FILE            = 0x0000_0200;
+        const FILE_ATTRIBUTE_STRICTLY_SEQUENTIAL    = 0x2000_0000;
         const FILE_ATTRIBUTE_SYSTEM                 = 0x0000_0004;
         const FILE_ATTRIBUTE_TEMPORARY              = 0x0000_0100;
-        const FILE_ATTRIBUTE_VIRTUAL                = 0x0000_1000;
+        const FILE_ATTRIBUTE_UNPINNED               = 0x0010_0000;
+        const FILE_ATTRIBUTE_VIRTUAL                = 0x0001_0000;
     }
 }
-bitflags! {
+named_flags! {
+    prefix = "USN_REASON_";
     pub struct Reason: u32 {
         const USN_REASON_BASIC_INFO_CHANGE      = 0x0000_8000;
         const USN_REASON_CLOSE                  = 0x8000_0000;
@@ -33,6 +201,7 @@ bitflags! {
         const USN_REASON_DATA_EXTEND            = 0x0000_0002;
         const USN_REASON_DATA_OVERWRITE         = 0x0000_0001;
         const USN_REASON_DATA_TRUNCATION        = 0x0000_0004;
+        const USN_REASON_DESIRED_STORAGE_CLASS_CHANGE = 0x0100_0000;
         const USN_REASON_EA_CHANGE              = 0x0000_0400;
         const USN_REASON_ENCRYPTION_CHANGE      = 0x0004_0000;
         const USN_REASON_FILE_CREATE            = 0x0000_0100;
@@ -52,7 +221,8 @@ bitflags! {
         const USN_REASON_TRANSACTED_CHANGE      = 0x0040_0000;
     }
 }
-bitflags! {
+named_flags! {
+    prefix = "USN_SOURCE_";
     pub struct SourceInfo: u32 {
         const USN_SOURCE_AUXILIARY_DATA                 = 0x0000_0002;
         const USN_SOURCE_DATA_MANAGEMENT                = 0x0000_0001;
@@ -60,45 +230,3 @@ bitflags! {
         const USN_SOURCE_CLIENT_REPLICATION_MANAGEMENT  = 0x0000_0008;
     }
 }
-
-impl fmt::Display for FileAttributes {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f,"{}",self.bits())
-    }
-}
-
-impl ser::Serialize for FileAttributes {
-    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
-        where S: ser::Serializer
-    {
-        serializer.serialize_str(&format!("{:?}", self))
-    }
-}
-
-impl fmt::Display for Reason {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f,"{}",self.bits())
-    }
-}
-
-impl ser::Serialize for Reason {
-    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
-        where S: ser::Serializer
-    {
-        serializer.serialize_str(&format!("{:?}", self))
-    }
-}
-
-impl fmt::Display for SourceInfo {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f,"{}",self.bits())
-    }
-}
-
-impl ser::Serialize for SourceInfo {
-    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
-        where S: ser::Serializer
-    {
-        serializer.serialize_str(&format!("{:?}", self))
-    }
-}
\ No newline at end of file
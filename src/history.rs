@@ -0,0 +1,188 @@
+use std::collections::HashMap;
+use serde::Serialize;
+use chrono::{DateTime, Utc};
+use winstructs::ntfs::mft_reference::MftReference;
+use crate::flags::Reason;
+use crate::mapping::EntryMapping;
+use crate::record::UsnEntry;
+
+
+/// A rename or move of a file.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct NameChange {
+    pub old_name: String,
+    pub old_parent: MftReference,
+    pub old_usn: u64,
+    /// The new name is None if the file's next record was not a new name record,
+    /// such as when it was overwritten.
+    pub new_name: Option<String>,
+    pub new_parent: Option<MftReference>,
+    /// The USN and timestamp of the new name record, or of the old name record if
+    /// there is no new name.
+    pub usn: u64,
+    pub timestamp: Option<DateTime<Utc>>,
+    /// The parent changed.
+    pub moved: bool,
+}
+
+
+/// The names a file reference had, from the first record seen of it.
+#[derive(Serialize, Debug, Clone)]
+pub struct RenameHistory {
+    pub file_reference: MftReference,
+    pub first_name: String,
+    pub first_parent: MftReference,
+    pub first_usn: u64,
+    pub first_timestamp: Option<DateTime<Utc>>,
+    /// The first record seen created the file.
+    pub created: bool,
+    pub changes: Vec<NameChange>,
+}
+
+impl RenameHistory {
+    fn new(entry: &UsnEntry) -> Self {
+        let record = &entry.record;
+
+        RenameHistory {
+            file_reference: record.get_file_reference(),
+            first_name: record.get_file_name(),
+            first_parent: record.get_parent_reference(),
+            first_usn: record.get_usn(),
+            first_timestamp: record.get_timestamp(),
+            created: record.get_reason_code().contains(Reason::USN_REASON_FILE_CREATE),
+            changes: Vec::new(),
+        }
+    }
+
+    /// Every name and parent the file had, in order.
+    pub fn names(&self) -> Vec<EntryMapping> {
+        let mut names = vec![EntryMapping {
+            name: self.first_name.clone(),
+            parent: self.first_parent
+        }];
+
+        for change in &self.changes {
+            let names_to_add = [
+                Some(EntryMapping { name: change.old_name.clone(), parent: change.old_parent }),
+                match (&change.new_name, change.new_parent) {
+                    (Some(name), Some(parent)) => Some(EntryMapping { name: name.clone(), parent }),
+                    _ => None
+                }
+            ];
+
+            for name in names_to_add.iter().flatten() {
+                if names.last() != Some(name) {
+                    names.push(name.clone());
+                }
+            }
+        }
+
+        names
+    }
+}
+
+
+/// An old name record waiting for its new name record.
+#[derive(Debug)]
+struct PendingRename {
+    name: String,
+    parent: MftReference,
+    usn: u64,
+    timestamp: Option<DateTime<Utc>>,
+}
+
+
+/// RenameTracker links each `USN_REASON_RENAME_OLD_NAME` record to the file's next
+/// record, which is its `USN_REASON_RENAME_NEW_NAME` record, to build the rename
+/// and move history of each file reference. Records must be added in USN order.
+#[derive(Debug, Default)]
+pub struct RenameTracker {
+    histories: HashMap<MftReference, RenameHistory>,
+    pending: HashMap<MftReference, PendingRename>,
+}
+
+impl RenameTracker {
+    pub fn new() -> Self {
+        RenameTracker::default()
+    }
+
+    pub fn add(&mut self, entry: &UsnEntry) {
+        let record = &entry.record;
+
+        // Version 4 records do not have a name
+        if record.get_major_version() == 4 {
+            return;
+        }
+
+        let file_reference = record.get_file_reference();
+        let reason = record.get_reason_code();
+
+        let history = self.histories.entry(file_reference)
+            .or_insert_with(|| RenameHistory::new(entry));
+
+        if let Some(pending) = self.pending.remove(&file_reference) {
+            let is_new_name = reason.contains(Reason::USN_REASON_RENAME_NEW_NAME) &&
+                !reason.contains(Reason::USN_REASON_RENAME_OLD_NAME);
+
+            history.changes.push(match is_new_name {
+                true => NameChange {
+                    moved: pending.parent != record.get_parent_reference(),
+                    old_name: pending.name,
+                    old_parent: pending.parent,
+                    old_usn: pending.usn,
+                    new_name: Some(record.get_file_name()),
+                    new_parent: Some(record.get_parent_reference()),
+                    usn: record.get_usn(),
+                    timestamp: record.get_timestamp()
+                },
+                false => unmatched_change(pending)
+            });
+        }
+
+        if reason.contains(Reason::USN_REASON_RENAME_OLD_NAME) {
+            self.pending.insert(file_reference, PendingRename {
+                name: record.get_file_name(),
+                parent: record.get_parent_reference(),
+                usn: record.get_usn(),
+                timestamp: record.get_timestamp()
+            });
+        }
+    }
+
+    /// Get the history of a file reference. Renames waiting for their new name are
+    /// not included until `finish` is called.
+    pub fn get_history(&self, file_reference: &MftReference) -> Option<&RenameHistory> {
+        self.histories.get(file_reference)
+    }
+
+    /// Add the renames still waiting for their new name, as there are no more
+    /// records, and take the histories in the order the files were first seen.
+    pub fn finish(&mut self) -> Vec<RenameHistory> {
+        for (file_reference, pending) in self.pending.drain() {
+            if let Some(history) = self.histories.get_mut(&file_reference) {
+                history.changes.push(unmatched_change(pending));
+            }
+        }
+
+        let mut histories: Vec<RenameHistory> = self.histories.drain()
+            .map(|(_, history)| history)
+            .collect();
+        histories.sort_by_key(|history| history.first_usn);
+
+        histories
+    }
+}
+
+
+fn unmatched_change(pending: PendingRename) -> NameChange {
+    NameChange {
+        old_name: pending.name,
+        old_parent: pending.parent,
+        old_usn: pending.usn,
+        new_name: None,
+        new_parent: None,
+        usn: pending.usn,
+        timestamp: pending.timestamp,
+        moved: false
+    }
+}
@@ -0,0 +1,446 @@
+use std::cmp;
+use std::fs::File;
+use std::io;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+use byteorder::{ByteOrder, LittleEndian};
+use flate2::read::ZlibDecoder;
+use lru::LruCache;
+use crate::ReadSeek;
+use crate::error::UsnError;
+
+/// The signature of an EWF (E01) segment file.
+const EWF_SIGNATURE: &[u8] = b"EVF\x09\x0D\x0A\xFF\x00";
+const SIZE_FILE_HEADER: u64 = 13;
+const SIZE_SECTION_DESCRIPTOR: usize = 76;
+const SIZE_TABLE_HEADER: usize = 24;
+/// The size of the checksum after an uncompressed chunk.
+const SIZE_CHUNK_CHECKSUM: u64 = 4;
+/// The chunk offsets of a table have the compressed flag in the top bit.
+const CHUNK_COMPRESSED: u32 = 0x8000_0000;
+/// The number of decompressed chunks to keep, chunks are usually 32 KB.
+const CHUNK_CACHE_SIZE: usize = 64;
+/// The largest chunk size accepted from a volume section.
+const MAX_CHUNK_SIZE: u64 = 64 * 1024 * 1024;
+
+
+/// Check if a file starts with the EWF (E01) signature.
+pub fn is_ewf_file(filename: &str) -> bool {
+    let mut signature = [0u8; 8];
+    match File::open(filename).and_then(|mut file| file.read_exact(&mut signature)) {
+        Ok(_) => signature == EWF_SIGNATURE,
+        Err(_) => false
+    }
+}
+
+
+/// Get the extension of a segment from the extension of the first segment. The
+/// extensions are E01 to E99, then EAA to EZZ, FAA to ZZZ. The case of the first
+/// extension is kept.
+pub fn segment_extension(first_extension: &str, segment_number: u32) -> Option<String> {
+    let first_letter = first_extension.chars().next()?;
+    let is_lowercase = first_letter.is_lowercase();
+    let first_letter = first_letter.to_ascii_uppercase();
+
+    let extension = match segment_number {
+        0 => return None,
+        1..=99 => format!("{}{:02}", first_letter, segment_number),
+        _ => {
+            let index = segment_number - 100;
+            let letter = |offset: u32| (b'A' + (offset % 26) as u8) as char;
+
+            let first_offset = (first_letter as u32) - ('A' as u32) + index / (26 * 26);
+            if first_offset >= 26 {
+                return None;
+            }
+
+            format!("{}{}{}", letter(first_offset), letter(index / 26), letter(index))
+        }
+    };
+
+    Some(match is_lowercase {
+        true => extension.to_lowercase(),
+        false => extension
+    })
+}
+
+
+/// The geometry of the media, from the volume section.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EwfMedia {
+    pub chunk_count: u32,
+    pub sectors_per_chunk: u32,
+    pub bytes_per_sector: u32,
+    pub sector_count: u64,
+}
+
+impl EwfMedia {
+    fn from_buffer(buffer: &[u8]) -> Result<EwfMedia, UsnError> {
+        if buffer.len() < 24 {
+            return Err(UsnError::invalid_image("EWF volume section is too small".to_string()));
+        }
+
+        let media = EwfMedia {
+            chunk_count: LittleEndian::read_u32(&buffer[4..8]),
+            sectors_per_chunk: LittleEndian::read_u32(&buffer[8..12]),
+            bytes_per_sector: LittleEndian::read_u32(&buffer[12..16]),
+            sector_count: LittleEndian::read_u64(&buffer[16..24]),
+        };
+
+        if media.sectors_per_chunk == 0 || media.bytes_per_sector == 0 || media.chunk_size() > MAX_CHUNK_SIZE {
+            return Err(UsnError::invalid_image(
+                format!("Invalid EWF geometry: {} sectors per chunk, {} bytes per sector",
+                    media.sectors_per_chunk, media.bytes_per_sector)
+            ));
+        }
+
+        if media.sector_count.checked_mul(u64::from(media.bytes_per_sector)).is_none() {
+            return Err(UsnError::invalid_image(
+                format!("Invalid EWF media size: {} sectors of {} bytes", media.sector_count, media.bytes_per_sector)
+            ));
+        }
+
+        Ok(media)
+    }
+
+    pub fn chunk_size(&self) -> u64 {
+        u64::from(self.sectors_per_chunk) * u64::from(self.bytes_per_sector)
+    }
+
+    /// The size of the media, which is checked to fit when the volume section is read.
+    pub fn media_size(&self) -> u64 {
+        self.sector_count * u64::from(self.bytes_per_sector)
+    }
+
+    /// The largest a stored chunk can be, which is a chunk that did not compress
+    /// with the zlib overhead, or an uncompressed chunk with its checksum.
+    fn max_stored_chunk_size(&self) -> u64 {
+        let chunk_size = self.chunk_size();
+        chunk_size + (chunk_size >> 12) + (chunk_size >> 14) + 13 + SIZE_CHUNK_CHECKSUM
+    }
+}
+
+
+/// Where a chunk is stored in the segments.
+#[derive(Debug, Clone, Copy)]
+struct ChunkLocation {
+    segment: usize,
+    offset: u64,
+    size: u64,
+    compressed: bool,
+}
+
+
+/// A section of a segment file.
+struct Section {
+    section_type: String,
+    offset: u64,
+    next_offset: u64,
+    size: u64,
+}
+
+
+/// EwfImage reads the media of an EWF (E01) image. The media is stored in chunks
+/// that are zlib compressed or followed by a checksum, and the chunks are split
+/// across segment files (.E01, .E02...). Decompressed chunks are cached.
+pub struct EwfImage<T: ReadSeek> {
+    segments: Vec<T>,
+    media: EwfMedia,
+    chunks: Vec<ChunkLocation>,
+    position: u64,
+    cache: LruCache<usize, Vec<u8>>,
+}
+
+impl EwfImage<File> {
+    /// Open an image from its first segment, the other segments are found by
+    /// their extensions.
+    pub fn from_path(filename: &str) -> Result<Self, UsnError> {
+        let path = Path::new(filename);
+        let first_extension = path.extension()
+            .and_then(|extension| extension.to_str())
+            .unwrap_or("E01");
+
+        let mut segments = vec![File::open(path)?];
+        for segment_number in 2.. {
+            let segment_path = match segment_extension(first_extension, segment_number) {
+                Some(extension) => path.with_extension(extension),
+                None => break
+            };
+
+            if !segment_path.is_file() {
+                break;
+            }
+            segments.push(File::open(segment_path)?);
+        }
+
+        Self::new(segments)
+    }
+}
+
+impl<T: ReadSeek> EwfImage<T> {
+    /// Read the chunk tables of the segments, which must be in order.
+    pub fn new(mut segments: Vec<T>) -> Result<Self, UsnError> {
+        let mut media = None;
+        let mut chunks = Vec::new();
+
+        for (index, segment) in segments.iter_mut().enumerate() {
+            let segment_number = read_file_header(segment)?;
+            if segment_number as usize != index + 1 {
+                return Err(UsnError::invalid_image(
+                    format!("EWF segment {} is number {}", index + 1, segment_number)
+                ));
+            }
+
+            read_sections(segment, index, &mut media, &mut chunks)?;
+        }
+
+        let media = match media {
+            Some(media) => media,
+            None => return Err(UsnError::invalid_image("EWF image has no volume section".to_string()))
+        };
+
+        // Rounded up without overflowing, as the media size is from the image
+        let needed_chunks = media.media_size() / media.chunk_size() +
+            u64::from(media.media_size() % media.chunk_size() != 0);
+        if (chunks.len() as u64) < needed_chunks {
+            warn!("EWF image has {} of {} chunks, the rest reads as zeros", chunks.len(), needed_chunks);
+        }
+
+        Ok(EwfImage {
+            segments,
+            media,
+            chunks,
+            position: 0,
+            cache: LruCache::new(CHUNK_CACHE_SIZE),
+        })
+    }
+
+    pub fn media(&self) -> &EwfMedia {
+        &self.media
+    }
+
+    pub fn size(&self) -> u64 {
+        self.media.media_size()
+    }
+
+    /// Read and decompress a chunk.
+    fn read_chunk(&mut self, chunk_index: usize) -> io::Result<Vec<u8>> {
+        let chunk_size = self.media.chunk_size() as usize;
+
+        let location = match self.chunks.get(chunk_index) {
+            Some(location) => *location,
+            None => return Ok(vec![0; chunk_size])
+        };
+
+        if location.size > self.media.max_stored_chunk_size() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("EWF chunk {} of {} bytes is larger than a chunk", chunk_index, location.size)
+            ));
+        }
+
+        let mut stored = vec![0; location.size as usize];
+        let segment = &mut self.segments[location.segment];
+        segment.seek(SeekFrom::Start(location.offset))?;
+        segment.read_exact(&mut stored)?;
+
+        let mut chunk = Vec::with_capacity(chunk_size);
+        if location.compressed {
+            ZlibDecoder::new(stored.as_slice())
+                .take(chunk_size as u64)
+                .read_to_end(&mut chunk)?;
+        } else {
+            stored.truncate(cmp::min(location.size.saturating_sub(SIZE_CHUNK_CHECKSUM) as usize, chunk_size));
+            chunk = stored;
+        }
+
+        chunk.resize(chunk_size, 0);
+        Ok(chunk)
+    }
+}
+
+impl<T: ReadSeek> Read for EwfImage<T> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let chunk_size = self.media.chunk_size();
+        let media_size = self.media.media_size();
+        let mut total_read = 0;
+
+        while total_read < buf.len() && self.position < media_size {
+            let chunk_index = (self.position / chunk_size) as usize;
+            let chunk_offset = (self.position % chunk_size) as usize;
+
+            if !self.cache.contains(&chunk_index) {
+                let chunk = self.read_chunk(chunk_index)?;
+                self.cache.put(chunk_index, chunk);
+            }
+            let chunk = match self.cache.get(&chunk_index) {
+                Some(chunk) => chunk,
+                None => break
+            };
+
+            let read_size = cmp::min(
+                cmp::min(buf.len() - total_read, chunk.len() - chunk_offset),
+                (media_size - self.position) as usize
+            );
+            buf[total_read..total_read + read_size]
+                .copy_from_slice(&chunk[chunk_offset..chunk_offset + read_size]);
+
+            total_read += read_size;
+            self.position += read_size as u64;
+        }
+
+        Ok(total_read)
+    }
+}
+
+impl<T: ReadSeek> Seek for EwfImage<T> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(offset) => offset as i64,
+            SeekFrom::End(offset) => self.media.media_size() as i64 + offset,
+            SeekFrom::Current(offset) => self.position as i64 + offset
+        };
+
+        if position < 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to a negative position"
+            ));
+        }
+
+        self.position = position as u64;
+        Ok(self.position)
+    }
+}
+
+
+/// Check the signature of a segment and get its segment number.
+fn read_file_header<T: ReadSeek>(segment: &mut T) -> Result<u16, UsnError> {
+    let mut header = [0u8; SIZE_FILE_HEADER as usize];
+    segment.seek(SeekFrom::Start(0))?;
+    segment.read_exact(&mut header)?;
+
+    if &header[0..8] != EWF_SIGNATURE {
+        return Err(UsnError::invalid_image("No EWF segment signature".to_string()));
+    }
+
+    Ok(LittleEndian::read_u16(&header[9..11]))
+}
+
+fn read_section<T: ReadSeek>(segment: &mut T, offset: u64) -> Result<Section, UsnError> {
+    let mut descriptor = [0u8; SIZE_SECTION_DESCRIPTOR];
+    segment.seek(SeekFrom::Start(offset))?;
+    segment.read_exact(&mut descriptor)?;
+
+    let type_end = descriptor[0..16].iter().position(|byte| *byte == 0).unwrap_or(16);
+
+    Ok(Section {
+        section_type: String::from_utf8_lossy(&descriptor[0..type_end]).to_string(),
+        offset,
+        next_offset: LittleEndian::read_u64(&descriptor[16..24]),
+        size: LittleEndian::read_u64(&descriptor[24..32]),
+    })
+}
+
+/// Read the data of a section, which must be within the segment of `segment_size`.
+fn read_section_data<T: ReadSeek>(segment: &mut T, section: &Section, segment_size: u64) -> Result<Vec<u8>, UsnError> {
+    let data_size = section.size.saturating_sub(SIZE_SECTION_DESCRIPTOR as u64);
+    if data_size > segment_size.saturating_sub(section.offset + SIZE_SECTION_DESCRIPTOR as u64) {
+        return Err(UsnError::invalid_image(
+            format!("EWF {} section at offset {} is larger than its segment", section.section_type, section.offset)
+        ));
+    }
+
+    let mut data = vec![0u8; data_size as usize];
+    segment.seek(SeekFrom::Start(section.offset + SIZE_SECTION_DESCRIPTOR as u64))?;
+    segment.read_exact(&mut data)?;
+    Ok(data)
+}
+
+/// Walk the sections of a segment, reading the volume section and adding the
+/// chunks of each table.
+fn read_sections<T: ReadSeek>(segment: &mut T, segment_index: usize, media: &mut Option<EwfMedia>, chunks: &mut Vec<ChunkLocation>) -> Result<(), UsnError> {
+    let segment_size = segment.seek(SeekFrom::End(0))?;
+    // The end of the chunk data, which is the end of the last chunk of a table
+    let mut sectors_end = None;
+    let mut offset = SIZE_FILE_HEADER;
+
+    while offset.saturating_add(SIZE_SECTION_DESCRIPTOR as u64) <= segment_size {
+        let section = read_section(segment, offset)?;
+
+        match section.section_type.as_str() {
+            "volume" | "disk" if media.is_none() => {
+                *media = Some(EwfMedia::from_buffer(&read_section_data(segment, &section, segment_size)?)?);
+            },
+            "sectors" => {
+                sectors_end = Some(section.offset.checked_add(section.size).ok_or_else(|| UsnError::invalid_image(
+                    format!("EWF sectors section at offset {} has an invalid size", section.offset)
+                ))?);
+            },
+            "table" => {
+                let data = read_section_data(segment, &section, segment_size)?;
+                add_table_chunks(&data, segment_index, sectors_end.unwrap_or(section.offset), chunks)?;
+            },
+            "next" | "done" => break,
+            _ => {}
+        }
+
+        // The last section points to itself
+        if section.next_offset <= offset {
+            break;
+        }
+        offset = section.next_offset;
+    }
+
+    Ok(())
+}
+
+/// Add the chunks of a table. Each chunk ends where the next starts, and the
+/// last chunk ends at `data_end`.
+fn add_table_chunks(data: &[u8], segment_index: usize, data_end: u64, chunks: &mut Vec<ChunkLocation>) -> Result<(), UsnError> {
+    if data.len() < SIZE_TABLE_HEADER {
+        return Err(UsnError::invalid_image("EWF table section is too small".to_string()));
+    }
+
+    let entry_count = LittleEndian::read_u32(&data[0..4]) as usize;
+    let base_offset = LittleEndian::read_u64(&data[8..16]);
+    let entries_end = SIZE_TABLE_HEADER + entry_count * 4;
+    if entries_end > data.len() {
+        return Err(UsnError::invalid_image(
+            format!("EWF table of {} entries is larger than its section", entry_count)
+        ));
+    }
+
+    let entries: Vec<(u64, bool)> = data[SIZE_TABLE_HEADER..entries_end].chunks_exact(4)
+        .map(|entry| {
+            let entry = LittleEndian::read_u32(entry);
+            let offset = base_offset.checked_add(u64::from(entry & !CHUNK_COMPRESSED))
+                .ok_or_else(|| UsnError::invalid_image(
+                    format!("EWF table base offset {} is outside of the segment", base_offset)
+                ))?;
+            Ok((offset, entry & CHUNK_COMPRESSED != 0))
+        })
+        .collect::<Result<_, UsnError>>()?;
+
+    for (index, (offset, compressed)) in entries.iter().enumerate() {
+        let end = match entries.get(index + 1) {
+            Some((next_offset, _)) => *next_offset,
+            None => data_end
+        };
+
+        if end <= *offset {
+            return Err(UsnError::invalid_image(
+                format!("EWF chunk {} at offset {} has no data", chunks.len(), offset)
+            ));
+        }
+
+        chunks.push(ChunkLocation {
+            segment: segment_index,
+            offset: *offset,
+            size: end - offset,
+            compressed: *compressed,
+        });
+    }
+
+    Ok(())
+}
@@ -0,0 +1,19 @@
+pub mod ntfs;
+pub mod vss;
+pub mod ewf;
+
+use std::fs::File;
+use crate::ReadSeek;
+use crate::error::UsnError;
+use self::ewf::{is_ewf_file, EwfImage};
+
+
+/// Open an image file. EWF (E01) images are opened from their first segment and
+/// read as the media they contain, anything else is read as a raw image.
+pub fn open_image(filename: &str) -> Result<Box<dyn ReadSeek>, UsnError> {
+    if is_ewf_file(filename) {
+        Ok(Box::new(EwfImage::from_path(filename)?))
+    } else {
+        Ok(Box::new(File::open(filename)?))
+    }
+}
@@ -0,0 +1,667 @@
+use std::cmp;
+use std::fs::File;
+use std::io;
+use std::io::{Read, Seek, SeekFrom};
+use std::ops::Range;
+use byteorder::{ByteOrder, LittleEndian};
+use mft::{MftEntry, MftParser};
+use mft::attribute::MftAttributeType;
+use crate::ReadSeek;
+use crate::error::UsnError;
+
+const ATTRIBUTE_LIST: u32 = 0x20;
+const DATA: u32 = 0x80;
+const END_OF_ATTRIBUTES: u32 = 0xFFFF_FFFF;
+
+/// The entry number of the $LogFile.
+const LOGFILE_ENTRY: u64 = 2;
+/// The entry number of the $Extend directory.
+const EXTEND_ENTRY: u64 = 11;
+
+
+/// The fields of an NTFS boot sector needed to read the volume.
+#[derive(Debug, Clone)]
+pub struct BootSector {
+    pub bytes_per_sector: u16,
+    pub sectors_per_cluster: u32,
+    pub total_sectors: u64,
+    pub mft_lcn: u64,
+    pub mft_mirror_lcn: u64,
+    pub mft_record_size: u32,
+}
+
+/// 2 to the power of exponent, if the exponent is at most max_exponent.
+fn power_of_two(exponent: u32, max_exponent: u32) -> Option<u32> {
+    if exponent > max_exponent {
+        return None;
+    }
+    1u32.checked_shl(exponent)
+}
+
+impl BootSector {
+    pub fn from_buffer(buffer: &[u8]) -> Result<BootSector, UsnError> {
+        if buffer.len() < 512 || &buffer[3..11] != b"NTFS    " {
+            return Err(UsnError::invalid_image(
+                "No NTFS boot sector signature".to_string()
+            ));
+        }
+
+        let bytes_per_sector = LittleEndian::read_u16(&buffer[11..13]);
+
+        // Values above 0x80 are the negative exponent of the sectors per cluster
+        let sectors_per_cluster = match buffer[13] {
+            value if value > 0x80 => power_of_two(256 - u32::from(value), 12)
+                .ok_or_else(|| UsnError::invalid_image(
+                    format!("Invalid NTFS sectors per cluster value: 0x{:02x}", value)
+                ))?,
+            value => u32::from(value)
+        };
+
+        let total_sectors = LittleEndian::read_u64(&buffer[40..48]);
+        let mft_lcn = LittleEndian::read_u64(&buffer[48..56]);
+        let mft_mirror_lcn = LittleEndian::read_u64(&buffer[56..64]);
+
+        let cluster_size = u32::from(bytes_per_sector)
+            .checked_mul(sectors_per_cluster)
+            .ok_or_else(|| UsnError::invalid_image(
+                format!("Invalid NTFS cluster size: {} bytes per sector, {} sectors per cluster",
+                    bytes_per_sector, sectors_per_cluster)
+            ))?;
+
+        // Negative values are the exponent of the record size in bytes,
+        // positive values are the number of clusters.
+        let clusters_per_record = buffer[64] as i8;
+        let mft_record_size = if clusters_per_record < 0 {
+            power_of_two((-i32::from(clusters_per_record)) as u32, 16)
+        } else {
+            cluster_size.checked_mul(clusters_per_record as u32)
+        }.ok_or_else(|| UsnError::invalid_image(
+            format!("Invalid NTFS clusters per MFT record value: {}", clusters_per_record)
+        ))?;
+
+        if bytes_per_sector == 0 || sectors_per_cluster == 0 || mft_record_size == 0 {
+            return Err(UsnError::invalid_image(
+                format!("Invalid NTFS geometry: {} bytes per sector, {} sectors per cluster",
+                    bytes_per_sector, sectors_per_cluster)
+            ));
+        }
+
+        Ok(BootSector {
+            bytes_per_sector,
+            sectors_per_cluster,
+            total_sectors,
+            mft_lcn,
+            mft_mirror_lcn,
+            mft_record_size
+        })
+    }
+
+    pub fn cluster_size(&self) -> u64 {
+        u64::from(self.bytes_per_sector) * u64::from(self.sectors_per_cluster)
+    }
+}
+
+
+/// A run of clusters of non-resident data. Sparse runs have no lcn.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DataRun {
+    pub vcn: u64,
+    pub lcn: Option<u64>,
+    pub length: u64,
+}
+
+/// Decode a runlist starting at `starting_vcn`.
+pub fn decode_runlist(buffer: &[u8], starting_vcn: u64) -> Result<Vec<DataRun>, UsnError> {
+    let mut runs = Vec::new();
+    let mut index = 0;
+    let mut vcn = starting_vcn;
+    let mut lcn: i64 = 0;
+
+    while index < buffer.len() && buffer[index] != 0 {
+        let length_size = (buffer[index] & 0x0F) as usize;
+        let offset_size = (buffer[index] >> 4) as usize;
+        index += 1;
+
+        if length_size == 0 || length_size > 8 || offset_size > 8 ||
+            index + length_size + offset_size > buffer.len() {
+            return Err(UsnError::invalid_image(
+                format!("Invalid runlist header at offset {}", index - 1)
+            ));
+        }
+
+        let length = LittleEndian::read_uint(&buffer[index..index + length_size], length_size);
+        index += length_size;
+
+        let run_lcn = if offset_size > 0 {
+            lcn = match lcn.checked_add(LittleEndian::read_int(&buffer[index..index + offset_size], offset_size)) {
+                Some(lcn) if lcn >= 0 => lcn,
+                _ => return Err(UsnError::invalid_image(
+                    format!("Runlist has an invalid lcn at vcn {}", vcn)
+                ))
+            };
+            index += offset_size;
+
+            Some(lcn as u64)
+        } else {
+            None
+        };
+
+        runs.push(DataRun {
+            vcn,
+            lcn: run_lcn,
+            length
+        });
+        vcn = vcn.checked_add(length).ok_or_else(|| UsnError::invalid_image(
+            format!("Runlist has an invalid length at vcn {}", vcn)
+        ))?;
+    }
+
+    Ok(runs)
+}
+
+
+/// The runs and size of a non-resident attribute.
+#[derive(Debug, Clone, Default)]
+pub struct NonResidentData {
+    pub runs: Vec<DataRun>,
+    pub size: u64,
+}
+
+
+/// An attribute record of an MFT entry.
+struct AttributeRecord<'a> {
+    type_code: u32,
+    name: String,
+    /// The whole attribute record, including the header
+    record: &'a [u8],
+}
+
+impl<'a> AttributeRecord<'a> {
+    fn is_non_resident(&self) -> bool {
+        self.record[8] != 0
+    }
+
+    /// The content of a resident attribute.
+    fn resident_value(&self) -> Option<&'a [u8]> {
+        if self.is_non_resident() || self.record.len() < 22 {
+            return None;
+        }
+
+        let length = LittleEndian::read_u32(&self.record[16..20]) as usize;
+        let offset = LittleEndian::read_u16(&self.record[20..22]) as usize;
+        self.record.get(offset..offset + length)
+    }
+
+    fn starting_vcn(&self) -> u64 {
+        LittleEndian::read_u64(&self.record[16..24])
+    }
+
+    /// The data size, only valid in the attribute starting at vcn 0.
+    fn data_size(&self) -> u64 {
+        LittleEndian::read_u64(&self.record[48..56])
+    }
+
+    fn runs(&self) -> Result<Vec<DataRun>, UsnError> {
+        let runlist_offset = LittleEndian::read_u16(&self.record[32..34]) as usize;
+        match self.record.get(runlist_offset..) {
+            Some(runlist) => decode_runlist(runlist, self.starting_vcn()),
+            None => Err(UsnError::invalid_image("Runlist offset is out of bounds".to_string()))
+        }
+    }
+}
+
+/// Get the attribute records of a fixed up MFT entry.
+fn get_attribute_records(entry: &MftEntry) -> Vec<AttributeRecord<'_>> {
+    let data = &entry.data;
+    let mut records = Vec::new();
+    let mut offset = entry.header.first_attribute_record_offset as usize;
+
+    while offset + 16 <= data.len() {
+        let type_code = LittleEndian::read_u32(&data[offset..offset + 4]);
+        if type_code == END_OF_ATTRIBUTES {
+            break;
+        }
+
+        let length = LittleEndian::read_u32(&data[offset + 4..offset + 8]) as usize;
+        if length < 16 || offset + length > data.len() {
+            break;
+        }
+        let record = &data[offset..offset + length];
+
+        // Non-resident headers are 64 bytes, resident headers are 24 bytes
+        if (record[8] != 0 && length < 64) || (record[8] == 0 && length < 24) {
+            break;
+        }
+
+        let name_length = record[9] as usize;
+        let name_offset = LittleEndian::read_u16(&record[10..12]) as usize;
+        let name = match record.get(name_offset..name_offset + name_length * 2) {
+            Some(name_buffer) => utf16_to_string(name_buffer),
+            None => String::new()
+        };
+
+        records.push(AttributeRecord {
+            type_code,
+            name,
+            record
+        });
+        offset += length;
+    }
+
+    records
+}
+
+fn utf16_to_string(buffer: &[u8]) -> String {
+    let characters: Vec<u16> = buffer.chunks_exact(2)
+        .map(LittleEndian::read_u16)
+        .collect();
+    String::from_utf16_lossy(&characters)
+}
+
+
+/// NtfsStream reads non-resident data from a volume. Sparse runs, and any part
+/// not covered by a run, read as zeros without touching the volume.
+pub struct NtfsStream<T: ReadSeek> {
+    handle: T,
+    volume_offset: u64,
+    cluster_size: u64,
+    data: NonResidentData,
+    position: u64,
+}
+
+impl<T: ReadSeek> NtfsStream<T> {
+    pub fn new(handle: T, volume_offset: u64, cluster_size: u64, data: NonResidentData) -> Self {
+        NtfsStream {
+            handle,
+            volume_offset,
+            cluster_size,
+            data,
+            position: 0
+        }
+    }
+
+    /// The runs of the stream.
+    pub fn runs(&self) -> &[DataRun] {
+        &self.data.runs
+    }
+
+    pub fn size(&self) -> u64 {
+        self.data.size
+    }
+
+    /// The byte ranges of the stream that are not sparse.
+    pub fn data_regions(&self) -> Result<Vec<Range<u64>>, UsnError> {
+        let mut regions: Vec<Range<u64>> = Vec::new();
+
+        for run in self.data.runs.iter().filter(|run| run.lcn.is_some()) {
+            let start = run.vcn.checked_mul(self.cluster_size);
+            let end = run.vcn.checked_add(run.length)
+                .and_then(|end_vcn| end_vcn.checked_mul(self.cluster_size));
+            let (start, end) = match (start, end) {
+                (Some(start), Some(end)) => (start, cmp::min(end, self.data.size)),
+                _ => return Err(UsnError::invalid_image(
+                    format!("Data run at vcn {} is outside of the addressable range", run.vcn)
+                ))
+            };
+            if start >= end {
+                continue;
+            }
+
+            match regions.last_mut() {
+                Some(last) if last.end == start => last.end = end,
+                _ => regions.push(start..end)
+            }
+        }
+
+        Ok(regions)
+    }
+}
+
+impl<T: ReadSeek> NtfsStream<T> {
+    /// Read from the run at the current position, up to the end of the run.
+    fn read_run(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.position >= self.data.size || buf.is_empty() {
+            return Ok(0);
+        }
+
+        let vcn = self.position / self.cluster_size;
+        let cluster_offset = self.position % self.cluster_size;
+        let stream_remaining = self.data.size - self.position;
+        let out_of_range = |vcn: u64| io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Data run at vcn {} is outside of the addressable range", vcn)
+        );
+
+        let run_index = self.data.runs.binary_search_by(|run| {
+            if run.vcn.saturating_add(run.length) <= vcn {
+                cmp::Ordering::Less
+            } else if run.vcn > vcn {
+                cmp::Ordering::Greater
+            } else {
+                cmp::Ordering::Equal
+            }
+        }).ok();
+
+        let (lcn, run_remaining) = match run_index {
+            Some(index) => {
+                let run = self.data.runs[index];
+                let run_remaining = (run.length - (vcn - run.vcn)).checked_mul(self.cluster_size)
+                    .ok_or_else(|| out_of_range(run.vcn))? - cluster_offset;
+                let lcn = match run.lcn {
+                    Some(lcn) => Some(lcn.checked_add(vcn - run.vcn).ok_or_else(|| out_of_range(run.vcn))?),
+                    None => None
+                };
+                (lcn, run_remaining)
+            },
+            None => {
+                // Not covered by a run, read zeros up to the next run
+                let next_vcn = self.data.runs.iter()
+                    .map(|run| run.vcn)
+                    .filter(|run_vcn| *run_vcn > vcn)
+                    .min();
+                let run_remaining = match next_vcn {
+                    Some(next_vcn) => (next_vcn - vcn).checked_mul(self.cluster_size)
+                        .ok_or_else(|| out_of_range(next_vcn))? - cluster_offset,
+                    None => stream_remaining
+                };
+                (None, run_remaining)
+            }
+        };
+
+        let read_size = cmp::min(
+            buf.len() as u64,
+            cmp::min(run_remaining, stream_remaining)
+        ) as usize;
+
+        match lcn {
+            Some(lcn) => {
+                let position = lcn.checked_mul(self.cluster_size)
+                    .and_then(|position| position.checked_add(self.volume_offset))
+                    .and_then(|position| position.checked_add(cluster_offset))
+                    .ok_or_else(|| io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("Data run lcn {} is outside of the volume", lcn)
+                    ))?;
+                self.handle.seek(SeekFrom::Start(position))?;
+                self.handle.read_exact(&mut buf[..read_size])?;
+            },
+            None => {
+                for byte in buf[..read_size].iter_mut() {
+                    *byte = 0;
+                }
+            }
+        }
+
+        self.position += read_size as u64;
+        Ok(read_size)
+    }
+}
+
+impl<T: ReadSeek> Read for NtfsStream<T> {
+    /// Reads across runs so that the buffer is only partially filled at the end
+    /// of the stream.
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut total_read = 0;
+        while total_read < buf.len() {
+            let bytes_read = self.read_run(&mut buf[total_read..])?;
+            if bytes_read == 0 {
+                break;
+            }
+            total_read += bytes_read;
+        }
+        Ok(total_read)
+    }
+}
+
+impl<T: ReadSeek> Seek for NtfsStream<T> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(offset) => offset as i64,
+            SeekFrom::End(offset) => self.data.size as i64 + offset,
+            SeekFrom::Current(offset) => self.position as i64 + offset
+        };
+
+        if position < 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to a negative position"
+            ));
+        }
+
+        self.position = position as u64;
+        Ok(self.position)
+    }
+}
+
+
+/// NtfsVolume reads the $MFT and the $UsnJrnl:$J of an NTFS volume in a raw image.
+pub struct NtfsVolume<T: ReadSeek> {
+    handle: T,
+    offset: u64,
+    boot_sector: BootSector,
+    mft_data: NonResidentData,
+}
+
+impl NtfsVolume<File> {
+    /// Open the volume that starts at `offset` bytes into the image at `filename`.
+    pub fn from_path(filename: &str, offset: u64) -> Result<Self, UsnError> {
+        let file_handle = File::open(filename)?;
+        Self::new(file_handle, offset)
+    }
+}
+
+impl<T: ReadSeek> NtfsVolume<T> {
+    pub fn new(mut handle: T, offset: u64) -> Result<Self, UsnError> {
+        let mut buffer = vec![0; 512];
+        handle.seek(SeekFrom::Start(offset))?;
+        handle.read_exact(&mut buffer)?;
+        let boot_sector = BootSector::from_buffer(&buffer)?;
+
+        // Read the $MFT's own entry from the start of the $MFT
+        let mut buffer = vec![0; boot_sector.mft_record_size as usize];
+        let mft_offset = boot_sector.mft_lcn.checked_mul(boot_sector.cluster_size())
+            .and_then(|mft_offset| mft_offset.checked_add(offset))
+            .ok_or_else(|| UsnError::invalid_image(
+                format!("$MFT lcn {} is outside of the addressable range", boot_sector.mft_lcn)
+            ))?;
+        handle.seek(SeekFrom::Start(mft_offset))?;
+        handle.read_exact(&mut buffer)?;
+        let mft_entry = MftEntry::from_buffer(buffer, 0)?;
+
+        // Until the $MFT's runs are known, only its first record can be read.
+        let mut volume = NtfsVolume {
+            handle,
+            offset,
+            mft_data: NonResidentData {
+                runs: vec![DataRun {
+                    vcn: 0,
+                    lcn: Some(boot_sector.mft_lcn),
+                    length: 1 + u64::from(boot_sector.mft_record_size) / boot_sector.cluster_size()
+                }],
+                size: u64::from(boot_sector.mft_record_size)
+            },
+            boot_sector
+        };
+
+        volume.mft_data = volume.get_data(&mft_entry, "")?;
+
+        Ok(volume)
+    }
+
+    pub fn boot_sector(&self) -> &BootSector {
+        &self.boot_sector
+    }
+
+    /// The runs and size of the $MFT.
+    pub fn mft_data(&self) -> &NonResidentData {
+        &self.mft_data
+    }
+
+    /// Read an entry of the $MFT.
+    pub fn read_entry(&mut self, entry: u64) -> Result<MftEntry, UsnError> {
+        let record_size = u64::from(self.boot_sector.mft_record_size);
+        let mut buffer = vec![0; record_size as usize];
+
+        let mut stream = self.stream_of(self.mft_data.clone());
+        let entry_offset = entry.checked_mul(record_size).ok_or_else(|| UsnError::invalid_image(
+            format!("Entry {} is outside of the $MFT", entry)
+        ))?;
+        stream.seek(SeekFrom::Start(entry_offset))?;
+        stream.read_exact(&mut buffer)?;
+
+        Ok(MftEntry::from_buffer(buffer, entry)?)
+    }
+
+    fn stream_of(&mut self, data: NonResidentData) -> NtfsStream<&mut T> {
+        let cluster_size = self.boot_sector.cluster_size();
+        NtfsStream::new(&mut self.handle, self.offset, cluster_size, data)
+    }
+
+    /// Get the runs and size of the non-resident $DATA attribute of an entry with
+    /// the given name. If the entry has an $ATTRIBUTE_LIST, the runs are collected
+    /// from all the entries the attribute is in.
+    pub fn get_data(&mut self, entry: &MftEntry, name: &str) -> Result<NonResidentData, UsnError> {
+        let attribute_list = get_attribute_records(entry).into_iter()
+            .find(|attribute| attribute.type_code == ATTRIBUTE_LIST)
+            .map(|attribute| -> Result<Vec<u8>, UsnError> {
+                if attribute.is_non_resident() {
+                    let data = NonResidentData {
+                        runs: attribute.runs()?,
+                        size: attribute.data_size()
+                    };
+                    let mut value = Vec::new();
+                    self.stream_of(data).read_to_end(&mut value)?;
+                    return Ok(value);
+                }
+
+                match attribute.resident_value() {
+                    Some(value) => Ok(value.to_vec()),
+                    None => Err(UsnError::invalid_image(
+                        format!("Entry {} has an $ATTRIBUTE_LIST value outside of its record", entry.header.record_number)
+                    ))
+                }
+            })
+            .transpose()?;
+
+        // The entries that have the attribute
+        let mut entry_numbers = vec![entry.header.record_number];
+        if let Some(attribute_list) = attribute_list {
+            entry_numbers.clear();
+
+            let mut offset = 0;
+            while offset + 26 <= attribute_list.len() {
+                let list_entry = &attribute_list[offset..];
+                let type_code = LittleEndian::read_u32(&list_entry[0..4]);
+                let length = LittleEndian::read_u16(&list_entry[4..6]) as usize;
+                let name_length = list_entry[6] as usize;
+                let name_offset = list_entry[7] as usize;
+                if length == 0 {
+                    break;
+                }
+
+                let list_name = match list_entry.get(name_offset..name_offset + name_length * 2) {
+                    Some(name_buffer) => utf16_to_string(name_buffer),
+                    None => String::new()
+                };
+
+                // The reference is 6 bytes of entry number and 2 bytes of sequence
+                let entry_number = LittleEndian::read_u48(&list_entry[16..22]);
+                if type_code == DATA && list_name == name && !entry_numbers.contains(&entry_number) {
+                    entry_numbers.push(entry_number);
+                }
+
+                offset += length;
+            }
+        }
+
+        let mut data = NonResidentData::default();
+        let mut found = false;
+        for entry_number in entry_numbers {
+            let attribute_entry = if entry_number == entry.header.record_number {
+                None
+            } else {
+                Some(self.read_entry(entry_number)?)
+            };
+
+            for attribute in get_attribute_records(attribute_entry.as_ref().unwrap_or(entry)) {
+                if attribute.type_code != DATA || attribute.name != name || !attribute.is_non_resident() {
+                    continue;
+                }
+
+                if attribute.starting_vcn() == 0 {
+                    data.size = attribute.data_size();
+                }
+                data.runs.extend(attribute.runs()?);
+                found = true;
+            }
+        }
+
+        if !found {
+            return Err(UsnError::invalid_image(
+                format!("Entry {} has no non-resident $DATA attribute named '{}'", entry.header.record_number, name)
+            ));
+        }
+
+        data.runs.sort_by_key(|run| run.vcn);
+        Ok(data)
+    }
+
+    /// Find the entry of $Extend\$UsnJrnl.
+    pub fn find_usn_journal_entry(&mut self) -> Result<MftEntry, UsnError> {
+        let entry_count = self.mft_data.size / u64::from(self.boot_sector.mft_record_size);
+
+        for entry_number in EXTEND_ENTRY + 1..entry_count {
+            let entry = match self.read_entry(entry_number) {
+                Ok(entry) => entry,
+                Err(_) => continue
+            };
+
+            if !entry.header.is_valid() || !entry.is_allocated() || entry.header.base_reference.entry != 0 {
+                continue;
+            }
+
+            let is_usn_journal = entry.iter_attributes_matching(Some(vec![MftAttributeType::FileName]))
+                .filter_map(Result::ok)
+                .filter_map(|attribute| attribute.data.into_file_name())
+                .any(|file_name| file_name.parent.entry == EXTEND_ENTRY && file_name.name == "$UsnJrnl");
+
+            if is_usn_journal {
+                return Ok(entry);
+            }
+        }
+
+        Err(UsnError::invalid_image("Unable to find $Extend\\$UsnJrnl".to_string()))
+    }
+
+    /// Get the runs and size of $UsnJrnl:$J.
+    pub fn usn_journal_data(&mut self) -> Result<NonResidentData, UsnError> {
+        let entry = self.find_usn_journal_entry()?;
+        self.get_data(&entry, "$J")
+    }
+
+    /// Turn the volume into a stream of the given data.
+    pub fn into_stream(self, data: NonResidentData) -> NtfsStream<T> {
+        let cluster_size = self.boot_sector.cluster_size();
+        NtfsStream::new(self.handle, self.offset, cluster_size, data)
+    }
+
+    /// Turn the volume into a stream of $UsnJrnl:$J.
+    pub fn into_usn_journal_stream(mut self) -> Result<NtfsStream<T>, UsnError> {
+        let data = self.usn_journal_data()?;
+        Ok(self.into_stream(data))
+    }
+
+    /// Turn the volume into a stream of the $LogFile.
+    pub fn into_logfile_stream(mut self) -> Result<NtfsStream<T>, UsnError> {
+        let entry = self.read_entry(LOGFILE_ENTRY)?;
+        let data = self.get_data(&entry, "")?;
+        Ok(self.into_stream(data))
+    }
+
+    /// Turn the volume into an MftParser of its $MFT.
+    pub fn into_mft_parser(self) -> Result<MftParser<NtfsStream<T>>, UsnError> {
+        let data = self.mft_data.clone();
+        let size = data.size;
+        Ok(MftParser::from_read_seek(self.into_stream(data), Some(size))?)
+    }
+}
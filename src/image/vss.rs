@@ -0,0 +1,393 @@
+use std::cmp;
+use std::fs::File;
+use std::io;
+use std::io::{Read, Seek, SeekFrom};
+use std::collections::{HashMap, HashSet};
+use byteorder::{ByteOrder, LittleEndian};
+use chrono::{DateTime, Utc};
+use winstructs::guid::Guid;
+use crate::ReadSeek;
+use crate::error::UsnError;
+use crate::utils::u64_to_datetime;
+
+/// The offset of the VSS volume header in the volume.
+const VOLUME_HEADER_OFFSET: u64 = 0x1E00;
+/// {3808876b-c176-4e48-b7ae-04046e6cc752}
+const VSS_IDENTIFIER: [u8; 16] = [
+    0x6B, 0x87, 0x08, 0x38, 0x76, 0xC1, 0x48, 0x4E,
+    0xB7, 0xAE, 0x04, 0x04, 0x6E, 0x6C, 0xC7, 0x52
+];
+const RECORD_TYPE_VOLUME_HEADER: u32 = 1;
+const RECORD_TYPE_CATALOG: u32 = 2;
+const RECORD_TYPE_BLOCK_LIST: u32 = 3;
+
+/// Catalogs, block lists and copied data are all in blocks of this size.
+const BLOCK_SIZE: u64 = 0x4000;
+const SIZE_BLOCK_HEADER: usize = 128;
+const SIZE_CATALOG_ENTRY: usize = 128;
+const SIZE_BLOCK_DESCRIPTOR: usize = 32;
+const SIZE_SECTOR: usize = 512;
+/// A bit for each sector of a block.
+const ALL_SECTORS: u32 = 0xFFFF_FFFF;
+
+const CATALOG_ENTRY_STORE_INFO: u64 = 2;
+const CATALOG_ENTRY_STORE_LOCATION: u64 = 3;
+
+const BLOCK_FORWARDER: u32 = 0x01;
+const BLOCK_OVERLAY: u32 = 0x02;
+const BLOCK_NOT_USED: u32 = 0x04;
+
+
+/// The blocks of a shadow copy store, by the offset of the original block in the
+/// volume.
+#[derive(Debug, Clone, Default)]
+struct StoreBlocks {
+    /// The offset of the block's copy in the store.
+    copies: HashMap<u64, u64>,
+    /// The block was moved, its data is at this offset as of the next store.
+    forwarders: HashMap<u64, u64>,
+    /// The offset of a copy of some of the block's sectors, and a bitmap of the
+    /// sectors.
+    overlays: HashMap<u64, (u64, u32)>,
+}
+
+impl StoreBlocks {
+    fn add_descriptor(&mut self, descriptor: &[u8]) {
+        let original_offset = LittleEndian::read_u64(&descriptor[0..8]);
+        let relative_offset = LittleEndian::read_u64(&descriptor[8..16]);
+        let store_offset = LittleEndian::read_u64(&descriptor[16..24]);
+        let flags = LittleEndian::read_u32(&descriptor[24..28]);
+        let bitmap = LittleEndian::read_u32(&descriptor[28..32]);
+
+        if flags & BLOCK_NOT_USED != 0 {
+            return;
+        }
+
+        if flags & BLOCK_FORWARDER != 0 {
+            self.forwarders.insert(original_offset, relative_offset);
+        } else if flags & BLOCK_OVERLAY != 0 {
+            // Later overlays of the same block add to the sectors
+            let overlay = self.overlays.entry(original_offset).or_insert((store_offset, 0));
+            overlay.0 = store_offset;
+            overlay.1 |= bitmap;
+        } else {
+            self.copies.insert(original_offset, store_offset);
+        }
+    }
+}
+
+
+/// A shadow copy of the volume.
+#[derive(Debug, Clone)]
+pub struct ShadowCopy {
+    /// The identifier of the shadow copy's store.
+    pub identifier: Guid,
+    pub creation_time: DateTime<Utc>,
+    /// The size of the volume when the shadow copy was created.
+    pub volume_size: u64,
+    blocks: StoreBlocks,
+}
+
+
+/// VssVolume reads the Volume Shadow Copy catalog of an NTFS volume in a raw
+/// image. Each shadow copy store holds the blocks that were overwritten after its
+/// snapshot was taken, so a snapshot is read from its own store, then the stores
+/// of the later snapshots, then the current volume.
+pub struct VssVolume<T: ReadSeek> {
+    handle: T,
+    offset: u64,
+    shadow_copies: Vec<ShadowCopy>,
+}
+
+impl VssVolume<File> {
+    /// Open the shadow copies of the volume that starts at `offset` bytes into the
+    /// image at `filename`.
+    pub fn from_path(filename: &str, offset: u64) -> Result<Self, UsnError> {
+        let file_handle = File::open(filename)?;
+        Self::new(file_handle, offset)
+    }
+}
+
+impl<T: ReadSeek> VssVolume<T> {
+    pub fn new(mut handle: T, offset: u64) -> Result<Self, UsnError> {
+        let mut header = vec![0; SIZE_SECTOR];
+        handle.seek(SeekFrom::Start(offset + VOLUME_HEADER_OFFSET))?;
+        handle.read_exact(&mut header)?;
+
+        if header[0..16] != VSS_IDENTIFIER ||
+            LittleEndian::read_u32(&header[20..24]) != RECORD_TYPE_VOLUME_HEADER {
+            return Err(UsnError::invalid_image("No VSS volume header".to_string()));
+        }
+
+        let mut volume = VssVolume {
+            handle,
+            offset,
+            shadow_copies: Vec::new()
+        };
+
+        // The catalog offset is 0 if there are no shadow copies
+        let catalog_offset = LittleEndian::read_u64(&header[48..56]);
+        if catalog_offset != 0 {
+            volume.shadow_copies = volume.read_catalog(catalog_offset)?;
+        }
+
+        Ok(volume)
+    }
+
+    /// The shadow copies, oldest first.
+    pub fn shadow_copies(&self) -> &[ShadowCopy] {
+        &self.shadow_copies
+    }
+
+    /// Turn the volume into a reader of the volume as of a shadow copy. The
+    /// shadow copy is the index into `shadow_copies`.
+    pub fn into_snapshot(self, index: usize) -> Result<VssSnapshot<T>, UsnError> {
+        let shadow_copy = match self.shadow_copies.get(index) {
+            Some(shadow_copy) => shadow_copy,
+            None => return Err(UsnError::invalid_image(
+                format!("No shadow copy {}, the volume has {}", index, self.shadow_copies.len())
+            ))
+        };
+
+        Ok(VssSnapshot {
+            identifier: shadow_copy.identifier.clone(),
+            size: shadow_copy.volume_size,
+            stores: self.shadow_copies[index..].iter()
+                .map(|shadow_copy| shadow_copy.blocks.clone())
+                .collect(),
+            handle: self.handle,
+            offset: self.offset,
+            position: 0,
+            block: None
+        })
+    }
+
+    /// Read a block of the volume.
+    fn read_block(&mut self, block_offset: u64) -> Result<Vec<u8>, UsnError> {
+        let mut block = vec![0; BLOCK_SIZE as usize];
+        self.handle.seek(SeekFrom::Start(self.offset + block_offset))?;
+        self.handle.read_exact(&mut block)?;
+        Ok(block)
+    }
+
+    /// Read the blocks of a linked list of catalog or block list blocks.
+    fn read_block_list(&mut self, first_offset: u64, record_type: u32) -> Result<Vec<Vec<u8>>, UsnError> {
+        let mut blocks = Vec::new();
+        let mut seen = HashSet::new();
+        let mut block_offset = first_offset;
+
+        while block_offset != 0 && seen.insert(block_offset) {
+            let block = self.read_block(block_offset)?;
+            if block[0..16] != VSS_IDENTIFIER || LittleEndian::read_u32(&block[20..24]) != record_type {
+                return Err(UsnError::invalid_image(
+                    format!("VSS block at offset {} is not of record type {}", block_offset, record_type)
+                ));
+            }
+
+            block_offset = LittleEndian::read_u64(&block[40..48]);
+            blocks.push(block);
+        }
+
+        Ok(blocks)
+    }
+
+    /// Read the shadow copies of the catalog. Each store has an entry with its
+    /// creation time and an entry with the location of its block list.
+    fn read_catalog(&mut self, catalog_offset: u64) -> Result<Vec<ShadowCopy>, UsnError> {
+        let mut store_info = Vec::new();
+        let mut block_lists = HashMap::new();
+
+        for block in self.read_block_list(catalog_offset, RECORD_TYPE_CATALOG)? {
+            for entry in block[SIZE_BLOCK_HEADER..].chunks_exact(SIZE_CATALOG_ENTRY) {
+                let identifier = entry[16..32].to_vec();
+
+                match LittleEndian::read_u64(&entry[0..8]) {
+                    CATALOG_ENTRY_STORE_INFO => store_info.push((
+                        identifier,
+                        LittleEndian::read_u64(&entry[8..16]),
+                        LittleEndian::read_u64(&entry[48..56])
+                    )),
+                    CATALOG_ENTRY_STORE_LOCATION => {
+                        block_lists.insert(identifier, LittleEndian::read_u64(&entry[8..16]));
+                    },
+                    _ => {}
+                }
+            }
+        }
+
+        let mut shadow_copies = Vec::new();
+        for (identifier, volume_size, creation_time) in store_info {
+            let block_list_offset = match block_lists.get(&identifier) {
+                Some(block_list_offset) => *block_list_offset,
+                None => {
+                    warn!("VSS store {:?} has no block list", identifier);
+                    continue;
+                }
+            };
+
+            let mut blocks = StoreBlocks::default();
+            for block in self.read_block_list(block_list_offset, RECORD_TYPE_BLOCK_LIST)? {
+                for descriptor in block[SIZE_BLOCK_HEADER..].chunks_exact(SIZE_BLOCK_DESCRIPTOR) {
+                    // Unused descriptors are all zeros
+                    if descriptor.iter().any(|byte| *byte != 0) {
+                        blocks.add_descriptor(descriptor);
+                    }
+                }
+            }
+
+            shadow_copies.push(ShadowCopy {
+                identifier: Guid::from_buffer(&identifier)?,
+                creation_time: u64_to_datetime(creation_time),
+                volume_size,
+                blocks
+            });
+        }
+
+        shadow_copies.sort_by_key(|shadow_copy| shadow_copy.creation_time);
+        Ok(shadow_copies)
+    }
+}
+
+
+/// VssSnapshot reads the volume as of a shadow copy. Offsets are relative to the
+/// start of the volume, so it can be opened with `NtfsVolume::new(snapshot, 0)`.
+pub struct VssSnapshot<T: ReadSeek> {
+    identifier: Guid,
+    size: u64,
+    /// The blocks of the snapshot's store and of every later store.
+    stores: Vec<StoreBlocks>,
+    handle: T,
+    offset: u64,
+    position: u64,
+    /// The last block read, by its offset.
+    block: Option<(u64, Vec<u8>)>,
+}
+
+impl<T: ReadSeek> VssSnapshot<T> {
+    /// The identifier of the shadow copy's store.
+    pub fn identifier(&self) -> &Guid {
+        &self.identifier
+    }
+
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Read the sectors of a block in `sectors` from `offset` in the volume.
+    fn copy_sectors(&mut self, offset: u64, sectors: u32, block: &mut [u8]) -> io::Result<()> {
+        let mut buffer = vec![0; block.len()];
+        self.handle.seek(SeekFrom::Start(self.offset + offset))?;
+        self.handle.read_exact(&mut buffer)?;
+
+        for (sector, (destination, source)) in block.chunks_mut(SIZE_SECTOR)
+            .zip(buffer.chunks(SIZE_SECTOR))
+            .enumerate() {
+            if sectors & (1 << sector) != 0 {
+                destination.copy_from_slice(source);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Find where the sectors of a block are as of the snapshot. Each store is
+    /// checked for an overlay of some sectors or a copy of the block, following
+    /// blocks that were moved. The sectors that are in none of the stores have not
+    /// changed since the snapshot, and are read from the volume.
+    fn resolve_block(&self, block_offset: u64) -> Vec<(u64, u32)> {
+        let mut sources = Vec::new();
+        let mut offset = block_offset;
+        let mut filled = 0u32;
+
+        for store in self.stores.iter() {
+            if let Some((store_offset, bitmap)) = store.overlays.get(&offset) {
+                let sectors = bitmap & !filled;
+                if sectors != 0 {
+                    sources.push((*store_offset, sectors));
+                    filled |= sectors;
+                }
+            }
+
+            if let Some(store_offset) = store.copies.get(&offset) {
+                sources.push((*store_offset, !filled));
+                filled = ALL_SECTORS;
+                break;
+            }
+
+            if let Some(forward_offset) = store.forwarders.get(&offset) {
+                offset = *forward_offset;
+            }
+        }
+
+        if filled != ALL_SECTORS {
+            sources.push((offset, !filled));
+        }
+
+        sources
+    }
+
+    fn read_block(&mut self, block_offset: u64) -> io::Result<Vec<u8>> {
+        let block_size = cmp::min(BLOCK_SIZE, self.size - block_offset) as usize;
+        let mut block = vec![0; block_size];
+
+        for (offset, sectors) in self.resolve_block(block_offset) {
+            self.copy_sectors(offset, sectors, &mut block)?;
+        }
+
+        Ok(block)
+    }
+}
+
+impl<T: ReadSeek> Read for VssSnapshot<T> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut total_read = 0;
+
+        while total_read < buf.len() && self.position < self.size {
+            let block_offset = self.position - self.position % BLOCK_SIZE;
+
+            let is_cached = match self.block {
+                Some((cached_offset, _)) => cached_offset == block_offset,
+                None => false
+            };
+            if !is_cached {
+                let block = self.read_block(block_offset)?;
+                self.block = Some((block_offset, block));
+            }
+
+            let block = match self.block {
+                Some((_, ref block)) => block,
+                None => break
+            };
+
+            let block_position = (self.position - block_offset) as usize;
+            let read_size = cmp::min(buf.len() - total_read, block.len() - block_position);
+            buf[total_read..total_read + read_size]
+                .copy_from_slice(&block[block_position..block_position + read_size]);
+
+            total_read += read_size;
+            self.position += read_size as u64;
+        }
+
+        Ok(total_read)
+    }
+}
+
+impl<T: ReadSeek> Seek for VssSnapshot<T> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(offset) => offset as i64,
+            SeekFrom::End(offset) => self.size as i64 + offset,
+            SeekFrom::Current(offset) => self.position as i64 + offset
+        };
+
+        if position < 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to a negative position"
+            ));
+        }
+
+        self.position = position as u64;
+        Ok(self.position)
+    }
+}
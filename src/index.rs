@@ -0,0 +1,315 @@
+use std::io;
+use std::collections::HashMap;
+use mft::{MftEntry, MftParser};
+use mft::attribute::MftAttributeType;
+use mft::attribute::header::ResidentialHeader;
+use mft::attribute::x30::FileNamespace;
+use serde::Serialize;
+use chrono::{DateTime, Utc};
+use winstructs::ntfs::mft_reference::MftReference;
+use crate::ReadSeek;
+use crate::mapping::EntryMapping;
+
+
+/// The MACB timestamps of a $STANDARD_INFORMATION or $FILE_NAME attribute.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+pub struct MacbTimes {
+    pub created: DateTime<Utc>,
+    pub modified: DateTime<Utc>,
+    pub mft_modified: DateTime<Utc>,
+    pub accessed: DateTime<Utc>,
+}
+
+
+/// The MFT fields added to a record by `FileIndex::get_enrichment`.
+#[derive(Serialize, Debug)]
+pub struct MftEnrichment {
+    pub mft_allocated: bool,
+    pub mft_logical_size: Option<u64>,
+    pub mft_si_created: Option<DateTime<Utc>>,
+    pub mft_si_modified: Option<DateTime<Utc>>,
+    pub mft_si_mft_modified: Option<DateTime<Utc>>,
+    pub mft_si_accessed: Option<DateTime<Utc>>,
+    pub mft_fn_created: Option<DateTime<Utc>>,
+    pub mft_fn_modified: Option<DateTime<Utc>>,
+    pub mft_fn_mft_modified: Option<DateTime<Utc>>,
+    pub mft_fn_accessed: Option<DateTime<Utc>>,
+}
+
+
+/// An MFT entry as known to the FileIndex.
+#[derive(Serialize, Debug)]
+pub struct FileEntry {
+    /// The reference of the file. For unallocated entries this is the reference
+    /// the file had before it was deleted.
+    pub reference: MftReference,
+    pub allocated: bool,
+    pub is_dir: bool,
+    /// The names of the file, one per hard link. DOS (8.3) names are only
+    /// included if the file has no other names.
+    pub names: Vec<EntryMapping>,
+    /// The timestamps of the $STANDARD_INFORMATION attribute.
+    pub standard_info: Option<MacbTimes>,
+    /// The timestamps of the best $FILE_NAME attribute.
+    pub file_name_times: Option<MacbTimes>,
+    /// The size of the unnamed $DATA attribute.
+    pub logical_size: Option<u64>,
+}
+
+
+/// FileIndex maps every MFT entry, files and directories, to its names so that
+/// a file reference can be resolved to its current paths.
+#[derive(Debug, Default)]
+pub struct FileIndex {
+    entries: HashMap<u64, FileEntry>,
+}
+
+impl FileIndex {
+    pub fn new() -> Self {
+        FileIndex::default()
+    }
+
+    pub fn from_mft_path(filename: &str) -> Result<Self, io::Error> {
+        let mut parser = MftParser::from_path(filename).map_err(|error|
+            io::Error::new(io::ErrorKind::Other, format!("{}", error))
+        )?;
+
+        let mut file_index = FileIndex::new();
+        file_index.build_file_index(&mut parser);
+
+        Ok(file_index)
+    }
+
+    pub fn build_file_index<T: ReadSeek>(&mut self, mft_parser: &mut MftParser<T>) {
+        // Names and data in extension entries belong to their base entry
+        let mut extension_names: HashMap<u64, Vec<EntryMapping>> = HashMap::new();
+        let mut extension_sizes: HashMap<u64, u64> = HashMap::new();
+
+        for entry in mft_parser.iter_entries() {
+            let e = match entry {
+                Ok(e) => e,
+                Err(err) => {
+                    warn!("Error reading an MFT entry: {}", err);
+                    continue;
+                }
+            };
+
+            let mut file_names: Vec<_> = e.iter_attributes_matching(Some(vec![MftAttributeType::FileName]))
+                .filter_map(Result::ok)
+                .filter_map(|attribute| attribute.data.into_file_name())
+                .collect();
+
+            if file_names.iter().any(|file_name| file_name.namespace != FileNamespace::DOS) {
+                file_names.retain(|file_name| file_name.namespace != FileNamespace::DOS);
+            }
+
+            let names = file_names.into_iter()
+                .map(|file_name| EntryMapping {
+                    name: file_name.name,
+                    parent: file_name.parent
+                });
+
+            let file_name_times = e.find_best_name_attribute()
+                .map(|file_name| MacbTimes {
+                    created: file_name.created,
+                    modified: file_name.modified,
+                    mft_modified: file_name.mft_modified,
+                    accessed: file_name.accessed
+                });
+
+            let logical_size = get_logical_size(&e);
+
+            if e.header.base_reference.entry != 0 {
+                extension_names.entry(e.header.base_reference.entry)
+                    .or_default()
+                    .extend(names);
+                if let Some(size) = logical_size {
+                    extension_sizes.insert(e.header.base_reference.entry, size);
+                }
+                continue;
+            }
+
+            let standard_info = e.iter_attributes_matching(Some(vec![MftAttributeType::StandardInformation]))
+                .filter_map(Result::ok)
+                .filter_map(|attribute| attribute.data.into_standard_info())
+                .map(|standard_info| MacbTimes {
+                    created: standard_info.created,
+                    modified: standard_info.modified,
+                    mft_modified: standard_info.mft_modified,
+                    accessed: standard_info.accessed
+                })
+                .next();
+
+            let mut sequence = e.header.sequence;
+            if !e.is_allocated() {
+                sequence = sequence.wrapping_sub(1);
+            }
+
+            self.entries.insert(
+                e.header.record_number,
+                FileEntry {
+                    reference: MftReference::new(e.header.record_number, sequence),
+                    allocated: e.is_allocated(),
+                    is_dir: e.is_dir(),
+                    names: names.collect(),
+                    standard_info,
+                    file_name_times,
+                    logical_size
+                }
+            );
+        }
+
+        for (entry, size) in extension_sizes {
+            if let Some(file_entry) = self.entries.get_mut(&entry) {
+                if file_entry.logical_size.is_none() {
+                    file_entry.logical_size = Some(size);
+                }
+            }
+        }
+
+        for (entry, names) in extension_names {
+            if let Some(file_entry) = self.entries.get_mut(&entry) {
+                file_entry.names.extend(names);
+            }
+        }
+    }
+
+    /// Get the entry for a reference. The sequence must match, otherwise the entry
+    /// has been reused by another file.
+    pub fn get_entry(&self, reference: &MftReference) -> Option<&FileEntry> {
+        match self.entries.get(&reference.entry) {
+            Some(file_entry) if file_entry.reference == *reference => Some(file_entry),
+            _ => None
+        }
+    }
+
+    /// Get the entry with this entry number, whatever its sequence.
+    pub fn get_entry_by_number(&self, entry: u64) -> Option<&FileEntry> {
+        self.entries.get(&entry)
+    }
+
+    /// Check if the file of a reference still exists, that is its entry is
+    /// allocated and has the same sequence.
+    pub fn exists(&self, reference: &MftReference) -> bool {
+        match self.get_entry(reference) {
+            Some(file_entry) => file_entry.allocated,
+            None => false
+        }
+    }
+
+    /// Get the MFT fields of a reference's entry to add to its records. The entry
+    /// must have the same sequence as the reference.
+    pub fn get_enrichment(&self, reference: &MftReference) -> Option<MftEnrichment> {
+        let file_entry = self.get_entry(reference)?;
+        let standard_info = file_entry.standard_info;
+        let file_name_times = file_entry.file_name_times;
+
+        Some(MftEnrichment {
+            mft_allocated: file_entry.allocated,
+            mft_logical_size: file_entry.logical_size,
+            mft_si_created: standard_info.map(|times| times.created),
+            mft_si_modified: standard_info.map(|times| times.modified),
+            mft_si_mft_modified: standard_info.map(|times| times.mft_modified),
+            mft_si_accessed: standard_info.map(|times| times.accessed),
+            mft_fn_created: file_name_times.map(|times| times.created),
+            mft_fn_modified: file_name_times.map(|times| times.modified),
+            mft_fn_mft_modified: file_name_times.map(|times| times.mft_modified),
+            mft_fn_accessed: file_name_times.map(|times| times.accessed),
+        })
+    }
+
+    /// Get the full paths of a reference, one for each of its names.
+    pub fn full_paths(&self, reference: &MftReference) -> Vec<String> {
+        match self.get_entry(reference) {
+            Some(_) if reference.entry == 5 => vec!["[root]".to_string()],
+            Some(file_entry) => {
+                file_entry.names.iter()
+                    .map(|name| format!("{}/{}", self.enumerate_path(&name.parent), name.name))
+                    .collect()
+            },
+            None => Vec::new()
+        }
+    }
+
+    /// Get the full path of a reference using its first name.
+    pub fn full_path(&self, reference: &MftReference) -> Option<String> {
+        self.full_paths(reference).into_iter().next()
+    }
+
+    /// Enumerate the path of a directory reference.
+    pub fn enumerate_path(&self, reference: &MftReference) -> String {
+        let mut lookup_ref = *reference;
+        let mut path_queue: Vec<String> = Vec::new();
+
+        loop {
+            if lookup_ref.entry == 5 {
+                path_queue.push("[root]".to_string());
+                break;
+            }
+
+            // A corrupt MFT can loop, so limit the depth.
+            if path_queue.len() > 255 {
+                path_queue.push("[<loop>]".to_string());
+                break;
+            }
+
+            match self.get_entry(&lookup_ref).and_then(|file_entry| file_entry.names.first()) {
+                Some(name) => {
+                    path_queue.push(name.name.clone());
+                    lookup_ref = name.parent;
+                },
+                None => {
+                    path_queue.push("[<unknown>]".to_string());
+                    break;
+                }
+            }
+        }
+
+        path_queue.reverse();
+        path_queue.join("/")
+    }
+
+    /// Add an entry to the index, replacing any entry with the same entry number.
+    pub fn add_entry(&mut self, file_entry: FileEntry) {
+        self.entries.insert(
+            file_entry.reference.entry,
+            file_entry
+        );
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+
+/// Get the size of the unnamed $DATA attribute of an entry. For non-resident
+/// data, only the attribute starting at VCN 0 has the size.
+fn get_logical_size(entry: &MftEntry) -> Option<u64> {
+    for attribute in entry.iter_attributes_matching(Some(vec![MftAttributeType::DATA])) {
+        let attribute = match attribute {
+            Ok(attribute) => attribute,
+            Err(_) => continue
+        };
+
+        if !attribute.header.name.is_empty() {
+            continue;
+        }
+
+        match attribute.header.residential_header {
+            ResidentialHeader::Resident(ref header) => {
+                return Some(u64::from(header.data_size));
+            },
+            ResidentialHeader::NonResident(ref header) if header.vnc_first == 0 => {
+                return Some(header.file_size);
+            },
+            _ => {}
+        }
+    }
+
+    None
+}
@@ -10,6 +10,18 @@ pub mod utils;
 pub mod flags;
 pub mod liveusn;
 pub mod mapping;
+pub mod filter;
+pub mod output;
+pub mod index;
+pub mod image;
+pub mod dedup;
+pub mod merge;
+pub mod anomaly;
+pub mod activity;
+pub mod history;
+pub mod lifecycle;
+pub mod confidence;
+pub mod logfile;
 
 
 use std::io;
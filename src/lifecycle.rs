@@ -0,0 +1,149 @@
+use std::collections::HashMap;
+use serde::Serialize;
+use chrono::{DateTime, Utc};
+use winstructs::ntfs::mft_reference::MftReference;
+use crate::flags::{FileAttributes, Reason};
+use crate::index::FileIndex;
+use crate::record::UsnEntry;
+
+
+/// The life of a file reference from its create to its delete record. Files that
+/// were created before the journal starts have no create, and files that still
+/// exist have no delete.
+#[derive(Serialize, Debug, Clone)]
+pub struct FileLifetime {
+    pub file_reference: MftReference,
+    /// Every name of the file, in the order they were seen.
+    pub names: Vec<String>,
+    /// The parent as of the last record.
+    pub parent_reference: MftReference,
+    pub is_dir: bool,
+    pub first_usn: u64,
+    pub last_usn: u64,
+    pub created: Option<DateTime<Utc>>,
+    pub deleted: Option<DateTime<Utc>>,
+    /// The seconds from the create to the delete.
+    pub duration_seconds: Option<f64>,
+    /// The file's MFT entry has since been used by a file with a higher sequence
+    /// number.
+    pub entry_reused: bool,
+}
+
+impl FileLifetime {
+    fn new(entry: &UsnEntry) -> Self {
+        let record = &entry.record;
+
+        FileLifetime {
+            file_reference: record.get_file_reference(),
+            names: Vec::new(),
+            parent_reference: record.get_parent_reference(),
+            is_dir: false,
+            first_usn: record.get_usn(),
+            last_usn: record.get_usn(),
+            created: None,
+            deleted: None,
+            duration_seconds: None,
+            entry_reused: false,
+        }
+    }
+
+    fn add(&mut self, entry: &UsnEntry) {
+        let record = &entry.record;
+        let reason = record.get_reason_code();
+
+        // Version 4 records do not have a name, parent or timestamp
+        if record.get_major_version() != 4 {
+            let file_name = record.get_file_name();
+            if !self.names.contains(&file_name) {
+                self.names.push(file_name);
+            }
+            self.parent_reference = record.get_parent_reference();
+            self.is_dir = record.get_file_attributes().contains(FileAttributes::FILE_ATTRIBUTE_DIRECTORY);
+        }
+
+        if reason.contains(Reason::USN_REASON_FILE_CREATE) && self.created.is_none() {
+            self.created = record.get_timestamp();
+        }
+        if reason.contains(Reason::USN_REASON_FILE_DELETE) {
+            self.deleted = record.get_timestamp();
+        }
+
+        if let (Some(created), Some(deleted)) = (self.created, self.deleted) {
+            self.duration_seconds = Some((deleted - created).num_milliseconds() as f64 / 1000.0);
+        }
+
+        self.last_usn = record.get_usn();
+    }
+}
+
+
+/// LifecycleTracker follows each file reference from `USN_REASON_FILE_CREATE` to
+/// `USN_REASON_FILE_DELETE`. Records must be added in USN order.
+#[derive(Debug, Default)]
+pub struct LifecycleTracker {
+    open: HashMap<MftReference, FileLifetime>,
+    finished: Vec<FileLifetime>,
+    // The highest sequence number seen of each entry
+    sequences: HashMap<u64, u16>,
+}
+
+impl LifecycleTracker {
+    pub fn new() -> Self {
+        LifecycleTracker::default()
+    }
+
+    pub fn add(&mut self, entry: &UsnEntry) {
+        let file_reference = entry.record.get_file_reference();
+
+        let sequence = self.sequences.entry(file_reference.entry)
+            .or_insert(file_reference.sequence);
+        if file_reference.sequence > *sequence {
+            *sequence = file_reference.sequence;
+        }
+
+        let lifetime = self.open.entry(file_reference)
+            .or_insert_with(|| FileLifetime::new(entry));
+        lifetime.add(entry);
+
+        // The delete ends the file's life, later records are of another lifetime
+        if lifetime.deleted.is_some() {
+            if let Some(lifetime) = self.open.remove(&file_reference) {
+                self.finished.push(lifetime);
+            }
+        }
+    }
+
+    /// Take every lifetime in the order they started. A lifetime's entry is reused
+    /// if the journal or the optional file index has the entry with a higher
+    /// sequence number.
+    pub fn finish(&mut self, file_index: Option<&FileIndex>) -> Vec<FileLifetime> {
+        let mut lifetimes = std::mem::take(&mut self.finished);
+        lifetimes.extend(self.open.drain().map(|(_, lifetime)| lifetime));
+        lifetimes.sort_by_key(|lifetime| lifetime.first_usn);
+
+        for lifetime in lifetimes.iter_mut() {
+            let file_reference = lifetime.file_reference;
+
+            let journal_sequence = self.sequences.get(&file_reference.entry).copied();
+            let mft_sequence = file_index
+                .and_then(|index| index.get_entry_by_number(file_reference.entry))
+                .map(|file_entry| file_entry.reference.sequence);
+
+            lifetime.entry_reused = [journal_sequence, mft_sequence].iter()
+                .flatten()
+                .any(|sequence| *sequence > file_reference.sequence);
+        }
+
+        lifetimes
+    }
+
+    /// Get the lifetimes of an iterator's records.
+    pub fn track<I: Iterator<Item = UsnEntry>>(records: I, file_index: Option<&FileIndex>) -> Vec<FileLifetime> {
+        let mut tracker = LifecycleTracker::new();
+        for entry in records {
+            tracker.add(&entry);
+        }
+
+        tracker.finish(file_index)
+    }
+}
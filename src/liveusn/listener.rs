@@ -3,10 +3,11 @@ use std::fs::File;
 use std::process::exit;
 use std::time::Duration;
 use std::sync::mpsc::Sender;
+use serde_json::json;
 use serde_json::value::Value;
 use byteorder::{ByteOrder, LittleEndian};
 use crate::flags;
-use crate::record::EntryMeta;
+use crate::record::{EntryMeta, UsnEntry};
 use crate::liveusn::winfuncs::{
     query_usn_journal,
     read_usn_journal,
@@ -21,11 +22,13 @@ pub struct UsnVolumeListener {
     source: String,
     sleep_ms: u64,
     historical_flag: bool,
-    sender: Sender<Value>
+    sender: Sender<(UsnEntry, Value)>
 }
 
 impl UsnVolumeListener {
-    pub fn new(source: String, historical_flag: bool, sender: Sender<Value>) -> Self {
+    /// The listener sends each entry along with an object of additional
+    /// fields (the entry's `full_path`).
+    pub fn new(source: String, historical_flag: bool, sender: Sender<(UsnEntry, Value)>) -> Self {
         let sleep_ms = 100;
 
         UsnVolumeListener {
@@ -146,23 +149,10 @@ impl UsnVolumeListener {
                             None => "[<unknown>]".to_string()
                         };
 
-                        let mut entry_value = match usn_entry.to_json_value(){
-                            Ok(value) => value,
-                            Err(e) => {
-                                eprintln!("Error serializing entry to json value {:?}: {:?}", usn_entry, e);
-                                continue;
-                            }
-                        };
-
                         let full_file_name = format!("{}/{}", &full_path, &file_name);
+                        let additional = json!({"full_path": full_file_name});
 
-                        let map = entry_value.as_object_mut().unwrap();
-                        map.insert(
-                            "full_path".to_string(), 
-                            Value::String(full_file_name)
-                        );
-
-                        match self.sender.send(entry_value) {
+                        match self.sender.send((usn_entry, additional)) {
                             Ok(_) => {
                                 record_count += 1;
                             },
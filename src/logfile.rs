@@ -0,0 +1,445 @@
+use std::io;
+use std::fs::File;
+use std::io::SeekFrom;
+use std::collections::VecDeque;
+use byteorder::{ByteOrder, LittleEndian};
+use crate::ReadSeek;
+use crate::error::UsnError;
+use crate::record::{EntryMeta, UsnEntry};
+
+
+const RESTART_SIGNATURE: &[u8] = b"RSTR";
+const RECORD_SIGNATURE: &[u8] = b"RCRD";
+// The update sequence fixups protect the end of every 512 bytes
+const SIZE_SECTOR: usize = 512;
+// The layout of the log when no restart page can be read
+const DEFAULT_PAGE_SIZE: usize = 4096;
+const DEFAULT_PAGE_DATA_OFFSET: usize = 0x40;
+const DEFAULT_RECORD_HEADER_LENGTH: usize = 0x30;
+// Log record types
+const LOG_RECORD_CLIENT: u32 = 1;
+const LOG_RECORD_RESTART: u32 = 2;
+// The NTFS operation that writes to a non-resident attribute, such as $J
+const UPDATE_NONRESIDENT_VALUE: u16 = 0x08;
+// The size of the NTFS log record header at the start of the client data
+const SIZE_NTFS_HEADER: usize = 0x20;
+// Client data larger than this is not a log record
+const MAX_CLIENT_DATA_LENGTH: usize = 0x10000;
+
+
+/// Apply the update sequence fixups of a multi sector structure, such as a
+/// $LogFile page. The last two bytes of each 512 bytes must match the update
+/// sequence number and are replaced with the saved bytes. An error means the
+/// structure is not valid or was torn by an incomplete write.
+pub fn apply_fixups(buffer: &mut [u8]) -> Result<(), UsnError> {
+    if buffer.len() < 8 {
+        return Err(UsnError::invalid_logfile("Buffer is too small for a multi sector header".to_string()));
+    }
+
+    let usa_offset = LittleEndian::read_u16(&buffer[4..6]) as usize;
+    let usa_count = LittleEndian::read_u16(&buffer[6..8]) as usize;
+    if usa_count == 0 || usa_offset + usa_count * 2 > buffer.len() || (usa_count - 1) * SIZE_SECTOR > buffer.len() {
+        return Err(UsnError::invalid_logfile(
+            format!("Update sequence array of {} at offset {} does not fit", usa_count, usa_offset)
+        ));
+    }
+
+    let sequence_number = [buffer[usa_offset], buffer[usa_offset + 1]];
+    for sector in 1..usa_count {
+        let sector_end = sector * SIZE_SECTOR;
+        if buffer[sector_end - 2..sector_end] != sequence_number {
+            return Err(UsnError::invalid_logfile(
+                format!("Sector {} does not match the update sequence number", sector - 1)
+            ));
+        }
+
+        let fixup = usa_offset + sector * 2;
+        buffer[sector_end - 2] = buffer[fixup];
+        buffer[sector_end - 1] = buffer[fixup + 1];
+    }
+
+    Ok(())
+}
+
+
+/// The layout of the log pages, from the restart area.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LogFileLayout {
+    /// The size of the two restart pages at the start of the file.
+    pub system_page_size: usize,
+    /// The size of the log record pages.
+    pub log_page_size: usize,
+    /// Where the log records start in a page.
+    pub page_data_offset: usize,
+    pub record_header_length: usize,
+    /// The number of bits of an LSN that are its sequence number, the rest are
+    /// its offset in the file. None if no restart page could be read.
+    pub seq_number_bits: Option<u32>,
+}
+
+impl Default for LogFileLayout {
+    fn default() -> Self {
+        LogFileLayout {
+            system_page_size: DEFAULT_PAGE_SIZE,
+            log_page_size: DEFAULT_PAGE_SIZE,
+            page_data_offset: DEFAULT_PAGE_DATA_OFFSET,
+            record_header_length: DEFAULT_RECORD_HEADER_LENGTH,
+            seq_number_bits: None,
+        }
+    }
+}
+
+impl LogFileLayout {
+    /// Read the layout from a restart page, with its fixups applied.
+    pub fn from_restart_page(page: &[u8]) -> Result<LogFileLayout, UsnError> {
+        if page.len() < 0x1A || &page[0..4] != RESTART_SIGNATURE {
+            return Err(UsnError::invalid_logfile("Restart page signature is not RSTR".to_string()));
+        }
+
+        let system_page_size = LittleEndian::read_u32(&page[0x10..0x14]) as usize;
+        let log_page_size = LittleEndian::read_u32(&page[0x14..0x18]) as usize;
+        let restart_area_offset = LittleEndian::read_u16(&page[0x18..0x1A]) as usize;
+
+        for page_size in [system_page_size, log_page_size].iter() {
+            if !page_size.is_power_of_two() || *page_size < SIZE_SECTOR {
+                return Err(UsnError::invalid_logfile(format!("Page size {} is not valid", page_size)));
+            }
+        }
+
+        let restart_area = match page.get(restart_area_offset..restart_area_offset + 0x28) {
+            Some(restart_area) => restart_area,
+            None => return Err(UsnError::invalid_logfile(
+                format!("Restart area offset {} is past the restart page", restart_area_offset)
+            ))
+        };
+
+        let seq_number_bits = LittleEndian::read_u32(&restart_area[0x10..0x14]);
+        let record_header_length = LittleEndian::read_u16(&restart_area[0x24..0x26]) as usize;
+        let page_data_offset = LittleEndian::read_u16(&restart_area[0x26..0x28]) as usize;
+
+        if !(3..64).contains(&seq_number_bits) {
+            return Err(UsnError::invalid_logfile(format!("Sequence number bits {} is not valid", seq_number_bits)));
+        }
+        if record_header_length < DEFAULT_RECORD_HEADER_LENGTH || page_data_offset >= log_page_size {
+            return Err(UsnError::invalid_logfile(
+                format!("Record header length {} or page data offset {} is not valid", record_header_length, page_data_offset)
+            ));
+        }
+
+        Ok(LogFileLayout {
+            system_page_size,
+            log_page_size,
+            page_data_offset,
+            record_header_length,
+            seq_number_bits: Some(seq_number_bits),
+        })
+    }
+
+    /// The offset in the file that an LSN refers to.
+    pub fn lsn_to_offset(&self, lsn: u64) -> Option<u64> {
+        self.seq_number_bits.map(|bits| (lsn << bits) >> (bits - 3))
+    }
+}
+
+
+/// LogFileParser recovers the USN records of an NTFS $LogFile. Writes to the
+/// $UsnJrnl:$J are logged with their data, so the log can still have recent
+/// records after the journal is deleted.
+pub struct LogFileParser<T: ReadSeek> {
+    inner_handle: T,
+    source: String,
+    handle_size: u64,
+    layout: LogFileLayout,
+}
+
+impl LogFileParser<File> {
+    pub fn from_path(filename: &str) -> Result<Self, UsnError> {
+        let file_handle = File::open(filename)?;
+        Self::from_read_seek(filename.to_string(), file_handle)
+    }
+}
+
+impl <T: ReadSeek> LogFileParser<T> {
+    /// Create a parser, reading the layout from the first restart page that is
+    /// valid. If neither is, the default layout is used.
+    pub fn from_read_seek(source: String, mut inner_handle: T) -> Result<Self, UsnError> {
+        let handle_size = inner_handle.seek(SeekFrom::End(0))?;
+
+        let mut layout = None;
+        let mut restart_offset = 0;
+        for _ in 0..2 {
+            match read_restart_page(&mut inner_handle, restart_offset) {
+                Ok(restart_layout) => {
+                    layout = Some(restart_layout);
+                    break;
+                },
+                Err(error) => {
+                    debug!("restart page at offset {} is not valid: {}", restart_offset, error);
+                    restart_offset += DEFAULT_PAGE_SIZE as u64;
+                }
+            }
+        }
+
+        let layout = layout.unwrap_or_else(|| {
+            warn!("{} has no valid restart page, using the default layout", source);
+            LogFileLayout::default()
+        });
+
+        Ok(LogFileParser {
+            inner_handle,
+            source,
+            handle_size,
+            layout,
+        })
+    }
+
+    pub fn layout(&self) -> LogFileLayout {
+        self.layout
+    }
+
+    /// Iterate the USN records in the redo data of the log records.
+    pub fn records(&mut self) -> IterLogFileRecords<'_, T> {
+        let page_offset = 2 * self.layout.system_page_size as u64;
+
+        IterLogFileRecords {
+            parser: self,
+            page_offset,
+            stream: LogStream::default(),
+            entries: VecDeque::new(),
+        }
+    }
+
+    fn read_page(&mut self, offset: u64) -> io::Result<Vec<u8>> {
+        let mut page = vec![0u8; self.layout.log_page_size];
+        self.inner_handle.seek(SeekFrom::Start(offset))?;
+        self.inner_handle.read_exact(&mut page)?;
+        Ok(page)
+    }
+}
+
+
+fn read_restart_page<T: ReadSeek>(handle: &mut T, offset: u64) -> Result<LogFileLayout, UsnError> {
+    let mut header = [0u8; 0x18];
+    handle.seek(SeekFrom::Start(offset))?;
+    handle.read_exact(&mut header)?;
+
+    let system_page_size = LittleEndian::read_u32(&header[0x10..0x14]) as usize;
+    if &header[0..4] != RESTART_SIGNATURE || !system_page_size.is_power_of_two() || system_page_size < SIZE_SECTOR {
+        return Err(UsnError::invalid_logfile("Restart page signature or size is not valid".to_string()));
+    }
+
+    let mut page = vec![0u8; system_page_size];
+    handle.seek(SeekFrom::Start(offset))?;
+    handle.read_exact(&mut page)?;
+    apply_fixups(&mut page)?;
+
+    LogFileLayout::from_restart_page(&page)
+}
+
+
+/// The data of consecutive log record pages, where log records can span pages.
+#[derive(Debug, Default)]
+struct LogStream {
+    data: Vec<u8>,
+    // The position in the data and file offset of each page's data
+    pages: Vec<(usize, u64)>,
+    // The position of the next log record
+    position: usize,
+}
+
+impl LogStream {
+    fn add_page(&mut self, file_offset: u64, page_data: &[u8]) {
+        // Drop the pages before the current position
+        let keep_from = self.pages.iter()
+            .rposition(|(position, _)| *position <= self.position)
+            .unwrap_or(0);
+        if keep_from > 0 {
+            let drained = self.pages[keep_from].0;
+            self.data.drain(..drained);
+            self.pages.drain(..keep_from);
+            for page in self.pages.iter_mut() {
+                page.0 -= drained;
+            }
+            self.position -= drained;
+        }
+
+        self.pages.push((self.data.len(), file_offset));
+        self.data.extend_from_slice(page_data);
+    }
+
+    fn clear(&mut self) {
+        *self = LogStream::default();
+    }
+
+    /// The file offset of a position in the data.
+    fn file_offset(&self, position: usize) -> u64 {
+        match self.pages.iter().rev().find(|(page_position, _)| *page_position <= position) {
+            Some((page_position, file_offset)) => file_offset + (position - page_position) as u64,
+            None => 0
+        }
+    }
+}
+
+
+/// Iterates the USN records of a $LogFile. The log record pages are read in file
+/// order, and log records are walked across pages. Where the log wraps or a page
+/// is not valid, the walk moves ahead 8 bytes at a time to the next valid log
+/// record header.
+pub struct IterLogFileRecords<'p, T: ReadSeek> {
+    parser: &'p mut LogFileParser<T>,
+    page_offset: u64,
+    stream: LogStream,
+    entries: VecDeque<UsnEntry>,
+}
+
+impl <'p, T: ReadSeek> IterLogFileRecords<'p, T> {
+    fn read_next_page(&mut self) -> bool {
+        let page_size = self.parser.layout.log_page_size as u64;
+        if self.page_offset + page_size > self.parser.handle_size {
+            return false;
+        }
+
+        let page_offset = self.page_offset;
+        self.page_offset += page_size;
+
+        let mut page = match self.parser.read_page(page_offset) {
+            Ok(page) => page,
+            Err(error) => {
+                error!("{}", error);
+                return false;
+            }
+        };
+
+        if &page[0..4] != RECORD_SIGNATURE {
+            self.stream.clear();
+            return true;
+        }
+        if let Err(error) = apply_fixups(&mut page) {
+            debug!("log page at offset {} is not valid: {}", page_offset, error);
+            self.stream.clear();
+            return true;
+        }
+
+        let page_data_offset = self.parser.layout.page_data_offset;
+        self.stream.add_page(page_offset + page_data_offset as u64, &page[page_data_offset..]);
+        self.walk_stream();
+
+        true
+    }
+
+    /// Walk the complete log records in the stream.
+    fn walk_stream(&mut self) {
+        let header_length = self.parser.layout.record_header_length;
+
+        while self.stream.position + header_length <= self.stream.data.len() {
+            let position = self.stream.position;
+
+            let (lsn, client_data_length) = match self.read_record_header(position) {
+                Some(header) => header,
+                None => {
+                    self.stream.position += 8;
+                    continue;
+                }
+            };
+
+            // Log records are 8 byte aligned
+            let record_length = (header_length + client_data_length + 7) & !7;
+            if position + record_length > self.stream.data.len() {
+                // The rest of the record is in the next page
+                break;
+            }
+
+            let client_data = position + header_length..position + header_length + client_data_length;
+            self.add_usn_records(lsn, client_data);
+            self.stream.position += record_length;
+        }
+    }
+
+    /// Get the LSN and client data length of the log record header at a position
+    /// if it is valid.
+    fn read_record_header(&self, position: usize) -> Option<(u64, usize)> {
+        let header = &self.stream.data[position..position + self.parser.layout.record_header_length];
+
+        let this_lsn = LittleEndian::read_u64(&header[0x00..0x08]);
+        let client_previous_lsn = LittleEndian::read_u64(&header[0x08..0x10]);
+        let client_undo_next_lsn = LittleEndian::read_u64(&header[0x10..0x18]);
+        let client_data_length = LittleEndian::read_u32(&header[0x18..0x1C]) as usize;
+        let record_type = LittleEndian::read_u32(&header[0x20..0x24]);
+        let flags = LittleEndian::read_u16(&header[0x28..0x2A]);
+
+        if this_lsn == 0 ||
+            client_previous_lsn >= this_lsn ||
+            client_undo_next_lsn >= this_lsn ||
+            (record_type != LOG_RECORD_CLIENT && record_type != LOG_RECORD_RESTART) ||
+            flags > 1 ||
+            client_data_length > MAX_CLIENT_DATA_LENGTH {
+            return None;
+        }
+
+        // The LSN has the record's offset in the file
+        if let Some(offset) = self.parser.layout.lsn_to_offset(this_lsn) {
+            if offset != self.stream.file_offset(position) {
+                return None;
+            }
+        }
+
+        Some((this_lsn, client_data_length))
+    }
+
+    /// Add the USN records in the redo data of a write to a non-resident
+    /// attribute.
+    fn add_usn_records(&mut self, lsn: u64, client_data: std::ops::Range<usize>) {
+        let data = &self.stream.data[client_data.clone()];
+        if data.len() < SIZE_NTFS_HEADER {
+            return;
+        }
+
+        let redo_operation = LittleEndian::read_u16(&data[0x00..0x02]);
+        let redo_offset = LittleEndian::read_u16(&data[0x04..0x06]) as usize;
+        let redo_length = LittleEndian::read_u16(&data[0x06..0x08]) as usize;
+        if redo_operation != UPDATE_NONRESIDENT_VALUE {
+            return;
+        }
+
+        let redo_data = match data.get(redo_offset..redo_offset + redo_length) {
+            Some(redo_data) => redo_data,
+            None => return
+        };
+
+        // The redo data can have several records back to back
+        let mut i = 0;
+        while i + 8 <= redo_data.len() {
+            let record_length = LittleEndian::read_u32(&redo_data[i..i + 4]) as usize;
+            let major_version = LittleEndian::read_u16(&redo_data[i + 4..i + 6]);
+            if record_length == 0 || i + record_length > redo_data.len() || !(2..=4).contains(&major_version) {
+                break;
+            }
+
+            let offset = self.stream.file_offset(client_data.start + redo_offset + i);
+            let entry_meta = EntryMeta::new(&self.parser.source, offset).with_lsn(lsn);
+
+            match UsnEntry::new(entry_meta, major_version, &redo_data[i..i + record_length]) {
+                Ok(entry) => self.entries.push_back(entry),
+                Err(error) => debug!("error at offset {} of LSN {}: {}", offset, lsn, error)
+            }
+
+            i += record_length;
+        }
+    }
+}
+
+impl <'p, T: ReadSeek> Iterator for IterLogFileRecords<'p, T> {
+    type Item = UsnEntry;
+
+    fn next(&mut self) -> Option<UsnEntry> {
+        loop {
+            if let Some(entry) = self.entries.pop_front() {
+                return Some(entry);
+            }
+
+            if !self.read_next_page() {
+                return None;
+            }
+        }
+    }
+}
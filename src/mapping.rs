@@ -2,28 +2,56 @@ use std::io;
 use std::fmt;
 use mft::MftParser;
 use crate::ReadSeek;
+use crate::flags;
+use crate::record::UsnEntry;
 use serde::Serialize;
 use lru::LruCache;
 use std::collections::HashMap;
+use chrono::{DateTime, Utc};
 use winstructs::ntfs::mft_reference::MftReference;
 use serde::ser::{Serializer, SerializeMap};
 
 
-#[derive(Serialize, Debug)]
+#[derive(Serialize, Debug, Clone, PartialEq)]
 pub struct EntryMapping {
     pub name: String,
     pub parent: MftReference,
 }
 
 
+/// Whether a delta is the state of a directory before or after its record.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+pub enum DeltaKind {
+    /// A delete or the old name of a rename. The mapping is the state before the record.
+    Before,
+    /// A create or the new name of a rename. The mapping is the state after the record.
+    After,
+}
+
+
+/// A directory's name and parent as recorded by the journal record with `usn`.
+#[derive(Serialize, Debug, Clone)]
+pub struct MappingDelta {
+    pub usn: u64,
+    pub timestamp: Option<DateTime<Utc>>,
+    pub kind: DeltaKind,
+    pub mapping: EntryMapping,
+}
+
+
 pub struct FolderMapping {
     pub mapping: HashMap<MftReference, EntryMapping>,
-    pub cache: LruCache<MftReference, String>
+    pub cache: LruCache<MftReference, String>,
+    /// Deltas per reference, sorted by USN. Used by `enumerate_path_at`.
+    pub deltas: HashMap<MftReference, Vec<MappingDelta>>,
+    // Deltas per reference that have a timestamp, sorted by timestamp then USN.
+    // Used by `enumerate_path_at_time`.
+    time_deltas: HashMap<MftReference, Vec<MappingDelta>>
 }
 
 impl fmt::Debug for FolderMapping {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "FolderMapping {{ mapping: {:?}, cache: LruCache }}", self.mapping)
+        write!(f, "FolderMapping {{ mapping: {:?}, cache: LruCache, deltas: {:?} }}", self.mapping, self.deltas)
     }
 }
 
@@ -34,7 +62,9 @@ impl FolderMapping {
 
         FolderMapping {
             mapping,
-            cache
+            cache,
+            deltas: HashMap::new(),
+            time_deltas: HashMap::new()
         }
     }
 
@@ -50,7 +80,9 @@ impl FolderMapping {
         let cache: LruCache<MftReference, String> = LruCache::new(100);
         let mut folder_mapping = FolderMapping {
             mapping,
-            cache
+            cache,
+            deltas: HashMap::new(),
+            time_deltas: HashMap::new()
         };
 
         folder_mapping.build_folder_mapping(
@@ -133,6 +165,151 @@ impl FolderMapping {
         );
     }
 
+    /// Add a delta for a reference. Deltas are kept sorted by USN, and those with
+    /// a timestamp are also kept sorted by timestamp.
+    pub fn add_delta(&mut self, entry_reference: MftReference, delta: MappingDelta) {
+        if delta.timestamp.is_some() {
+            let time_deltas = self.time_deltas.entry(entry_reference).or_default();
+            let index = time_deltas.partition_point(
+                |time_delta| (time_delta.timestamp, time_delta.usn) <= (delta.timestamp, delta.usn)
+            );
+            time_deltas.insert(index, delta.clone());
+        }
+
+        let deltas = self.deltas.entry(entry_reference).or_default();
+        let index = match deltas.binary_search_by_key(&delta.usn, |delta| delta.usn) {
+            Ok(index) => index,
+            Err(index) => index
+        };
+        deltas.insert(index, delta);
+    }
+
+    /// Add a delta if the entry is a directory create, delete or rename. Returns
+    /// true if a delta was added.
+    pub fn add_journal_entry(&mut self, entry: &UsnEntry) -> bool {
+        let record = &entry.record;
+        let reason = record.get_reason_code();
+
+        if !record.get_file_attributes().contains(flags::FileAttributes::FILE_ATTRIBUTE_DIRECTORY) {
+            return false;
+        }
+
+        // A record can be both a create and a delete, in which case the
+        // directory no longer exists after it.
+        let kind = if reason.intersects(flags::Reason::USN_REASON_FILE_DELETE | flags::Reason::USN_REASON_RENAME_OLD_NAME) {
+            DeltaKind::Before
+        } else if reason.intersects(flags::Reason::USN_REASON_FILE_CREATE | flags::Reason::USN_REASON_RENAME_NEW_NAME) {
+            DeltaKind::After
+        } else {
+            return false;
+        };
+
+        self.add_delta(
+            record.get_file_reference(),
+            MappingDelta {
+                usn: record.get_usn(),
+                timestamp: record.get_timestamp(),
+                kind,
+                mapping: EntryMapping {
+                    name: record.get_file_name(),
+                    parent: record.get_parent_reference()
+                }
+            }
+        );
+
+        true
+    }
+
+    /// The reasons of the records that `add_journal_entry` makes deltas for.
+    pub fn delta_reasons() -> flags::Reason {
+        flags::Reason::USN_REASON_FILE_CREATE |
+        flags::Reason::USN_REASON_FILE_DELETE |
+        flags::Reason::USN_REASON_RENAME_OLD_NAME |
+        flags::Reason::USN_REASON_RENAME_NEW_NAME
+    }
+
+    /// Select the delta that describes a reference at `point`. The deltas must be
+    /// sorted by `key`. The closest delta at or before `point` is used if it is an
+    /// After delta, otherwise the closest delta at or after `point` if it is a Before
+    /// delta. If neither, the closest of the two is the best known name.
+    fn select_delta<K, F>(deltas: &[MappingDelta], point: K, key: F) -> Option<&EntryMapping>
+        where K: PartialOrd, F: Fn(&MappingDelta) -> K
+    {
+        let previous = deltas[..deltas.partition_point(|delta| key(delta) <= point)].last();
+        let next = deltas.get(deltas.partition_point(|delta| key(delta) < point));
+
+        match (previous, next) {
+            (Some(delta), _) if delta.kind == DeltaKind::After => Some(&delta.mapping),
+            (_, Some(delta)) if delta.kind == DeltaKind::Before => Some(&delta.mapping),
+            (Some(delta), _) | (None, Some(delta)) => Some(&delta.mapping),
+            (None, None) => None
+        }
+    }
+
+    /// Get the mapping of a reference as it was at `usn`, falling back to the MFT.
+    pub fn get_mapping_at(&self, entry_reference: &MftReference, usn: u64) -> Option<&EntryMapping> {
+        self.deltas.get(entry_reference)
+            .and_then(|deltas| Self::select_delta(deltas, usn, |delta| delta.usn))
+            .or_else(|| self.mapping.get(entry_reference))
+    }
+
+    /// Get the mapping of a reference as it was at `timestamp`, falling back to the MFT.
+    pub fn get_mapping_at_time(&self, entry_reference: &MftReference, timestamp: DateTime<Utc>) -> Option<&EntryMapping> {
+        self.time_deltas.get(entry_reference)
+            .and_then(|deltas| Self::select_delta(deltas, Some(timestamp), |delta| delta.timestamp))
+            .or_else(|| self.mapping.get(entry_reference))
+    }
+
+    /// Enumerate the path of a reference as it was at `usn`. Unlike `enumerate_path`
+    /// this does not change the mapping, so records can be resolved in any order.
+    pub fn enumerate_path_at(&self, entry: u64, sequence: u16, usn: u64) -> String {
+        self.enumerate_path_with(
+            MftReference { entry, sequence },
+            |lookup_ref| self.get_mapping_at(lookup_ref, usn)
+        )
+    }
+
+    /// Enumerate the path of a reference as it was at `timestamp`.
+    pub fn enumerate_path_at_time(&self, entry: u64, sequence: u16, timestamp: DateTime<Utc>) -> String {
+        self.enumerate_path_with(
+            MftReference { entry, sequence },
+            |lookup_ref| self.get_mapping_at_time(lookup_ref, timestamp)
+        )
+    }
+
+    fn enumerate_path_with<'a, F>(&'a self, mut lookup_ref: MftReference, get_mapping: F) -> String
+        where F: Fn(&MftReference) -> Option<&'a EntryMapping>
+    {
+        let mut path_queue: Vec<String> = Vec::new();
+
+        loop {
+            if lookup_ref.entry == 5 {
+                path_queue.push("[root]".to_string());
+                break;
+            }
+
+            // A corrupt mapping can loop, so limit the depth.
+            if path_queue.len() > 255 {
+                path_queue.push("[<loop>]".to_string());
+                break;
+            }
+
+            match get_mapping(&lookup_ref) {
+                Some(folder_map) => {
+                    path_queue.push(folder_map.name.clone());
+                    lookup_ref = folder_map.parent;
+                },
+                None => {
+                    path_queue.push("[<unknown>]".to_string());
+                    break;
+                }
+            }
+        }
+
+        path_queue.reverse();
+        path_queue.join("/")
+    }
+
     fn enumerate_path_queue(&self, lookup_ref: &MftReference, path_queue: &mut Vec<String>) {
         if lookup_ref.entry != 5 {
             match self.mapping.get(&lookup_ref) {
@@ -0,0 +1,124 @@
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+use chrono::{DateTime, Utc};
+use crate::record::UsnEntry;
+
+
+/// The order of merged records.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MergeOrder {
+    Usn,
+    /// Timestamps in a $J are not always increasing, such as after the clock is
+    /// changed, so merging journals by timestamp is only approximately time ordered.
+    Timestamp,
+}
+
+impl MergeOrder {
+    pub fn from_name(name: &str) -> Option<MergeOrder> {
+        match name.to_lowercase().as_str() {
+            "usn" => Some(MergeOrder::Usn),
+            "timestamp" => Some(MergeOrder::Timestamp),
+            _ => None
+        }
+    }
+
+    fn get_key(self, entry: &UsnEntry) -> MergeKey {
+        let record = &entry.record;
+
+        match self {
+            MergeOrder::Usn => MergeKey::Usn(record.get_usn()),
+            MergeOrder::Timestamp => MergeKey::Timestamp(record.get_timestamp(), record.get_usn()),
+        }
+    }
+}
+
+
+/// The sort key of a record. Records without a timestamp (version 4) sort first
+/// when merging by timestamp.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum MergeKey {
+    Usn(u64),
+    Timestamp(Option<DateTime<Utc>>, u64),
+}
+
+
+/// The next record of an input. Ties are broken by the input's position so that
+/// the merge is stable.
+struct HeapItem {
+    key: MergeKey,
+    input: usize,
+    entry: UsnEntry,
+}
+
+impl PartialEq for HeapItem {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for HeapItem {}
+
+impl PartialOrd for HeapItem {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for HeapItem {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.key, self.input).cmp(&(other.key, other.input))
+    }
+}
+
+
+/// MergedRecords k-way merges the records of several inputs, such as the
+/// `UsnParser::records()` of the $J of each shadow copy, into one stream ordered
+/// by USN or timestamp. Each input must already be in that order, which a $J
+/// parsed from start to end is by USN but only approximately by timestamp. Records
+/// out of order in an input are written when they are reached, so they stay out
+/// of order in the stream. Only the next record of each input is
+/// held in memory. Each record keeps the source and offset it was found at in
+/// its `meta`.
+pub struct MergedRecords<I: Iterator<Item = UsnEntry>> {
+    inputs: Vec<I>,
+    order: MergeOrder,
+    heap: BinaryHeap<Reverse<HeapItem>>,
+}
+
+impl<I: Iterator<Item = UsnEntry>> MergedRecords<I> {
+    pub fn new(inputs: Vec<I>, order: MergeOrder) -> Self {
+        let mut merged = MergedRecords {
+            heap: BinaryHeap::with_capacity(inputs.len()),
+            inputs,
+            order
+        };
+
+        for input in 0..merged.inputs.len() {
+            merged.fill(input);
+        }
+
+        merged
+    }
+
+    /// Push the next record of an input onto the heap.
+    fn fill(&mut self, input: usize) {
+        if let Some(entry) = self.inputs[input].next() {
+            self.heap.push(Reverse(HeapItem {
+                key: self.order.get_key(&entry),
+                input,
+                entry
+            }));
+        }
+    }
+}
+
+impl<I: Iterator<Item = UsnEntry>> Iterator for MergedRecords<I> {
+    type Item = UsnEntry;
+
+    fn next(&mut self) -> Option<UsnEntry> {
+        let Reverse(item) = self.heap.pop()?;
+        self.fill(item.input);
+
+        Some(item.entry)
+    }
+}
@@ -0,0 +1,312 @@
+use std::io::Write;
+use serde_json::Value;
+use chrono::SecondsFormat;
+use crate::flags;
+use crate::flags::FlagFormat;
+use crate::error::UsnError;
+use crate::record::UsnEntry;
+use crate::dedup::SightingTracker;
+
+
+/// The columns written by the CsvWriter, in order.
+pub const CSV_COLUMNS: &[&str] = &[
+    "meta__source",
+    "meta__offset",
+    "major_version",
+    "usn",
+    "timestamp",
+    "file_reference_entry",
+    "file_reference_sequence",
+    "parent_reference_entry",
+    "parent_reference_sequence",
+    "file_name",
+    "full_name",
+    "reason",
+    "file_attributes",
+    "source_info",
+];
+
+
+/// The supported output formats.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OutputFormat {
+    Jsonl,
+    Csv,
+    Tsv,
+    Bodyfile,
+}
+
+impl OutputFormat {
+    pub fn from_name(name: &str) -> Option<OutputFormat> {
+        match name.to_lowercase().as_str() {
+            "jsonl" => Some(OutputFormat::Jsonl),
+            "csv" => Some(OutputFormat::Csv),
+            "tsv" => Some(OutputFormat::Tsv),
+            "bodyfile" => Some(OutputFormat::Bodyfile),
+            _ => None
+        }
+    }
+
+    /// Create a writer for this format that writes to `writer`. The flag format
+    /// is only used by the JSONL format.
+    pub fn get_writer<'w, W: Write + 'w>(self, writer: W, flag_format: FlagFormat) -> Box<dyn OutputWriter + 'w> {
+        match self {
+            OutputFormat::Jsonl => Box::new(JsonlWriter::new(writer).with_flag_format(flag_format)),
+            OutputFormat::Csv => Box::new(CsvWriter::new(writer, b',')),
+            OutputFormat::Tsv => Box::new(CsvWriter::new(writer, b'\t')),
+            OutputFormat::Bodyfile => Box::new(BodyfileWriter::new(writer)),
+        }
+    }
+}
+
+
+/// An OutputWriter serializes UsnEntrys to an output stream.
+pub trait OutputWriter {
+    /// Write an entry. The `additional` value is an object of extra fields, such
+    /// as `full_name`, which are written if the format supports them.
+    fn write_entry(&mut self, entry: &UsnEntry, additional: Option<Value>) -> Result<(), UsnError>;
+
+    /// Flush any buffered output.
+    fn flush(&mut self) -> Result<(), UsnError>;
+}
+
+
+/// Get the `full_name` (or the listener's `full_path`) from the additional fields.
+fn get_full_name(additional: &Option<Value>) -> Option<String> {
+    additional.as_ref()
+        .and_then(|value| value.get("full_name").or_else(|| value.get("full_path")))
+        .and_then(|value| value.as_str())
+        .map(|full_name| full_name.to_string())
+}
+
+
+/// Writes each entry as a line of JSON with the additional fields merged in.
+pub struct JsonlWriter<W: Write> {
+    writer: W,
+    flag_format: FlagFormat,
+}
+
+impl<W: Write> JsonlWriter<W> {
+    pub fn new(writer: W) -> Self {
+        JsonlWriter {
+            writer,
+            flag_format: FlagFormat::Text
+        }
+    }
+
+    /// Set how the reason, source info and file attribute flags are written.
+    pub fn with_flag_format(mut self, flag_format: FlagFormat) -> Self {
+        self.flag_format = flag_format;
+        self
+    }
+}
+
+impl<W: Write> OutputWriter for JsonlWriter<W> {
+    fn write_entry(&mut self, entry: &UsnEntry, additional: Option<Value>) -> Result<(), UsnError> {
+        let mut entry_value = entry.to_json_value()?;
+
+        // Flags serialize as text, so only other formats need replacing. V4
+        // records have no file attributes.
+        if self.flag_format != FlagFormat::Text {
+            if let Some(value_map) = entry_value.as_object_mut() {
+                let record = &entry.record;
+                value_map.insert("reason".to_string(), record.get_reason_code().to_json_value(self.flag_format));
+                value_map.insert("source_info".to_string(), record.get_source_info().to_json_value(self.flag_format));
+                if value_map.contains_key("file_attributes") {
+                    value_map.insert("file_attributes".to_string(), record.get_file_attributes().to_json_value(self.flag_format));
+                }
+            }
+        }
+
+        if let (Some(value_map), Some(Value::Object(additional_map))) = (entry_value.as_object_mut(), additional) {
+            value_map.extend(additional_map);
+        }
+
+        writeln!(
+            self.writer, "{}",
+            serde_json::to_string(&entry_value)?
+        )?;
+
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), UsnError> {
+        Ok(self.writer.flush()?)
+    }
+}
+
+
+/// Writes each entry as a row of delimited values with the fixed columns of
+/// `CSV_COLUMNS`. Flags are written as their names joined by `|`. Of the
+/// additional fields, only `full_name` (or `full_path`) is written.
+pub struct CsvWriter<W: Write> {
+    writer: csv::Writer<W>,
+    header_written: bool,
+}
+
+impl<W: Write> CsvWriter<W> {
+    pub fn new(writer: W, delimiter: u8) -> Self {
+        let writer = csv::WriterBuilder::new()
+            .delimiter(delimiter)
+            .from_writer(writer);
+
+        CsvWriter {
+            writer,
+            header_written: false
+        }
+    }
+
+    fn write_header(&mut self) -> Result<(), UsnError> {
+        if !self.header_written {
+            self.writer.write_record(CSV_COLUMNS)?;
+            self.header_written = true;
+        }
+        Ok(())
+    }
+}
+
+impl<W: Write> OutputWriter for CsvWriter<W> {
+    fn write_entry(&mut self, entry: &UsnEntry, additional: Option<Value>) -> Result<(), UsnError> {
+        self.write_header()?;
+
+        let record = &entry.record;
+        let file_reference = record.get_file_reference();
+        let parent_reference = record.get_parent_reference();
+
+        let timestamp = match record.get_timestamp() {
+            Some(timestamp) => timestamp.to_rfc3339_opts(SecondsFormat::Micros, true),
+            None => String::new()
+        };
+
+        let full_name = get_full_name(&additional).unwrap_or_default();
+
+        self.writer.write_record(&[
+            entry.meta.source.clone(),
+            entry.meta.offset.to_string(),
+            record.get_major_version().to_string(),
+            record.get_usn().to_string(),
+            timestamp,
+            file_reference.entry.to_string(),
+            file_reference.sequence.to_string(),
+            parent_reference.entry.to_string(),
+            parent_reference.sequence.to_string(),
+            record.get_file_name(),
+            full_name,
+            record.get_reason_code().display_names().join("|"),
+            record.get_file_attributes().display_names().join("|"),
+            record.get_source_info().display_names().join("|"),
+        ])?;
+
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), UsnError> {
+        self.write_header()?;
+        Ok(self.writer.flush()?)
+    }
+}
+
+
+/// Writes each entry as a line of a Sleuth Kit 3.x bodyfile so that it can be
+/// merged with `fls` output and processed by `mactime`.
+/// (https://wiki.sleuthkit.org/index.php?title=Body_file)
+///
+/// The name is the `full_name` if given, otherwise the file name, followed by the
+/// reason flags. The inode is written as ENTRY-SEQUENCE and all four time fields
+/// are set to the record's timestamp.
+pub struct BodyfileWriter<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> BodyfileWriter<W> {
+    pub fn new(writer: W) -> Self {
+        BodyfileWriter {
+            writer
+        }
+    }
+}
+
+impl<W: Write> OutputWriter for BodyfileWriter<W> {
+    fn write_entry(&mut self, entry: &UsnEntry, additional: Option<Value>) -> Result<(), UsnError> {
+        let record = &entry.record;
+        let file_reference = record.get_file_reference();
+
+        let name = get_full_name(&additional)
+            .unwrap_or_else(|| record.get_file_name());
+        let reasons = record.get_reason_code().display_short_names().join(" ");
+
+        let mode = if record.get_file_attributes().contains(flags::FileAttributes::FILE_ATTRIBUTE_DIRECTORY) {
+            "d/d"
+        } else {
+            "r/r"
+        };
+
+        let timestamp = match record.get_timestamp() {
+            Some(timestamp) => timestamp.timestamp(),
+            None => 0
+        };
+
+        // MD5|name|inode|mode_as_string|UID|GID|size|atime|mtime|ctime|crtime
+        writeln!(
+            self.writer,
+            "0|{} ($UsnJrnl: {})|{}-{}|{}|0|0|0|{}|{}|{}|{}",
+            name.replace('|', "_"),
+            reasons,
+            file_reference.entry,
+            file_reference.sequence,
+            mode,
+            timestamp, timestamp, timestamp, timestamp
+        )?;
+
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), UsnError> {
+        Ok(self.writer.flush()?)
+    }
+}
+
+
+/// Deduplicates the entries written to it, writing each entry to the inner writer
+/// the first time it is seen. Only the keys of the entries are kept, so this works
+/// across several sources. When flushed, every entry that was found more than
+/// once is written to the sightings writer as a line of JSON listing every source
+/// and offset it was found at, keyed by the `meta__source` and `meta__offset` of
+/// the entry that was written.
+pub struct DedupWriter<'w> {
+    writer: Box<dyn OutputWriter + 'w>,
+    sightings_writer: Box<dyn Write + 'w>,
+    tracker: SightingTracker,
+}
+
+impl<'w> DedupWriter<'w> {
+    pub fn new(writer: Box<dyn OutputWriter + 'w>, sightings_writer: Box<dyn Write + 'w>) -> Self {
+        DedupWriter {
+            writer,
+            sightings_writer,
+            tracker: SightingTracker::new()
+        }
+    }
+}
+
+impl<'w> OutputWriter for DedupWriter<'w> {
+    fn write_entry(&mut self, entry: &UsnEntry, additional: Option<Value>) -> Result<(), UsnError> {
+        if self.tracker.add(entry) {
+            self.writer.write_entry(entry, additional)?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), UsnError> {
+        self.writer.flush()?;
+
+        for record_sightings in self.tracker.take_repeated() {
+            writeln!(
+                self.sightings_writer, "{}",
+                serde_json::to_string(&record_sightings)?
+            )?;
+        }
+
+        Ok(self.sightings_writer.flush()?)
+    }
+}
@@ -13,7 +13,7 @@ use crate::error::UsnError;
 use crate::utils::u64_to_datetime;
 
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct UsnEntry {
     pub meta: EntryMeta,
     pub record: UsnRecord,
@@ -49,15 +49,33 @@ pub struct EntryMeta {
     pub source: String,
     #[serde(rename(serialize = "meta__offset"))]
     pub offset: u64,
+    /// The LSN of the $LogFile record the UsnRecord was found in.
+    #[serde(rename(serialize = "meta__lsn"), skip_serializing_if = "Option::is_none")]
+    pub lsn: Option<u64>,
+    /// The identifier of the shadow copy the UsnRecord was found in.
+    #[serde(rename(serialize = "meta__snapshot"), skip_serializing_if = "Option::is_none")]
+    pub snapshot: Option<String>,
 }
 impl EntryMeta {
     pub fn new(source: &str, offset: u64) -> Self {
         EntryMeta {
             source: source.to_string(),
             offset: offset,
+            lsn: None,
+            snapshot: None,
         }
     }
 
+    pub fn with_lsn(mut self, lsn: u64) -> Self {
+        self.lsn = Some(lsn);
+        self
+    }
+
+    pub fn with_snapshot(mut self, snapshot: &str) -> Self {
+        self.snapshot = Some(snapshot.to_string());
+        self
+    }
+
     pub fn to_json_value(&self) -> Result<Value, UsnError> {
         Ok(serde_json::to_value(&self)?)
     }
@@ -65,11 +83,12 @@ impl EntryMeta {
 
 
 /// UsnRecord represents the multiple possible versions of the UsnRecord
-#[derive(Serialize, Debug)]
+#[derive(Serialize, Debug, Clone)]
 #[serde(untagged)]
 pub enum UsnRecord {
     V2(UsnRecordV2),
-    V3(UsnRecordV3)
+    V3(UsnRecordV3),
+    V4(UsnRecordV4)
 }
 impl UsnRecord {
     pub fn new<R: Read>(version: u16, mut reader: R)-> Result<UsnRecord, UsnError> {
@@ -85,6 +104,12 @@ impl UsnRecord {
             )?;
             Ok(UsnRecord::V3(usn_record_v3))
         }
+        else if version == 4 {
+            let usn_record_v4 = UsnRecordV4::new(
+                &mut reader
+            )?;
+            Ok(UsnRecord::V4(usn_record_v4))
+        }
         else {
             Err(UsnError::unsupported_usn_version(
                 format!("Unsupported USN version {}", version)
@@ -92,24 +117,54 @@ impl UsnRecord {
         }
     }
 
+    pub fn get_major_version(&self) -> u16 {
+        match self {
+            UsnRecord::V2(record) => record.major_version,
+            UsnRecord::V3(record) => record.major_version,
+            UsnRecord::V4(record) => record.major_version,
+        }
+    }
+
     pub fn get_usn(&self) -> u64 {
         match self {
             UsnRecord::V2(ref record) => record.usn.clone(),
             UsnRecord::V3(ref record) => record.usn.clone(),
+            UsnRecord::V4(ref record) => record.usn,
+        }
+    }
+
+    /// V4 records do not contain a timestamp, so None is returned for them.
+    pub fn get_timestamp(&self) -> Option<DateTime<Utc>> {
+        match self {
+            UsnRecord::V2(record) => Some(record.timestamp),
+            UsnRecord::V3(record) => Some(record.timestamp),
+            UsnRecord::V4(_) => None,
         }
     }
 
+    pub fn get_source_info(&self) -> flags::SourceInfo {
+        match self {
+            UsnRecord::V2(record) => record.source_info,
+            UsnRecord::V3(record) => record.source_info,
+            UsnRecord::V4(record) => record.source_info,
+        }
+    }
+
+    /// V4 records do not contain a file name, so an empty string is returned for them.
     pub fn get_file_name(&self) -> String {
         match self {
             UsnRecord::V2(ref record) => record.file_name.clone(),
             UsnRecord::V3(ref record) => record.file_name.clone(),
+            UsnRecord::V4(_) => String::new(),
         }
     }
 
+    /// V4 records do not contain file attributes, so empty flags are returned for them.
     pub fn get_file_attributes(&self) -> flags::FileAttributes {
         match self {
             UsnRecord::V2(record) => record.file_attributes,
             UsnRecord::V3(record) => record.file_attributes,
+            UsnRecord::V4(_) => flags::FileAttributes::empty(),
         }
     }
 
@@ -117,6 +172,15 @@ impl UsnRecord {
         match self {
             UsnRecord::V2(record) => record.reason,
             UsnRecord::V3(record) => record.reason,
+            UsnRecord::V4(record) => record.reason,
+        }
+    }
+
+    pub fn get_unknown_bits(&self) -> UnknownBits {
+        match self {
+            UsnRecord::V2(record) => record.unknown_bits,
+            UsnRecord::V3(record) => record.unknown_bits,
+            UsnRecord::V4(record) => record.unknown_bits,
         }
     }
 
@@ -124,6 +188,7 @@ impl UsnRecord {
         match self {
             UsnRecord::V2(record) => record.file_reference,
             UsnRecord::V3(record) => record.file_reference.as_mft_reference(),
+            UsnRecord::V4(record) => record.file_reference.as_mft_reference(),
         }
     }
 
@@ -131,6 +196,7 @@ impl UsnRecord {
         match self {
             UsnRecord::V2(record) => record.parent_reference,
             UsnRecord::V3(record) => record.parent_reference.as_mft_reference(),
+            UsnRecord::V4(record) => record.parent_reference.as_mft_reference(),
         }
     }
 
@@ -167,10 +233,33 @@ impl UsnRecord {
 }
 
 
+/// The bits of a record's flags that are not defined flags, such as flags added
+/// by a newer version of Windows. Only serialized if any are set.
+#[derive(Serialize, Debug, Clone, Copy, Default, PartialEq)]
+pub struct UnknownBits {
+    pub reason: u32,
+    pub source_info: u32,
+    pub file_attributes: u32,
+}
+impl UnknownBits {
+    pub fn new(reason: flags::Reason, source_info: flags::SourceInfo, file_attributes: flags::FileAttributes) -> Self {
+        UnknownBits {
+            reason: reason.unknown_bits(),
+            source_info: source_info.unknown_bits(),
+            file_attributes: file_attributes.unknown_bits(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == UnknownBits::default()
+    }
+}
+
+
 /// Represents a USN_RECORD_V2 structure
 /// https://docs.microsoft.com/en-us/windows/win32/api/winioctl/ns-winioctl-usn_record_v2
 ///
-#[derive(Serialize, Debug)]
+#[derive(Serialize, Debug, Clone)]
 pub struct UsnRecordV2 {
     pub record_length: u32,
     pub major_version: u16,
@@ -180,12 +269,20 @@ pub struct UsnRecordV2 {
     pub usn: u64,
     pub timestamp: DateTime<Utc>,
     pub reason: flags::Reason,
+    #[serde(skip)]
+    pub raw_reason: u32,
     pub source_info: flags::SourceInfo,
+    #[serde(skip)]
+    pub raw_source_info: u32,
     pub security_id: u32,
     pub file_attributes: flags::FileAttributes,
+    #[serde(skip)]
+    pub raw_file_attributes: u32,
     pub file_name_length: u16,
     pub file_name_offset: u16,
-    pub file_name: String
+    pub file_name: String,
+    #[serde(skip_serializing_if = "UnknownBits::is_empty")]
+    pub unknown_bits: UnknownBits
 }
 
 impl UsnRecordV2 {
@@ -232,10 +329,14 @@ impl UsnRecordV2 {
         let timestamp = u64_to_datetime(
             buffer.read_u64::<LittleEndian>()?
         );
-        let reason = flags::Reason::from_bits_truncate(buffer.read_u32::<LittleEndian>()?);
-        let source_info = flags::SourceInfo::from_bits_truncate(buffer.read_u32::<LittleEndian>()?);
+        let raw_reason = buffer.read_u32::<LittleEndian>()?;
+        let raw_source_info = buffer.read_u32::<LittleEndian>()?;
         let security_id = buffer.read_u32::<LittleEndian>()?;
-        let file_attributes = flags::FileAttributes::from_bits_truncate(buffer.read_u32::<LittleEndian>()?);
+        let raw_file_attributes = buffer.read_u32::<LittleEndian>()?;
+
+        let reason = flags::Reason::from_bits_retain(raw_reason);
+        let source_info = flags::SourceInfo::from_bits_retain(raw_source_info);
+        let file_attributes = flags::FileAttributes::from_bits_retain(raw_file_attributes);
         let file_name_length = buffer.read_u16::<LittleEndian>()?;
         let file_name_offset = buffer.read_u16::<LittleEndian>()?;
 
@@ -265,12 +366,16 @@ impl UsnRecordV2 {
                 usn,
                 timestamp,
                 reason,
+                raw_reason,
                 source_info,
+                raw_source_info,
                 security_id,
                 file_attributes,
+                raw_file_attributes,
                 file_name_length,
                 file_name_offset,
-                file_name
+                file_name,
+                unknown_bits: UnknownBits::new(reason, source_info, file_attributes)
             }
         )
     }
@@ -279,7 +384,7 @@ impl UsnRecordV2 {
 
 /// Represents a 128 bit file reference
 ///
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Ntfs128Reference(pub u128);
 
 impl Ntfs128Reference {
@@ -313,7 +418,7 @@ impl ser::Serialize for Ntfs128Reference {
 /// Represents a USN_RECORD_V3 structure
 /// https://docs.microsoft.com/en-us/windows/win32/api/winioctl/ns-winioctl-usn_record_v3
 ///
-#[derive(Serialize, Debug)]
+#[derive(Serialize, Debug, Clone)]
 pub struct UsnRecordV3 {
     pub record_length: u32,
     pub major_version: u16,
@@ -323,12 +428,20 @@ pub struct UsnRecordV3 {
     pub usn: u64,
     pub timestamp: DateTime<Utc>,
     pub reason: flags::Reason,
+    #[serde(skip)]
+    pub raw_reason: u32,
     pub source_info: flags::SourceInfo,
+    #[serde(skip)]
+    pub raw_source_info: u32,
     pub security_id: u32,
     pub file_attributes: flags::FileAttributes,
+    #[serde(skip)]
+    pub raw_file_attributes: u32,
     pub file_name_length: u16,
     pub file_name_offset: u16,
-    pub file_name: String
+    pub file_name: String,
+    #[serde(skip_serializing_if = "UnknownBits::is_empty")]
+    pub unknown_bits: UnknownBits
 }
 impl UsnRecordV3 {
     pub fn new<T: Read>(mut buffer: T) -> Result<UsnRecordV3, UsnError> {
@@ -379,10 +492,14 @@ impl UsnRecordV3 {
         let timestamp = u64_to_datetime(
             buffer.read_u64::<LittleEndian>()?
         );
-        let reason = flags::Reason::from_bits_truncate(buffer.read_u32::<LittleEndian>()?);
-        let source_info = flags::SourceInfo::from_bits_truncate(buffer.read_u32::<LittleEndian>()?);
+        let raw_reason = buffer.read_u32::<LittleEndian>()?;
+        let raw_source_info = buffer.read_u32::<LittleEndian>()?;
         let security_id = buffer.read_u32::<LittleEndian>()?;
-        let file_attributes = flags::FileAttributes::from_bits_truncate(buffer.read_u32::<LittleEndian>()?);
+        let raw_file_attributes = buffer.read_u32::<LittleEndian>()?;
+
+        let reason = flags::Reason::from_bits_retain(raw_reason);
+        let source_info = flags::SourceInfo::from_bits_retain(raw_source_info);
+        let file_attributes = flags::FileAttributes::from_bits_retain(raw_file_attributes);
         let file_name_length = buffer.read_u16::<LittleEndian>()?;
         let file_name_offset = buffer.read_u16::<LittleEndian>()?;
 
@@ -412,13 +529,164 @@ impl UsnRecordV3 {
                 usn,
                 timestamp,
                 reason,
+                raw_reason,
                 source_info,
+                raw_source_info,
                 security_id,
                 file_attributes,
+                raw_file_attributes,
                 file_name_length,
                 file_name_offset,
-                file_name
+                file_name,
+                unknown_bits: UnknownBits::new(reason, source_info, file_attributes)
+            }
+        )
+    }
+}
+
+
+/// Represents a USN_RECORD_EXTENT structure
+/// https://docs.microsoft.com/en-us/windows/win32/api/winioctl/ns-winioctl-usn_record_extent
+///
+#[derive(Serialize, Debug, Clone)]
+pub struct UsnRecordExtent {
+    pub offset: i64,
+    pub length: i64,
+}
+impl UsnRecordExtent {
+    pub fn new<T: Read>(mut buffer: T) -> Result<UsnRecordExtent, UsnError> {
+        let offset = buffer.read_i64::<LittleEndian>()?;
+        let length = buffer.read_i64::<LittleEndian>()?;
+
+        Ok(
+            UsnRecordExtent {
+                offset,
+                length
             }
         )
     }
-}
\ No newline at end of file
+}
+
+/// Represents a USN_RECORD_V4 structure. These records are written when range
+/// tracking is enabled on a volume and describe the modified ranges of a file.
+/// https://docs.microsoft.com/en-us/windows/win32/api/winioctl/ns-winioctl-usn_record_v4
+///
+#[derive(Serialize, Debug, Clone)]
+pub struct UsnRecordV4 {
+    pub record_length: u32,
+    pub major_version: u16,
+    pub minor_version: u16,
+    pub file_reference: Ntfs128Reference,
+    pub parent_reference: Ntfs128Reference,
+    pub usn: u64,
+    pub reason: flags::Reason,
+    #[serde(skip)]
+    pub raw_reason: u32,
+    pub source_info: flags::SourceInfo,
+    #[serde(skip)]
+    pub raw_source_info: u32,
+    pub remaining_extents: u32,
+    pub number_of_extents: u16,
+    pub extent_size: u16,
+    pub extents: Vec<UsnRecordExtent>,
+    #[serde(skip_serializing_if = "UnknownBits::is_empty")]
+    pub unknown_bits: UnknownBits
+}
+impl UsnRecordV4 {
+    pub fn new<T: Read>(mut buffer: T) -> Result<UsnRecordV4, UsnError> {
+        let record_length = buffer.read_u32::<LittleEndian>()?;
+
+        // Do some length checks
+        if record_length == 0 {
+            return Err(
+                UsnError::invalid_record(
+                    "Record length is 0.".to_string()
+                )
+            );
+        }
+        if record_length > 1024 {
+            return Err(
+                UsnError::invalid_record(
+                    "Record length is over 1024.".to_string()
+                )
+            );
+        }
+
+        let major_version = buffer.read_u16::<LittleEndian>()?;
+        if major_version != 4 {
+            return Err(
+                UsnError::invalid_record(
+                    "Major version is not 4".to_string()
+                )
+            );
+        }
+
+        let minor_version = buffer.read_u16::<LittleEndian>()?;
+        if minor_version != 0 {
+            return Err(
+                UsnError::invalid_record(
+                    "Minor version is not 0".to_string()
+                )
+            );
+        }
+
+        let file_reference = Ntfs128Reference(
+            buffer.read_u128::<LittleEndian>()?
+        );
+        let parent_reference = Ntfs128Reference(
+            buffer.read_u128::<LittleEndian>()?
+        );
+
+        let usn = buffer.read_u64::<LittleEndian>()?;
+        let raw_reason = buffer.read_u32::<LittleEndian>()?;
+        let raw_source_info = buffer.read_u32::<LittleEndian>()?;
+        let reason = flags::Reason::from_bits_retain(raw_reason);
+        let source_info = flags::SourceInfo::from_bits_retain(raw_source_info);
+        let remaining_extents = buffer.read_u32::<LittleEndian>()?;
+        let number_of_extents = buffer.read_u16::<LittleEndian>()?;
+        let extent_size = buffer.read_u16::<LittleEndian>()?;
+
+        // The extents must be USN_RECORD_EXTENT structures and fit in the record
+        if extent_size != 16 {
+            return Err(
+                UsnError::invalid_record(
+                    format!("Extent size {} is not 16", extent_size)
+                )
+            );
+        }
+        if 64 + u32::from(number_of_extents) * u32::from(extent_size) > record_length {
+            return Err(
+                UsnError::invalid_record(
+                    format!("{} extents do not fit in record length {}", number_of_extents, record_length)
+                )
+            );
+        }
+
+        let mut extents = Vec::with_capacity(number_of_extents as usize);
+        for _ in 0..number_of_extents {
+            extents.push(
+                UsnRecordExtent::new(&mut buffer)?
+            );
+        }
+
+        Ok(
+            UsnRecordV4 {
+                record_length,
+                major_version,
+                minor_version,
+                file_reference,
+                parent_reference,
+                usn,
+                reason,
+                raw_reason,
+                source_info,
+                raw_source_info,
+                remaining_extents,
+                number_of_extents,
+                extent_size,
+                extents,
+                unknown_bits: UnknownBits::new(reason, source_info, flags::FileAttributes::empty())
+            }
+        )
+    }
+}
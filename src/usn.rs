@@ -2,14 +2,22 @@
 use rayon;
 use std::io;
 use regex::bytes;
-use std::cmp::max;
+use std::cmp::{self, max};
 use std::fs::File;
 use std::io::SeekFrom;
 #[cfg(feature = "multithreading")]
 use rayon::prelude::*;
-use std::collections::VecDeque;
+use std::ops::Range;
+use std::collections::{BTreeMap, VecDeque};
+use serde::Serialize;
 use byteorder::{ByteOrder, LittleEndian};
 use crate::ReadSeek;
+use crate::utils;
+use crate::error::{ErrorKind, UsnError};
+use crate::filter::RecordFilter;
+use crate::image::ntfs::{NtfsStream, NtfsVolume};
+use crate::image::open_image;
+use crate::image::vss::{VssSnapshot, VssVolume};
 use crate::record::{EntryMeta, UsnEntry};
 
 
@@ -20,22 +28,168 @@ const SIZE_CHUNK: usize = 17408;
 // It has been noticed that generally usn records are paged in 4096 byte pages. I have not
 // observed usn records overlaping the 4096 offset and are zero padded to the 4096 mark.
 const SIZE_SEARCH: usize = 16384;
+// Records are written in pages of this size
+const SIZE_PAGE: u64 = 4096;
 
 lazy_static! {
     static ref RE_USN: bytes::Regex = bytes::Regex::new(
-        "(?-u)..\x00\x00(\x02|\x03)\x00\x00\x00"
+        "(?-u)..\x00\x00(\x02|\x03|\x04)\x00\x00\x00"
     ).expect("Regex Error");
 }
 
 
+/// How records are found in the data.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ParseMode {
+    /// Search for anything that looks like a record header. For unallocated or
+    /// carved data.
+    Carve,
+    /// Walk the records of each page by their record length. Pages that can not
+    /// be walked are carved.
+    Structured,
+}
+
+impl ParseMode {
+    pub fn from_name(name: &str) -> Option<ParseMode> {
+        match name.to_lowercase().as_str() {
+            "carve" => Some(ParseMode::Carve),
+            "structured" => Some(ParseMode::Structured),
+            _ => None
+        }
+    }
+}
+
+
+/// The number of records found by each parse mode.
+#[derive(Serialize, Debug, Default, Clone, Copy, PartialEq)]
+pub struct ParseStatistics {
+    /// Records found by walking the pages.
+    pub structured_records: u64,
+    /// Records found by the carver.
+    pub carved_records: u64,
+    /// Pages that could not be walked and were carved from the damaged record on.
+    pub carved_pages: u64,
+}
+
+impl ParseStatistics {
+    pub fn add(&mut self, other: &ParseStatistics) {
+        self.structured_records += other.structured_records;
+        self.carved_records += other.carved_records;
+        self.carved_pages += other.carved_pages;
+    }
+
+    /// The number of records found by both modes.
+    pub fn records(&self) -> u64 {
+        self.structured_records + self.carved_records
+    }
+}
+
+
+/// Why a record candidate was rejected or the data could not be read.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum DiagnosticReason {
+    /// The record length is not 8 byte aligned.
+    MisalignedLength,
+    /// The minor version is not 0.
+    BadMinorVersion,
+    /// The file name offset does not follow the fixed portion of the record.
+    BadNameOffset,
+    /// The extents of a version 4 record do not fill the record.
+    BadExtents,
+    /// The major version is not 2, 3 or 4.
+    UnsupportedVersion,
+    /// The record failed validation while being parsed, such as its length being
+    /// over 1024.
+    InvalidRecord,
+    /// The file name is not valid UTF-16.
+    Utf16Decode,
+    /// The record runs past the end of the data.
+    ShortRead,
+    /// The data could not be read, no more data of the source was parsed.
+    ReadError,
+}
+
+impl DiagnosticReason {
+    fn from_error(error: &UsnError) -> DiagnosticReason {
+        match error.kind {
+            ErrorKind::Utf16DecodeError => DiagnosticReason::Utf16Decode,
+            ErrorKind::IoError => DiagnosticReason::ShortRead,
+            ErrorKind::UnsupportedVersion => DiagnosticReason::UnsupportedVersion,
+            _ => DiagnosticReason::InvalidRecord
+        }
+    }
+}
+
+
+/// A record candidate that was rejected, or where the data could not be read.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct CarveDiagnostic {
+    pub source: String,
+    pub offset: u64,
+    pub reason: DiagnosticReason,
+}
+
+impl CarveDiagnostic {
+    pub fn new(source: &str, offset: u64, reason: DiagnosticReason) -> Self {
+        CarveDiagnostic {
+            source: source.to_string(),
+            offset,
+            reason,
+        }
+    }
+}
+
+
+/// The number of diagnostics of a reason and the offset of the first.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+pub struct DiagnosticCount {
+    pub count: u64,
+    pub first_offset: u64,
+}
+
+
+/// How the records of a source were found and what was rejected, to tell a
+/// clean journal from a damaged one.
+#[derive(Serialize, Debug, Default, Clone, PartialEq)]
+pub struct ParseReport {
+    pub statistics: ParseStatistics,
+    pub diagnostics: BTreeMap<DiagnosticReason, DiagnosticCount>,
+}
+
+impl ParseReport {
+    pub fn add_diagnostic(&mut self, diagnostic: &CarveDiagnostic) {
+        self.diagnostics.entry(diagnostic.reason)
+            .and_modify(|count| count.count += 1)
+            .or_insert(DiagnosticCount {
+                count: 1,
+                first_offset: diagnostic.offset
+            });
+    }
+
+    /// The number of record candidates that were rejected.
+    pub fn rejected(&self) -> u64 {
+        self.diagnostics.iter()
+            .filter(|(reason, _)| **reason != DiagnosticReason::ReadError)
+            .map(|(_, count)| count.count)
+            .sum()
+    }
+}
+
+
 pub struct UsnParserSettings{
-    thread_count: usize
+    thread_count: usize,
+    filter: RecordFilter,
+    skip_leading_zeros: bool,
+    parse_mode: ParseMode
 }
 
 impl Default for UsnParserSettings {
     fn default() -> Self {
         UsnParserSettings {
-            thread_count: 0
+            thread_count: 0,
+            filter: RecordFilter::default(),
+            skip_leading_zeros: false,
+            parse_mode: ParseMode::Carve
         }
     }
 }
@@ -65,6 +219,30 @@ impl UsnParserSettings {
         self.thread_count = 1;
         self
     }
+
+    /// Sets the filter that records must match to be returned.
+    /// The filter is applied by the worker threads as each chunk is parsed.
+    ///
+    pub fn filter(mut self, filter: RecordFilter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    /// Start parsing at the first page that is not all zeros, found with a binary
+    /// search. This assumes the zeros are only at the start, like the sparse part
+    /// of a $J, so it should not be used on unallocated or carved data.
+    ///
+    pub fn skip_leading_zeros(mut self, skip_leading_zeros: bool) -> Self {
+        self.skip_leading_zeros = skip_leading_zeros;
+        self
+    }
+
+    /// Sets how records are found. Defaults to `ParseMode::Carve`.
+    ///
+    pub fn parse_mode(mut self, parse_mode: ParseMode) -> Self {
+        self.parse_mode = parse_mode;
+        self
+    }
 }
 
 
@@ -72,17 +250,64 @@ pub struct UsnParser<T: ReadSeek> {
     inner_handle: T,
     source: String,
     handle_size: u64,
-    settings: UsnParserSettings
+    settings: UsnParserSettings,
+    data_regions: Option<Vec<Range<u64>>>,
+    snapshot: Option<String>,
+    report: ParseReport
 }
 
 impl UsnParser<File> {
+    /// Create a parser for a file. If the file is sparse on disk, its holes are
+    /// skipped (Linux only).
     pub fn from_path(filename: &str) -> Result<Self, io::Error> {
         let file_handle = File::open(filename)?;
+        let data_regions = utils::get_file_data_regions(&file_handle);
 
-        Self::from_read_seek(
+        let parser = Self::from_read_seek(
             filename.to_string(),
             file_handle
-        )
+        )?;
+
+        Ok(match data_regions {
+            Some(data_regions) => parser.with_data_regions(data_regions),
+            None => parser
+        })
+    }
+}
+
+impl UsnParser<NtfsStream<File>> {
+    /// Create a parser for the $UsnJrnl:$J of the NTFS volume that starts at
+    /// `offset` bytes into the raw image `filename`. Sparse runs read as zeros.
+    pub fn from_ntfs_image(filename: &str, offset: u64) -> Result<Self, UsnError> {
+        let stream = NtfsVolume::from_path(filename, offset)?
+            .into_usn_journal_stream()?;
+        let data_regions = stream.data_regions()?;
+
+        Ok(Self::from_read_seek(
+            filename.to_string(),
+            stream
+        )?.with_data_regions(data_regions))
+    }
+}
+
+impl UsnParser<NtfsStream<VssSnapshot<Box<dyn ReadSeek>>>> {
+    /// Create a parser for the $UsnJrnl:$J of a shadow copy of the NTFS volume
+    /// that starts at `offset` bytes into the raw or EWF image `filename`. The
+    /// shadow copy is the index into `VssVolume::shadow_copies`, and its identifier
+    /// is added to each record's meta.
+    pub fn from_shadow_copy(filename: &str, offset: u64, index: usize) -> Result<Self, UsnError> {
+        let snapshot = VssVolume::new(open_image(filename)?, offset)?
+            .into_snapshot(index)?;
+        let identifier = snapshot.identifier().to_string();
+
+        let stream = NtfsVolume::new(snapshot, 0)?
+            .into_usn_journal_stream()?;
+        let data_regions = stream.data_regions()?;
+
+        Ok(Self::from_read_seek(
+            filename.to_string(),
+            stream
+        )?.with_data_regions(data_regions).with_snapshot(identifier))
     }
 }
 
@@ -98,7 +323,10 @@ impl <T: ReadSeek> UsnParser <T> {
             inner_handle: inner_handle,
             source: source,
             handle_size: end_offset,
-            settings: UsnParserSettings::default()
+            settings: UsnParserSettings::default(),
+            data_regions: None,
+            snapshot: None,
+            report: ParseReport::default()
         })
     }
 
@@ -107,26 +335,208 @@ impl <T: ReadSeek> UsnParser <T> {
         self
     }
 
+    /// The number of records found by each parse mode so far, before filtering.
+    pub fn statistics(&self) -> ParseStatistics {
+        self.report.statistics
+    }
+
+    /// The statistics and the rejected record candidates and read errors so far.
+    pub fn report(&self) -> &ParseReport {
+        &self.report
+    }
+
+    fn add_read_error(&mut self, offset: u64, error: io::Error) {
+        error!("{}", error);
+        self.report.add_diagnostic(
+            &CarveDiagnostic::new(&self.source, offset, DiagnosticReason::ReadError)
+        );
+    }
+
+    /// Set the shadow copy identifier that is added to each record's meta.
+    pub fn with_snapshot(mut self, snapshot: String) -> Self {
+        self.snapshot = Some(snapshot);
+        self
+    }
+
+    /// Set the regions of the handle that have data. Anything outside of them is
+    /// treated as zeros and not read.
+    pub fn with_data_regions(mut self, data_regions: Vec<Range<u64>>) -> Self {
+        self.data_regions = Some(data_regions);
+        self
+    }
+
+    /// Get the first offset at or after `offset` that is in a data region.
+    fn next_data_offset(&self, offset: u64) -> Option<u64> {
+        let data_regions = match self.data_regions {
+            Some(ref data_regions) => data_regions,
+            None => return Some(offset)
+        };
+
+        for region in data_regions {
+            if region.contains(&offset) {
+                return Some(offset);
+            }
+            if region.start > offset {
+                return Some(region.start);
+            }
+        }
+
+        None
+    }
+
+    /// Zero the parts of a buffer read at `offset` that are outside the data regions.
+    fn zero_holes(&self, offset: u64, buffer: &mut [u8]) {
+        let data_regions = match self.data_regions {
+            Some(ref data_regions) => data_regions,
+            None => return
+        };
+
+        let end = offset + buffer.len() as u64;
+        let mut hole_start = offset;
+
+        for region in data_regions.iter().chain(std::iter::once(&(end..end))) {
+            if region.end <= hole_start {
+                continue;
+            }
+            if region.start >= end {
+                buffer[(hole_start - offset) as usize..].iter_mut().for_each(|byte| *byte = 0);
+                return;
+            }
+            if region.start > hole_start {
+                buffer[(hole_start - offset) as usize..(region.start - offset) as usize]
+                    .iter_mut().for_each(|byte| *byte = 0);
+            }
+            hole_start = region.end;
+            if hole_start >= end {
+                return;
+            }
+        }
+    }
+
+    /// Binary search for the first page that is not all zeros. This assumes that
+    /// all the pages after it are not all zeros either.
+    pub fn find_first_data_page(&mut self) -> io::Result<u64> {
+        let first_offset = match self.next_data_offset(0) {
+            Some(offset) => offset,
+            None => return Ok(self.handle_size)
+        };
+
+        let mut low = first_offset / SIZE_PAGE;
+        let mut high = (self.handle_size + SIZE_PAGE - 1) / SIZE_PAGE;
+        let mut page = vec![0u8; SIZE_PAGE as usize];
+
+        while low < high {
+            let middle = low + (high - low) / 2;
+
+            self.inner_handle.seek(SeekFrom::Start(middle * SIZE_PAGE))?;
+            let bytes_read = self.inner_handle.read(&mut page)?;
+
+            if page[..bytes_read].iter().all(|byte| *byte == 0) {
+                low = middle + 1;
+            } else {
+                high = middle;
+            }
+        }
+
+        Ok(low * SIZE_PAGE)
+    }
+
+    /// The offset to start parsing from.
+    fn get_start_offset(&mut self) -> u64 {
+        if !self.settings.skip_leading_zeros {
+            return 0;
+        }
+
+        match self.find_first_data_page() {
+            Ok(offset) => {
+                debug!("skipping {} bytes of leading zeros", offset);
+                offset
+            },
+            Err(error) => {
+                error!("Error searching for the first page of data: {}", error);
+                0
+            }
+        }
+    }
+
+    /// Read the next chunk at or after `chunk_start_offset` and move it past the
+    /// chunk's search area. Chunks outside the data regions or whose search area
+    /// is all zeros are skipped, as no record can start in them.
+    fn read_chunk(&mut self, chunk_start_offset: &mut u64, chunk_size: usize, search_size: usize) -> Option<DataChunk> {
+        loop {
+            // Get the current offset
+            let current_offset = match self.next_data_offset(*chunk_start_offset) {
+                Some(offset) if offset < self.handle_size => offset,
+                _ => return None
+            };
+
+            // Create buffer for our data chunk
+            let mut buffer = vec![0u8; chunk_size];
+
+            // Seek to where we start our chunk
+            if let Err(error) = self.inner_handle.seek(SeekFrom::Start(current_offset)) {
+                self.add_read_error(current_offset, error);
+                return None;
+            }
+
+            // Read into buffer
+            let bytes_read = match self.inner_handle.read(buffer.as_mut_slice()) {
+                Ok(bytes_read) => bytes_read,
+                Err(error) => {
+                    self.add_read_error(current_offset, error);
+                    return None
+                }
+            };
+
+            self.zero_holes(current_offset, &mut buffer[..bytes_read]);
+
+            // Set the next chunk's offset
+            // Increment by search size and not chunk size
+            *chunk_start_offset = current_offset + search_size as u64;
+
+            if buffer[..search_size.min(bytes_read)].iter().all(|byte| *byte == 0) {
+                continue;
+            }
+
+            // Return data chunk
+            return Some(
+                DataChunk{
+                    source: self.source.to_owned(),
+                    offset: current_offset,
+                    search_size,
+                    parse_mode: self.settings.parse_mode,
+                    data: buffer
+                }
+            );
+        }
+    }
+
     pub fn get_chunk_iterator(&mut self) -> IterFileChunks<T> {
+        let chunk_start_offset = self.get_start_offset();
+
         IterFileChunks{
             parser: self,
             chunk_size: SIZE_CHUNK,
             search_size: SIZE_SEARCH,
-            chunk_start_offset: 0,
+            chunk_start_offset,
         }
     }
 
-    pub fn into_chunk_iterator(self) -> IntoIterFileChunks<T> {
+    pub fn into_chunk_iterator(mut self) -> IntoIterFileChunks<T> {
+        let chunk_start_offset = self.get_start_offset();
+
         IntoIterFileChunks {
             parser: self,
             chunk_size: SIZE_CHUNK,
             search_size: SIZE_SEARCH,
-            chunk_start_offset: 0,
+            chunk_start_offset,
         }
     }
 
     pub fn records(&mut self) -> impl Iterator<Item = UsnEntry> + '_ {
         let num_threads = max(self.settings.thread_count, 1);
+        let filter = self.settings.filter.clone();
+        let snapshot = self.snapshot.clone();
 
         let mut chunks = self.get_chunk_iterator();
 
@@ -152,17 +562,31 @@ impl <T: ReadSeek> UsnParser <T> {
                     let chunk_iter = list_of_chunks.into_iter();
 
                     // Serialize the records in each chunk.
-                    let iterators: Vec<Vec<UsnEntry>> = chunk_iter
-                        .map(|data_chunk| data_chunk.get_records()
-                        )
+                    let iterators: Vec<ChunkRecords> = chunk_iter
+                        .map(|data_chunk| data_chunk.parse(&filter))
                         .collect();
 
-                    Some(iterators.into_iter().flatten())
+                    let mut records = Vec::with_capacity(iterators.len());
+                    for chunk_records in iterators {
+                        let report = &mut chunks.parser.report;
+                        report.statistics.add(&chunk_records.statistics);
+                        for diagnostic in &chunk_records.diagnostics {
+                            report.add_diagnostic(diagnostic);
+                        }
+                        records.push(chunk_records.records);
+                    }
+
+                    Some(records.into_iter().flatten())
                 }
             }
         );
 
-        records_per_chunk.flatten()
+        records_per_chunk.flatten().map(move |mut entry| {
+            if let Some(ref snapshot) = snapshot {
+                entry.meta = entry.meta.with_snapshot(snapshot);
+            }
+            entry
+        })
     }
 }
 
@@ -180,51 +604,11 @@ impl <'c, T: ReadSeek> Iterator for IterFileChunks <'c, T> {
     type Item = DataChunk;
 
     fn next(&mut self) -> Option<<Self as Iterator>::Item> {
-        while self.chunk_start_offset < self.parser.handle_size {
-            // Create buffer for our data chunk
-            let mut buffer = vec![0u8; self.chunk_size];
-
-            // Get the current offset
-            let current_offset = self.chunk_start_offset;
-            
-            // Seek to where we start our chunk
-            match self.parser.inner_handle.seek(
-                SeekFrom::Start(current_offset)
-            ) {
-                Ok(_) => {},
-                Err(error) => {
-                    error!("{}", error);
-                    break;
-                }
-            }
-
-            // Read into buffer
-            let _bytes_read = match self.parser.inner_handle.read(
-                buffer.as_mut_slice()
-            ){
-                Ok(bytes_read) => bytes_read,
-                Err(error) => {
-                    error!("{}", error);
-                    return None
-                }
-            };
-
-            // Set the next chunk's offset
-            // Increment by search size and not chunk size
-            self.chunk_start_offset += self.search_size as u64;
-
-            // Return data chunk
-            return Some(
-                DataChunk{
-                    source: self.parser.source.to_owned(),
-                    offset: current_offset,
-                    search_size: self.search_size,
-                    data: buffer
-                }
-            );
-        }
-        
-        None
+        self.parser.read_chunk(
+            &mut self.chunk_start_offset,
+            self.chunk_size,
+            self.search_size
+        )
     }
 }
 
@@ -240,52 +624,22 @@ impl<T: ReadSeek> Iterator for IntoIterFileChunks<T> {
     type Item = DataChunk;
     
     fn next(&mut self) -> Option<<Self as Iterator>::Item> {
-        while self.chunk_start_offset < self.parser.handle_size {
-            // Create buffer for our data chunk
-            let mut buffer = vec![0u8; self.chunk_size];
-
-            // Get the current offset
-            let current_offset = self.chunk_start_offset;
-            
-            // Seek to where we start our chunk
-            match self.parser.inner_handle.seek(
-                SeekFrom::Start(current_offset)
-            ) {
-                Ok(_) => {},
-                Err(error) => {
-                    error!("{}", error);
-                    break;
-                }
-            }
-
-            // Read into buffer
-            let _bytes_read = match self.parser.inner_handle.read(
-                buffer.as_mut_slice()
-            ){
-                Ok(bytes_read) => bytes_read,
-                Err(error) => {
-                    error!("{}", error);
-                    return None
-                }
-            };
+        self.parser.read_chunk(
+            &mut self.chunk_start_offset,
+            self.chunk_size,
+            self.search_size
+        )
+    }
+}
 
-            // Set the next chunk's offset
-            // Increment by search size and not chunk size
-            self.chunk_start_offset += self.search_size as u64;
 
-            // Return data chunk
-            return Some(
-                DataChunk{
-                    source: self.parser.source.to_owned(),
-                    offset: current_offset,
-                    search_size: self.search_size,
-                    data: buffer
-                }
-            );
-        }
-        
-        None
-    }
+/// The records of a chunk, how they were found and the record candidates that
+/// were rejected.
+#[derive(Debug, Default)]
+pub struct ChunkRecords {
+    pub records: Vec<UsnEntry>,
+    pub statistics: ParseStatistics,
+    pub diagnostics: Vec<CarveDiagnostic>,
 }
 
 
@@ -294,6 +648,7 @@ pub struct DataChunk {
     source: String,
     offset: u64,
     search_size: usize,
+    parse_mode: ParseMode,
     data: Vec<u8>
 }
 
@@ -301,11 +656,127 @@ impl DataChunk {
     pub fn get_records(self) -> Vec<UsnEntry> {
         trace!("Getting records for ChunkData at offset: {}", self.offset);
 
-        let record_iterator = self.get_record_iterator();
+        self.get_records_with_statistics(&RecordFilter::default()).0
+    }
+
+    /// Get the records in this chunk that match the filter.
+    pub fn get_filtered_records(self, filter: &RecordFilter) -> Vec<UsnEntry> {
+        trace!("Getting filtered records for ChunkData at offset: {}", self.offset);
 
-        let records: Vec<UsnEntry> = record_iterator.collect();
+        self.get_records_with_statistics(filter).0
+    }
 
-        return records;
+    /// Get the records in this chunk that match the filter, using the chunk's parse
+    /// mode, and the number of records found by each mode before filtering.
+    pub fn get_records_with_statistics(self, filter: &RecordFilter) -> (Vec<UsnEntry>, ParseStatistics) {
+        let chunk_records = self.parse(filter);
+        (chunk_records.records, chunk_records.statistics)
+    }
+
+    /// Get the records in this chunk that match the filter using the chunk's parse
+    /// mode, along with the number of records found by each mode before filtering
+    /// and the record candidates that were rejected.
+    pub fn parse(self, filter: &RecordFilter) -> ChunkRecords {
+        let mut chunk_records = match self.parse_mode {
+            ParseMode::Carve => {
+                let mut chunk_records = ChunkRecords::default();
+                for result in self.get_record_results() {
+                    match result {
+                        Ok(entry) => chunk_records.records.push(entry),
+                        Err(diagnostic) => chunk_records.diagnostics.push(diagnostic)
+                    }
+                }
+                chunk_records.statistics.carved_records = chunk_records.records.len() as u64;
+                chunk_records
+            },
+            ParseMode::Structured => self.walk_pages()
+        };
+
+        chunk_records.records.retain(|entry| filter.matches(entry));
+
+        chunk_records
+    }
+
+    /// Walk the records of each page in the search area by their record length,
+    /// moving to the next page at the zero padding. If a page has a record that is
+    /// not valid, the rest of the page is carved.
+    fn walk_pages(self) -> ChunkRecords {
+        let mut chunk_records = ChunkRecords::default();
+        let statistics = &mut chunk_records.statistics;
+
+        let search_end = self.offset + self.search_size.min(self.data.len()) as u64;
+        let mut page_start = self.offset;
+
+        while page_start < search_end {
+            // Pages are aligned to the start of the stream
+            let page_end = cmp::min((page_start / SIZE_PAGE + 1) * SIZE_PAGE, search_end);
+            let end = (page_end - self.offset) as usize;
+            let mut i = (page_start - self.offset) as usize;
+
+            while i + 8 <= end {
+                let record_length = LittleEndian::read_u32(&self.data[i..i+4]) as usize;
+
+                // The rest of the page is zero padding
+                if record_length == 0 {
+                    break;
+                }
+
+                let result = match record_length <= end - i {
+                    true => parse_record_at(&self.source, &self.data[..end], self.offset, i),
+                    false => Err(CarveDiagnostic::new(
+                        &self.source,
+                        self.offset + i as u64,
+                        DiagnosticReason::ShortRead
+                    ))
+                };
+
+                match result {
+                    Ok(entry) => {
+                        chunk_records.records.push(entry);
+                        statistics.structured_records += 1;
+                        i += record_length;
+                    },
+                    Err(diagnostic) => {
+                        debug!("carving damaged page at offset {}", diagnostic.offset);
+                        let hits: Vec<usize> = RE_USN.find_iter(&self.data[i..end])
+                            .map(|m| i + m.start())
+                            .collect();
+
+                        // The damaged record is carved again if it looks like a record header
+                        if hits.first() != Some(&i) {
+                            chunk_records.diagnostics.push(diagnostic);
+                        }
+
+                        statistics.carved_pages += 1;
+                        for hit in hits {
+                            match parse_record_at(&self.source, &self.data, self.offset, hit) {
+                                Ok(entry) => {
+                                    chunk_records.records.push(entry);
+                                    statistics.carved_records += 1;
+                                },
+                                Err(diagnostic) => chunk_records.diagnostics.push(diagnostic)
+                            }
+                        }
+                        break;
+                    }
+                }
+            }
+
+            page_start = page_end;
+        }
+
+        chunk_records
+    }
+
+    /// Carve the chunk's search area, yielding each record or why a candidate was
+    /// rejected.
+    pub fn get_record_results(self) -> IterRecordResults {
+        IterRecordResults::new(
+            self.source,
+            self.data,
+            self.offset,
+            self.search_size
+        )
     }
 
     pub fn get_record_iterator(self) -> IterRecords {
@@ -318,21 +789,23 @@ impl DataChunk {
     }
 }
 
+/// Iterates the records carved from a block, along with why each candidate that
+/// is not a valid record was rejected.
 #[derive(Debug)]
-pub struct IterRecords {
+pub struct IterRecordResults {
     source: String,
     block: Vec<u8>,
     start_offset: u64,
     match_offsets: VecDeque<u64>,
 }
 
-impl IterRecords {
-    pub fn new(source: String, block: Vec<u8>, start_offset: u64, search_size: usize) -> IterRecords {
+impl IterRecordResults {
+    pub fn new(source: String, block: Vec<u8>, start_offset: u64, search_size: usize) -> IterRecordResults {
         let match_offsets: VecDeque<u64> = RE_USN.find_iter(&block[0..search_size])
             .map(|m| m.start() as u64)
             .collect();
 
-        IterRecords {
+        IterRecordResults {
             source,
             block,
             start_offset,
@@ -341,212 +814,193 @@ impl IterRecords {
     }
 }
 
-impl Iterator for IterRecords {
-    type Item = UsnEntry;
+impl Iterator for IterRecordResults {
+    type Item = Result<UsnEntry, CarveDiagnostic>;
 
-    fn next(&mut self) -> Option<UsnEntry> {
-        loop {
-            // start of hit
-            let start_of_hit = match self.match_offsets.pop_front(){
-                Some(offset) => offset,
-                None => break
-            };
+    fn next(&mut self) -> Option<Self::Item> {
+        // start of hit
+        let start_of_hit = self.match_offsets.pop_front()?;
 
-            // index starts at start of hit offset
-            let i = start_of_hit as usize;
+        Some(parse_record_at(
+            &self.source,
+            &self.block,
+            self.start_offset,
+            start_of_hit as usize
+        ))
+    }
+}
 
-            // the entries' absolute offset
-            let entry_offset = self.start_offset + start_of_hit;
 
-            // validate record length is 8 byte aligned
-            let record_length = LittleEndian::read_u32(&self.block[i..i+4]);
-            if record_length % 8 != 0 {
-                debug!("not 8 byte aligned at offset {}", entry_offset);
-                continue;
-            }
+#[derive(Debug)]
+pub struct IterRecords {
+    results: IterRecordResults,
+}
 
-            // Check versions
-            let major = LittleEndian::read_u16(&self.block[i+4..i+6]);
+impl IterRecords {
+    pub fn new(source: String, block: Vec<u8>, start_offset: u64, search_size: usize) -> IterRecords {
+        IterRecords {
+            results: IterRecordResults::new(source, block, start_offset, search_size)
+        }
+    }
+}
 
-            let usn_entry = match major {
-                2 => {
-                    let minor = LittleEndian::read_u16(&self.block[i+6..i+8]);
+impl Iterator for IterRecords {
+    type Item = UsnEntry;
 
-                    // validate minor version
-                    if minor != 0 {
-                        debug!("minor version does not match major at offset {}", entry_offset);
-                        continue;
-                    }
+    fn next(&mut self) -> Option<UsnEntry> {
+        self.results.find_map(Result::ok)
+    }
+}
 
-                    // validate name offset
-                    let name_offset = LittleEndian::read_u16(&self.block[i+58..i+60]);
-                    if name_offset != 60 {
-                        debug!("name offset does not match 60 at offset {}", entry_offset);
-                        continue;
-                    }
 
-                    // Create Entry Meta
-                    let entry_meta = EntryMeta::new(
-                        &self.source,
-                        entry_offset
-                    );
-
-                    // Parse entry
-                    let entry = match UsnEntry::new(
-                        entry_meta, 
-                        2,
-                        &self.block[start_of_hit as usize ..]
-                    ) {
-                        Ok(entry) => entry,
-                        Err(error) => {
-                            debug!("error at offset {}: {}", entry_offset, error);
-                            continue;
-                        }
-                    };
+/// Validate and parse the record at index `i` of a block. `start_offset` is the
+/// absolute offset of the block. Returns why the data is not a valid record if
+/// it is not one.
+fn parse_record_at(source: &str, block: &[u8], start_offset: u64, i: usize) -> Result<UsnEntry, CarveDiagnostic> {
+    // the entries' absolute offset
+    let entry_offset = start_offset + i as u64;
+    let reject = |reason| Err(CarveDiagnostic::new(source, entry_offset, reason));
 
-                    entry
-                },
-                other => {
-                    debug!("Version not handled: {}; offset: {}", other, entry_offset);
-                    continue;
-                }
-            };
+    // validate the length and version fields are within the block
+    if i + 8 > block.len() {
+        return reject(DiagnosticReason::ShortRead);
+    }
 
-            return Some(usn_entry);
+    // validate record length is 8 byte aligned
+    let record_length = LittleEndian::read_u32(&block[i..i+4]);
+    if record_length % 8 != 0 {
+        debug!("not 8 byte aligned at offset {}", entry_offset);
+        return reject(DiagnosticReason::MisalignedLength);
+    }
+
+    // Check versions
+    let major = LittleEndian::read_u16(&block[i+4..i+6]);
+
+    // The size of the fixed portion of the record
+    let header_size = match major {
+        2 => 60,
+        3 => 76,
+        4 => 64,
+        other => {
+            debug!("Version not handled: {}; offset: {}", other, entry_offset);
+            return reject(DiagnosticReason::UnsupportedVersion);
         }
+    };
 
-        None
+    // validate minor version
+    let minor = LittleEndian::read_u16(&block[i+6..i+8]);
+    if minor != 0 {
+        debug!("minor version does not match major at offset {}", entry_offset);
+        return reject(DiagnosticReason::BadMinorVersion);
     }
+
+    // validate the fixed portion of the record is within the block
+    if i + header_size > block.len() {
+        debug!("record header exceeds block at offset {}", entry_offset);
+        return reject(DiagnosticReason::ShortRead);
+    }
+
+    if major == 4 {
+        // validate the extents fill the record
+        let extent_count = LittleEndian::read_u16(&block[i+60..i+62]);
+        let extent_size = LittleEndian::read_u16(&block[i+62..i+64]);
+        if extent_size != 16 || record_length != 64 + u32::from(extent_count) * 16 {
+            debug!("extents do not match record length at offset {}", entry_offset);
+            return reject(DiagnosticReason::BadExtents);
+        }
+    } else {
+        // validate name offset
+        let name_offset = LittleEndian::read_u16(&block[i+header_size-2..i+header_size]);
+        if name_offset as usize != header_size {
+            debug!("name offset does not match {} at offset {}", header_size, entry_offset);
+            return reject(DiagnosticReason::BadNameOffset);
+        }
+    }
+
+    // Create Entry Meta
+    let entry_meta = EntryMeta::new(
+        source,
+        entry_offset
+    );
+
+    // Parse entry
+    UsnEntry::new(entry_meta, major, &block[i..])
+        .or_else(|error| {
+            debug!("error at offset {}: {}", entry_offset, error);
+            reject(DiagnosticReason::from_error(&error))
+        })
 }
 
 
-/// This iterator iterates records from a buffer by index.
-///
+/// Iterates the records of a buffer of consecutive records by index, along with
+/// why each position that is not a valid record was rejected.
 #[derive(Debug)]
-pub struct IterRecordsByIndex {
-    meta: EntryMeta,
+pub struct IterRecordResultsByIndex {
+    source: String,
     block: Vec<u8>,
+    start_offset: u64,
     index: usize,
 }
 
-impl IterRecordsByIndex {
+impl IterRecordResultsByIndex {
     pub fn new(meta: EntryMeta, block: Vec<u8>) -> Self {
-        IterRecordsByIndex {
-            meta: meta,
-            block: block,
+        IterRecordResultsByIndex {
+            source: meta.source,
+            block,
+            start_offset: meta.offset,
             index: 0
         }
     }
 }
 
-impl Iterator for IterRecordsByIndex {
-    type Item = UsnEntry;
+impl Iterator for IterRecordResultsByIndex {
+    type Item = Result<UsnEntry, CarveDiagnostic>;
 
-    fn next(&mut self) -> Option<UsnEntry> {
-        while self.index < self.block.len() {
-            self.meta.offset += self.index as u64;
-
-            let record_length = LittleEndian::read_u32(
-                &self.block[self.index..self.index+4]
-            );
-            if record_length % 8 != 0 {
-                debug!("not 8 byte aligned at offset {}", self.index);
-                self.index += 8;
-                continue;
-            }
-
-            // Check versions
-            let major = LittleEndian::read_u16(
-                &self.block[self.index+4..self.index+6]
-            );
-            let minor = LittleEndian::read_u16(
-                &self.block[self.index+6..self.index+8]
-            );
-
-            let usn_entry = match major {
-                2 => {
-                    // validate minor version
-                    if minor != 0 {
-                        debug!("minor version does not match major at offset {}", self.index);
-                        self.index += 8;
-                        continue;
-                    }
-
-                    // validate name offset
-                    let name_offset = LittleEndian::read_u16(
-                        &self.block[self.index+58..self.index+60]
-                    );
-                    if name_offset != 60 {
-                        debug!("name offset does not match 60 at offset {}", self.index);
-                        self.index += 8;
-                        continue;
-                    }
-
-                    // Parse entry
-                    let entry = match UsnEntry::new(
-                        self.meta.clone(),
-                        2,
-                        &self.block[self.index as usize ..]
-                    ) {
-                        Ok(entry) => entry,
-                        Err(error) => {
-                            debug!("error at offset {}: {}", self.index, error);
-                            self.index += 8;
-                            continue;
-                        }
-                    };
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.index >= self.block.len() {
+            return None;
+        }
 
-                    self.index += record_length as usize;
-
-                    entry
-                },
-                3 => {
-                    debug!("entry: {}", hex::encode(&self.block[self.index as usize .. self.index as usize + record_length as usize]));
-                    // validate minor version
-                    if minor != 0 {
-                        debug!("minor version does not match major at offset {}", self.index);
-                        self.index += 8;
-                        continue;
-                    }
+        let result = parse_record_at(
+            &self.source,
+            &self.block,
+            self.start_offset,
+            self.index
+        );
 
-                    // validate name offset
-                    let name_offset = LittleEndian::read_u16(
-                        &self.block[self.index+74..self.index+76]
-                    );
-                    if name_offset != 76 {
-                        debug!("name offset [{}] does not match 76 at offset {}", name_offset, self.index);
-                        self.index += 8;
-                        continue;
-                    }
+        // A valid record is followed by the next record, otherwise move on to
+        // the next 8 byte aligned position
+        self.index += match result {
+            Ok(_) => max(LittleEndian::read_u32(
+                &self.block[self.index..self.index+4]
+            ) as usize, 8),
+            Err(_) => 8
+        };
 
-                    // Parse entry
-                    let entry = match UsnEntry::new(
-                        self.meta.clone(),
-                        3,
-                        &self.block[self.index as usize ..]
-                    ) {
-                        Ok(entry) => entry,
-                        Err(error) => {
-                            debug!("error at offset {}: {}", self.index, error);
-                            self.index += 8;
-                            continue;
-                        }
-                    };
+        Some(result)
+    }
+}
 
-                    self.index += record_length as usize;
 
-                    entry
-                }
-                other => {
-                    debug!("Version not handled: {}; offset: {}", other, self.index);
-                    self.index += 8;
-                    continue;
-                }
-            };
+/// This iterator iterates records from a buffer by index.
+///
+#[derive(Debug)]
+pub struct IterRecordsByIndex {
+    results: IterRecordResultsByIndex,
+}
 
-            return Some(usn_entry);
+impl IterRecordsByIndex {
+    pub fn new(meta: EntryMeta, block: Vec<u8>) -> Self {
+        IterRecordsByIndex {
+            results: IterRecordResultsByIndex::new(meta, block)
         }
+    }
+}
 
-        None
+impl Iterator for IterRecordsByIndex {
+    type Item = UsnEntry;
+
+    fn next(&mut self) -> Option<UsnEntry> {
+        self.results.find_map(Result::ok)
     }
 }
\ No newline at end of file
@@ -1,3 +1,5 @@
+use std::fs::File;
+use std::ops::Range;
 use time::Duration;
 use chrono::{DateTime, NaiveDate, Utc};
 
@@ -13,4 +15,47 @@ pub fn u64_to_datetime(timestamp_u64: u64) -> DateTime<Utc> {
             ),
         Utc,
     )
-}
\ No newline at end of file
+}
+
+
+/// Get the regions of a file that have data, skipping the holes of a sparse file,
+/// with SEEK_DATA and SEEK_HOLE. Returns None if they are not supported.
+///
+#[cfg(target_os = "linux")]
+pub fn get_file_data_regions(file: &File) -> Option<Vec<Range<u64>>> {
+    use std::io;
+    use std::os::unix::io::AsRawFd;
+
+    let file_descriptor = file.as_raw_fd();
+    let size = file.metadata().ok()?.len();
+    let mut regions = Vec::new();
+    let mut offset: libc::off_t = 0;
+
+    while (offset as u64) < size {
+        let data_start = unsafe { libc::lseek(file_descriptor, offset, libc::SEEK_DATA) };
+        if data_start < 0 {
+            // ENXIO means there is no more data after offset
+            return match io::Error::last_os_error().raw_os_error() {
+                Some(libc::ENXIO) => Some(regions),
+                _ => None
+            };
+        }
+
+        let data_end = unsafe { libc::lseek(file_descriptor, data_start, libc::SEEK_HOLE) };
+        if data_end < 0 {
+            return None;
+        }
+
+        regions.push(data_start as u64..data_end as u64);
+        offset = data_end;
+    }
+
+    Some(regions)
+}
+
+/// SEEK_DATA and SEEK_HOLE are only used on Linux.
+///
+#[cfg(not(target_os = "linux"))]
+pub fn get_file_data_regions(_file: &File) -> Option<Vec<Range<u64>>> {
+    None
+}
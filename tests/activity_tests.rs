@@ -0,0 +1,82 @@
+extern crate rusty_usn;
+mod common;
+use common::{RecordBuilder, FILETIME, SECOND};
+use rusty_usn::activity::{ActivityAction, ActivityAggregator};
+use rusty_usn::flags::Reason;
+use rusty_usn::record::UsnEntry;
+
+
+/// Build a V2 record for a file with a sequence of 1.
+fn entry(usn: u64, file_entry: u64, parent_entry: u64, reason: Reason, name: &str) -> UsnEntry {
+    RecordBuilder::new(name)
+        .file_reference(file_entry, 1)
+        .parent_reference(parent_entry, 1)
+        .usn(usn)
+        .timestamp(FILETIME + usn * SECOND)
+        .reason(reason)
+        .entry("$J", usn * 0x60)
+}
+
+#[test]
+fn file_save_activity_test() {
+    let create = Reason::USN_REASON_FILE_CREATE;
+    let extend = Reason::USN_REASON_DATA_EXTEND;
+    let close = Reason::USN_REASON_CLOSE;
+
+    let mut aggregator = ActivityAggregator::new();
+    let records = [
+        entry(1, 100, 5, create, "report.docx"),
+        entry(2, 100, 5, create | extend, "report.docx"),
+        // Another file's record in between
+        entry(3, 200, 5, extend, "other.log"),
+        entry(4, 100, 5, create | extend | close, "report.docx"),
+    ];
+
+    let activities: Vec<_> = records.iter()
+        .filter_map(|entry| aggregator.add(entry))
+        .collect();
+
+    assert_eq!(activities.len(), 1);
+    let activity = &activities[0];
+    assert_eq!(activity.file_reference.entry, 100);
+    assert_eq!(activity.first_usn, 1);
+    assert_eq!(activity.last_usn, 4);
+    assert_eq!(activity.record_count, 3);
+    assert_eq!(activity.reason, create | extend | close);
+    assert_eq!(activity.actions, vec![ActivityAction::Created, ActivityAction::Modified]);
+    assert_eq!(activity.last_timestamp.unwrap() - activity.first_timestamp.unwrap(), chrono::Duration::seconds(3));
+    assert!(activity.closed);
+
+    // The other file was never closed
+    let open = aggregator.finish();
+    assert_eq!(open.len(), 1);
+    assert_eq!(open[0].file_name, "other.log");
+    assert!(!open[0].closed);
+}
+
+#[test]
+fn rename_activity_test() {
+    let old_name = Reason::USN_REASON_RENAME_OLD_NAME;
+    let new_name = Reason::USN_REASON_RENAME_NEW_NAME;
+    let close = Reason::USN_REASON_CLOSE;
+
+    let activities = ActivityAggregator::aggregate(vec![
+        entry(1, 100, 5, old_name, "draft.txt"),
+        entry(2, 100, 36, new_name, "final.txt"),
+        entry(3, 100, 36, new_name | close, "final.txt"),
+    ].into_iter());
+
+    assert_eq!(activities.len(), 1);
+    let activity = &activities[0];
+    assert_eq!(activity.actions, vec![ActivityAction::Renamed]);
+    assert_eq!(activity.file_name, "final.txt");
+    assert_eq!(activity.parent_reference.entry, 36);
+
+    assert_eq!(activity.renames.len(), 1);
+    let rename = &activity.renames[0];
+    assert_eq!(rename.old_name, "draft.txt");
+    assert_eq!(rename.old_parent.entry, 5);
+    assert_eq!(rename.new_name, "final.txt");
+    assert_eq!(rename.new_parent.entry, 36);
+    assert_eq!(rename.usn, 2);
+}
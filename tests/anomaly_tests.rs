@@ -0,0 +1,91 @@
+extern crate rusty_usn;
+mod common;
+use common::V2_RECORD;
+use chrono::Duration;
+use rusty_usn::anomaly::{AnomalyDetector, AnomalyKind};
+use rusty_usn::record::{EntryMeta, UsnEntry};
+
+// 2019-03-20 00:00:00 as a FILETIME
+const FILETIME: u64 = 131975136000000000;
+const MINUTE: u64 = 600000000;
+
+
+fn entry(source: &str, offset: u64, usn: u64, filetime: u64) -> UsnEntry {
+    let mut record_buffer = V2_RECORD.to_vec();
+    record_buffer[24..32].copy_from_slice(&usn.to_le_bytes());
+    record_buffer[32..40].copy_from_slice(&filetime.to_le_bytes());
+
+    UsnEntry::new(
+        EntryMeta::new(source, offset),
+        2,
+        record_buffer.as_slice()
+    ).unwrap()
+}
+
+fn check_all(detector: &mut AnomalyDetector, entries: &[UsnEntry]) -> Vec<(AnomalyKind, u64, u64)> {
+    entries.iter()
+        .flat_map(|entry| detector.check(entry))
+        .map(|anomaly| (anomaly.kind, anomaly.previous_offset, anomaly.offset))
+        .collect()
+}
+
+#[test]
+fn no_anomalies_test() {
+    let mut detector = AnomalyDetector::new();
+    let entries = [
+        entry("$J", 0, 0x2000_0000, FILETIME),
+        entry("$J", 96, 0x2000_0060, FILETIME + MINUTE),
+        // Small timestamp regressions are normal
+        entry("$J", 192, 0x2000_00C0, FILETIME),
+    ];
+
+    assert!(check_all(&mut detector, &entries).is_empty());
+}
+
+#[test]
+fn usn_anomalies_test() {
+    let mut detector = AnomalyDetector::new();
+    let entries = [
+        entry("$J", 0, 0x2000_0000, FILETIME),
+        entry("$J", 96, 0x1000_0000, FILETIME),
+        entry("$J", 192, 0x1800_0000, FILETIME),
+        entry("$J", 288, 0x60, FILETIME),
+    ];
+
+    assert_eq!(check_all(&mut detector, &entries), vec![
+        (AnomalyKind::UsnRegression, 0, 96),
+        (AnomalyKind::UsnGap, 96, 192),
+        (AnomalyKind::JournalRestart, 192, 288),
+    ]);
+}
+
+#[test]
+fn timestamp_regression_test() {
+    let mut detector = AnomalyDetector::new()
+        .timestamp_tolerance(Duration::minutes(30));
+    let entries = [
+        entry("$J", 0, 0x2000_0000, FILETIME + 60 * MINUTE),
+        entry("$J", 96, 0x2000_0060, FILETIME + 40 * MINUTE),
+        entry("$J", 192, 0x2000_00C0, FILETIME),
+    ];
+
+    let anomalies: Vec<_> = entries.iter()
+        .flat_map(|entry| detector.check(entry))
+        .collect();
+
+    assert_eq!(anomalies.len(), 1);
+    assert_eq!(anomalies[0].kind, AnomalyKind::TimestampRegression);
+    assert_eq!(anomalies[0].previous_offset, 96);
+    assert_eq!(anomalies[0].previous_usn, 0x2000_0060);
+}
+
+#[test]
+fn source_change_test() {
+    let mut detector = AnomalyDetector::new();
+    let entries = [
+        entry("vss1/$J", 0, 0x2000_0000, FILETIME),
+        entry("vss2/$J", 0, 0x60, FILETIME),
+    ];
+
+    assert!(check_all(&mut detector, &entries).is_empty());
+}
@@ -0,0 +1,146 @@
+//! Record fixtures and builders shared by the integration tests.
+#![allow(dead_code)]
+use byteorder::{ByteOrder, LittleEndian};
+use winstructs::ntfs::mft_reference::MftReference;
+use rusty_usn::flags::Reason;
+use rusty_usn::record::{EntryMeta, UsnEntry};
+
+// 2019-03-20 00:00:00 as a FILETIME
+pub const FILETIME: u64 = 131975136000000000;
+pub const SECOND: u64 = 10000000;
+
+/// A V2 record of BTDevManager.log with a USN of 20342374400.
+pub const V2_RECORD: &[u8] = &[
+    0x60,0x00,0x00,0x00,0x02,0x00,0x00,0x00,0x73,0x00,0x00,0x00,0x00,0x00,0x68,0x91,
+    0x3B,0x2A,0x02,0x00,0x00,0x00,0x07,0x00,0x00,0x00,0x80,0xBC,0x04,0x00,0x00,0x00,
+    0x53,0xC7,0x8B,0x18,0xC5,0xCC,0xCE,0x01,0x02,0x00,0x00,0x00,0x00,0x00,0x00,0x00,
+    0x00,0x00,0x00,0x00,0x20,0x20,0x00,0x00,0x20,0x00,0x3C,0x00,0x42,0x00,0x54,0x00,
+    0x44,0x00,0x65,0x00,0x76,0x00,0x4D,0x00,0x61,0x00,0x6E,0x00,0x61,0x00,0x67,0x00,
+    0x65,0x00,0x72,0x00,0x2E,0x00,0x6C,0x00,0x6F,0x00,0x67,0x00,0x00,0x00,0x00,0x00
+];
+
+
+/// The V2_RECORD fixture as an entry at offset 0 of "Test Buffer".
+pub fn v2_entry() -> UsnEntry {
+    UsnEntry::new(
+        EntryMeta::new("Test Buffer", 0),
+        2,
+        V2_RECORD
+    ).unwrap()
+}
+
+
+/// Builds the bytes of a V2 or V3 record. Defaults to a V2 record of an archive
+/// file with a reference of 40-1 in the root folder, a timestamp of FILETIME and
+/// a USN of 0.
+pub struct RecordBuilder {
+    major_version: u16,
+    file_reference: MftReference,
+    parent_reference: MftReference,
+    usn: u64,
+    timestamp: u64,
+    reason: u32,
+    file_attributes: u32,
+    name: String,
+}
+
+impl RecordBuilder {
+    pub fn new(name: &str) -> Self {
+        RecordBuilder {
+            major_version: 2,
+            file_reference: MftReference::new(40, 1),
+            parent_reference: MftReference::new(5, 5),
+            usn: 0,
+            timestamp: FILETIME,
+            reason: 0,
+            file_attributes: 0x20,
+            name: name.to_string()
+        }
+    }
+
+    pub fn major_version(mut self, major_version: u16) -> Self {
+        self.major_version = major_version;
+        self
+    }
+
+    pub fn file_reference(mut self, entry: u64, sequence: u16) -> Self {
+        self.file_reference = MftReference::new(entry, sequence);
+        self
+    }
+
+    pub fn parent_reference(mut self, entry: u64, sequence: u16) -> Self {
+        self.parent_reference = MftReference::new(entry, sequence);
+        self
+    }
+
+    pub fn usn(mut self, usn: u64) -> Self {
+        self.usn = usn;
+        self
+    }
+
+    /// The timestamp as a FILETIME.
+    pub fn timestamp(mut self, timestamp: u64) -> Self {
+        self.timestamp = timestamp;
+        self
+    }
+
+    pub fn reason(mut self, reason: Reason) -> Self {
+        self.reason = reason.bits();
+        self
+    }
+
+    /// The raw reason value, which can have bits that are not defined flags.
+    pub fn raw_reason(mut self, reason: u32) -> Self {
+        self.reason = reason;
+        self
+    }
+
+    pub fn file_attributes(mut self, file_attributes: u32) -> Self {
+        self.file_attributes = file_attributes;
+        self
+    }
+
+    pub fn build(&self) -> Vec<u8> {
+        let name: Vec<u8> = self.name.encode_utf16().flat_map(|c| c.to_le_bytes().to_vec()).collect();
+        let name_offset = if self.major_version == 2 { 60 } else { 76 };
+        let record_length = (name_offset + name.len() + 7) & !7;
+
+        let file_reference = self.file_reference.entry | (u64::from(self.file_reference.sequence) << 48);
+        let parent_reference = self.parent_reference.entry | (u64::from(self.parent_reference.sequence) << 48);
+
+        let mut record = vec![0u8; record_length];
+        LittleEndian::write_u32(&mut record[0..4], record_length as u32);
+        LittleEndian::write_u16(&mut record[4..6], self.major_version);
+        if self.major_version == 2 {
+            LittleEndian::write_u64(&mut record[8..16], file_reference);
+            LittleEndian::write_u64(&mut record[16..24], parent_reference);
+            LittleEndian::write_u64(&mut record[24..32], self.usn);
+            LittleEndian::write_u64(&mut record[32..40], self.timestamp);
+            LittleEndian::write_u32(&mut record[40..44], self.reason);
+            LittleEndian::write_u32(&mut record[52..56], self.file_attributes);
+            LittleEndian::write_u16(&mut record[56..58], name.len() as u16);
+            LittleEndian::write_u16(&mut record[58..60], 60);
+        } else {
+            // 128 bit references, of which the low 64 bits are used
+            LittleEndian::write_u64(&mut record[8..16], file_reference);
+            LittleEndian::write_u64(&mut record[24..32], parent_reference);
+            LittleEndian::write_u64(&mut record[40..48], self.usn);
+            LittleEndian::write_u64(&mut record[48..56], self.timestamp);
+            LittleEndian::write_u32(&mut record[56..60], self.reason);
+            LittleEndian::write_u32(&mut record[68..72], self.file_attributes);
+            LittleEndian::write_u16(&mut record[72..74], name.len() as u16);
+            LittleEndian::write_u16(&mut record[74..76], 76);
+        }
+        record[name_offset..name_offset + name.len()].copy_from_slice(&name);
+        record
+    }
+
+    /// Parse the built record as an entry from `source` at `offset`.
+    pub fn entry(&self, source: &str, offset: u64) -> UsnEntry {
+        UsnEntry::new(
+            EntryMeta::new(source, offset),
+            self.major_version,
+            self.build().as_slice()
+        ).unwrap()
+    }
+}
@@ -0,0 +1,99 @@
+extern crate rusty_usn;
+mod common;
+use common::{RecordBuilder, FILETIME};
+use byteorder::{ByteOrder, LittleEndian};
+use chrono::{DateTime, NaiveDate, Utc};
+use rusty_usn::confidence::{ConfidenceCheck, ConfidenceScorer};
+use rusty_usn::filter::RecordFilter;
+use rusty_usn::record::{EntryMeta, UsnEntry};
+
+
+/// Build a V2 record with a valid layout for the name.
+fn build_record(timestamp: u64, reason: u32, name: &str) -> Vec<u8> {
+    RecordBuilder::new(name)
+        .file_reference(61346, 10)
+        .parent_reference(83529, 2)
+        .usn(558015304)
+        .timestamp(timestamp)
+        .raw_reason(reason)
+        .build()
+}
+
+fn entry(record: &[u8]) -> UsnEntry {
+    UsnEntry::new(EntryMeta::new("Test Buffer", 0), 2, record).unwrap()
+}
+
+fn date(year: i32) -> DateTime<Utc> {
+    DateTime::from_utc(NaiveDate::from_ymd(year, 1, 1).and_hms(0, 0, 0), Utc)
+}
+
+#[test]
+fn plausible_record_test() {
+    let record = build_record(FILETIME, 0x8000_0002, "lastalive0.dat");
+
+    let confidence = ConfidenceScorer::new().score(&entry(&record));
+    assert_eq!(confidence.score, 1.0);
+    assert!(confidence.failed_checks.is_empty());
+}
+
+#[test]
+fn implausible_record_test() {
+    // A timestamp in 1601, no reason and a control character in the name
+    let mut record = build_record(1, 0, "a\u{1}.txt");
+    // A sequence of 0
+    LittleEndian::write_u16(&mut record[14..16], 0);
+
+    let confidence = ConfidenceScorer::new().score(&entry(&record));
+    assert_eq!(confidence.failed_checks, vec![
+        ConfidenceCheck::Timestamp,
+        ConfidenceCheck::FlagBits,
+        ConfidenceCheck::PrintableName,
+        ConfidenceCheck::References,
+    ]);
+    assert_eq!(confidence.score, 0.2);
+}
+
+#[test]
+fn name_length_test() {
+    let mut record = build_record(FILETIME, 0x8000_0002, "lastalive0.dat");
+    // The record is 8 bytes longer than the name needs
+    record.extend_from_slice(&[0u8; 8]);
+    let record_length = record.len() as u32;
+    LittleEndian::write_u32(&mut record[0..4], record_length);
+
+    let confidence = ConfidenceScorer::new().score(&entry(&record));
+    assert_eq!(confidence.failed_checks, vec![ConfidenceCheck::NameLength]);
+    assert_eq!(confidence.score, 0.8);
+}
+
+#[test]
+fn scorer_settings_test() {
+    let record = build_record(FILETIME, 0x8000_0002, "lastalive0.dat");
+    let entry = entry(&record);
+
+    // The record is from 2019 and its file entry is 61346
+    let scorer = ConfidenceScorer::new()
+        .earliest(date(2020))
+        .max_entry(50000);
+    assert_eq!(scorer.score(&entry).failed_checks, vec![
+        ConfidenceCheck::Timestamp,
+        ConfidenceCheck::References,
+    ]);
+
+    let scorer = ConfidenceScorer::new()
+        .latest(date(2018));
+    assert_eq!(scorer.score(&entry).failed_checks, vec![ConfidenceCheck::Timestamp]);
+}
+
+#[test]
+fn min_confidence_filter_test() {
+    let plausible = entry(&build_record(FILETIME, 0x8000_0002, "lastalive0.dat"));
+    let implausible = entry(&build_record(1, 0x8000_0002, "lastalive0.dat"));
+
+    let filter = RecordFilter::new().min_confidence(ConfidenceScorer::new(), 0.9);
+    assert!(filter.matches(&plausible));
+    assert!(!filter.matches(&implausible));
+
+    let filter = RecordFilter::new().min_confidence(ConfidenceScorer::new(), 0.8);
+    assert!(filter.matches(&implausible));
+}
@@ -0,0 +1,83 @@
+extern crate rusty_usn;
+mod common;
+use common::V2_RECORD;
+use serde_json::{json, Value};
+use rusty_usn::dedup::{RecordSightings, Sighting, SightingTracker};
+use rusty_usn::output::{DedupWriter, JsonlWriter, OutputWriter};
+use rusty_usn::record::{EntryMeta, UsnEntry};
+
+
+fn entry(source: &str, offset: u64, usn: u64) -> UsnEntry {
+    let mut record_buffer = V2_RECORD.to_vec();
+    record_buffer[24..32].copy_from_slice(&usn.to_le_bytes());
+
+    UsnEntry::new(
+        EntryMeta::new(source, offset),
+        2,
+        record_buffer.as_slice()
+    ).unwrap()
+}
+
+#[test]
+fn sighting_tracker_test() {
+    let mut tracker = SightingTracker::new();
+
+    assert!(tracker.add(&entry("$J", 8192, 100)));
+    assert!(tracker.add(&entry("$J", 8288, 200)));
+    assert!(!tracker.add(&entry("unallocated", 40960, 100)));
+    assert!(!tracker.add(&entry("$J", 8192, 100)));
+
+    // Only the records found more than once are listed
+    assert_eq!(tracker.take_repeated(), vec![
+        RecordSightings {
+            source: "$J".to_string(),
+            offset: 8192,
+            snapshot: None,
+            sightings: vec![
+                Sighting { source: "$J".to_string(), offset: 8192, snapshot: None },
+                Sighting { source: "unallocated".to_string(), offset: 40960, snapshot: None },
+            ]
+        }
+    ]);
+}
+
+#[test]
+fn dedup_writer_test() {
+    let mut buffer: Vec<u8> = Vec::new();
+    let mut sightings_buffer: Vec<u8> = Vec::new();
+
+    {
+        let mut writer = DedupWriter::new(
+            Box::new(JsonlWriter::new(&mut buffer)),
+            Box::new(&mut sightings_buffer)
+        );
+        writer.write_entry(&entry("vss1/$J", 8192, 100), Some(json!({"full_name": "[root]/BTDevManager.log"}))).unwrap();
+        writer.write_entry(&entry("vss2/$J", 4096, 100), None).unwrap();
+        writer.write_entry(&entry("vss2/$J", 4192, 200), None).unwrap();
+        writer.flush().unwrap();
+    }
+
+    // Each record is written the first time it is found
+    let output = String::from_utf8(buffer).unwrap();
+    let lines: Vec<&str> = output.lines().collect();
+    assert_eq!(lines.len(), 2);
+
+    let value: Value = serde_json::from_str(lines[0]).unwrap();
+    assert_eq!(value["meta__source"], "vss1/$J");
+    assert_eq!(value["full_name"], "[root]/BTDevManager.log");
+    assert!(value.get("sightings").is_none());
+
+    let sightings = String::from_utf8(sightings_buffer).unwrap();
+    let lines: Vec<&str> = sightings.lines().collect();
+    assert_eq!(lines.len(), 1);
+
+    let value: Value = serde_json::from_str(lines[0]).unwrap();
+    assert_eq!(value, json!({
+        "meta__source": "vss1/$J",
+        "meta__offset": 8192,
+        "sightings": [
+            {"source": "vss1/$J", "offset": 8192},
+            {"source": "vss2/$J", "offset": 4096}
+        ]
+    }));
+}
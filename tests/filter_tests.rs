@@ -0,0 +1,69 @@
+extern crate rusty_usn;
+mod common;
+use common::v2_entry;
+use chrono::{TimeZone, Utc};
+use winstructs::ntfs::mft_reference::MftReference;
+use rusty_usn::flags;
+use rusty_usn::filter::RecordFilter;
+
+
+#[test]
+fn flag_from_name_test() {
+    assert_eq!(flags::Reason::from_name("USN_REASON_CLOSE"), Some(flags::Reason::USN_REASON_CLOSE));
+    assert_eq!(flags::Reason::from_name("file_delete"), Some(flags::Reason::USN_REASON_FILE_DELETE));
+    assert_eq!(flags::Reason::from_name("NOT_A_REASON"), None);
+
+    let attributes = flags::FileAttributes::from_name_list("DIRECTORY,hidden").unwrap();
+    assert_eq!(attributes.bits(), 0x12);
+    assert!(flags::SourceInfo::from_name_list("DATA_MANAGEMENT,bogus").is_err());
+}
+
+#[test]
+fn record_filter_test() {
+    let entry = v2_entry();
+
+    assert!(RecordFilter::new().matches(&entry));
+
+    let reason_filter = RecordFilter::new()
+        .reason(flags::Reason::USN_REASON_DATA_EXTEND | flags::Reason::USN_REASON_CLOSE);
+    assert!(reason_filter.matches(&entry));
+    let reason_filter = RecordFilter::new()
+        .reason(flags::Reason::USN_REASON_FILE_DELETE);
+    assert!(!reason_filter.matches(&entry));
+
+    let attribute_filter = RecordFilter::new()
+        .file_attributes(flags::FileAttributes::FILE_ATTRIBUTE_DIRECTORY);
+    assert!(!attribute_filter.matches(&entry));
+
+    let time_filter = RecordFilter::new()
+        .after(Utc.ymd(2013, 10, 19).and_hms(0, 0, 0))
+        .before(Utc.ymd(2013, 10, 20).and_hms(0, 0, 0));
+    assert!(time_filter.matches(&entry));
+    let time_filter = RecordFilter::new()
+        .after(Utc.ymd(2013, 10, 20).and_hms(0, 0, 0));
+    assert!(!time_filter.matches(&entry));
+
+    let usn_filter = RecordFilter::new()
+        .min_usn(20342374400)
+        .max_usn(20342374400);
+    assert!(usn_filter.matches(&entry));
+    let usn_filter = RecordFilter::new()
+        .min_usn(20342374401);
+    assert!(!usn_filter.matches(&entry));
+
+    let glob_filter = RecordFilter::new().name_glob("bt*.LOG").unwrap();
+    assert!(glob_filter.matches(&entry));
+    let glob_filter = RecordFilter::new().name_glob("*.exe").unwrap();
+    assert!(!glob_filter.matches(&entry));
+    let regex_filter = RecordFilter::new().name_regex(r"^BTDev\w+\.log$").unwrap();
+    assert!(regex_filter.matches(&entry));
+    assert!(RecordFilter::new().name_regex("(").is_err());
+
+    let reference_filter = RecordFilter::new()
+        .file_reference(MftReference::new(115, 37224))
+        .parent_reference(MftReference::new(141883, 7));
+    assert!(reference_filter.matches(&entry));
+    let reference_filter = RecordFilter::new()
+        .file_reference(MftReference::new(115, 1));
+    assert!(!reference_filter.matches(&entry));
+}
@@ -0,0 +1,66 @@
+extern crate rusty_usn;
+use serde_json::json;
+use rusty_usn::flags::{FileAttributes, FlagFormat, Reason};
+
+
+#[test]
+fn unknown_bits_test() {
+    // 0x40000000 is not a defined reason
+    let reason = Reason::from_bits_retain(0x8000_0002 | 0x4000_0000);
+    assert_eq!(reason.bits(), 0xC000_0002);
+    assert_eq!(reason.unknown_bits(), 0x4000_0000);
+    assert!(reason.contains(Reason::USN_REASON_CLOSE));
+    assert_eq!(reason.display_names(), vec![
+        "USN_REASON_CLOSE".to_string(),
+        "USN_REASON_DATA_EXTEND".to_string(),
+        "0x40000000".to_string()
+    ]);
+    assert_eq!(reason.display_short_names(), vec![
+        "CLOSE".to_string(),
+        "DATA_EXTEND".to_string(),
+        "0x40000000".to_string()
+    ]);
+}
+
+#[test]
+fn text_flag_format_test() {
+    let reason = Reason::USN_REASON_DATA_EXTEND | Reason::USN_REASON_CLOSE;
+    let unknown = FileAttributes::from_bits_retain(0x20 | 0x8000_0000);
+
+    // Flags are serialized as text
+    assert_eq!(serde_json::to_value(reason).unwrap(), json!("USN_REASON_CLOSE | USN_REASON_DATA_EXTEND"));
+    assert_eq!(serde_json::to_value(FileAttributes::empty()).unwrap(), json!("(empty)"));
+    assert_eq!(unknown.to_json_value(FlagFormat::Text), json!("FILE_ATTRIBUTE_ARCHIVE | 0x80000000"));
+}
+
+#[test]
+fn array_flag_format_test() {
+    let reason = Reason::USN_REASON_DATA_EXTEND | Reason::USN_REASON_CLOSE;
+
+    assert_eq!(reason.to_json_value(FlagFormat::Array), json!(["USN_REASON_CLOSE", "USN_REASON_DATA_EXTEND"]));
+    assert_eq!(FileAttributes::empty().to_json_value(FlagFormat::Array), json!([]));
+}
+
+#[test]
+fn short_flag_format_test() {
+    let reason = Reason::USN_REASON_DATA_EXTEND | Reason::USN_REASON_CLOSE;
+    let unknown = FileAttributes::from_bits_retain(0x20 | 0x8000_0000);
+
+    assert_eq!(reason.to_json_value(FlagFormat::Short), json!(["CLOSE", "DATA_EXTEND"]));
+    assert_eq!(unknown.to_json_value(FlagFormat::Short), json!(["ARCHIVE", "0x80000000"]));
+}
+
+#[test]
+fn integer_flag_format_test() {
+    let reason = Reason::USN_REASON_DATA_EXTEND | Reason::USN_REASON_CLOSE;
+    let unknown = FileAttributes::from_bits_retain(0x20 | 0x8000_0000);
+
+    assert_eq!(reason.to_json_value(FlagFormat::Integer), json!(0x8000_0002u32));
+    assert_eq!(unknown.to_json_value(FlagFormat::Integer), json!(0x8000_0020u32));
+}
+
+#[test]
+fn flag_format_name_test() {
+    assert_eq!(FlagFormat::from_name("SHORT"), Some(FlagFormat::Short));
+    assert_eq!(FlagFormat::from_name("names"), None);
+}
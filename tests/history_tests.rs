@@ -0,0 +1,89 @@
+extern crate rusty_usn;
+mod common;
+use common::{RecordBuilder, FILETIME};
+use winstructs::ntfs::mft_reference::MftReference;
+use rusty_usn::flags::Reason;
+use rusty_usn::history::RenameTracker;
+use rusty_usn::record::UsnEntry;
+
+
+/// Build a V2 record for a file with a sequence of 1.
+fn entry(usn: u64, file_entry: u64, parent_entry: u64, reason: Reason, name: &str) -> UsnEntry {
+    RecordBuilder::new(name)
+        .file_reference(file_entry, 1)
+        .parent_reference(parent_entry, 1)
+        .usn(usn)
+        .timestamp(FILETIME + usn)
+        .reason(reason)
+        .entry("$J", usn)
+}
+
+#[test]
+fn rename_history_test() {
+    let create = Reason::USN_REASON_FILE_CREATE | Reason::USN_REASON_CLOSE;
+    let old_name = Reason::USN_REASON_RENAME_OLD_NAME;
+    let new_name = Reason::USN_REASON_RENAME_NEW_NAME;
+
+    let mut tracker = RenameTracker::new();
+    let records = [
+        entry(100, 40, 5, create, "New Text Document.txt"),
+        entry(200, 40, 5, old_name, "New Text Document.txt"),
+        // Another file's record in between
+        entry(296, 41, 5, create, "other.txt"),
+        entry(392, 40, 5, new_name, "notes.txt"),
+        entry(500, 40, 5, old_name, "notes.txt"),
+        entry(600, 40, 36, new_name, "notes.txt"),
+    ];
+
+    for record in records.iter() {
+        tracker.add(record);
+    }
+
+    let history = tracker.get_history(&MftReference::new(40, 1)).unwrap();
+    assert!(history.created);
+    assert_eq!(history.first_name, "New Text Document.txt");
+    assert_eq!(history.changes.len(), 2);
+
+    let rename = &history.changes[0];
+    assert_eq!(rename.old_name, "New Text Document.txt");
+    assert_eq!(rename.old_usn, 200);
+    assert_eq!(rename.new_name, Some("notes.txt".to_string()));
+    assert_eq!(rename.usn, 392);
+    assert!(!rename.moved);
+
+    let moved = &history.changes[1];
+    assert_eq!(moved.old_parent.entry, 5);
+    assert_eq!(moved.new_parent, Some(MftReference::new(36, 1)));
+    assert!(moved.moved);
+
+    let names: Vec<(String, u64)> = history.names().into_iter()
+        .map(|name| (name.name, name.parent.entry))
+        .collect();
+    assert_eq!(names, vec![
+        ("New Text Document.txt".to_string(), 5),
+        ("notes.txt".to_string(), 5),
+        ("notes.txt".to_string(), 36),
+    ]);
+}
+
+#[test]
+fn unmatched_rename_test() {
+    let old_name = Reason::USN_REASON_RENAME_OLD_NAME;
+
+    let mut tracker = RenameTracker::new();
+    // The new name record is missing, so the next record does not match
+    tracker.add(&entry(100, 40, 5, old_name, "a.txt"));
+    tracker.add(&entry(200, 40, 5, Reason::USN_REASON_DATA_EXTEND, "b.txt"));
+    // The journal ends before the new name
+    tracker.add(&entry(300, 40, 5, old_name, "b.txt"));
+
+    let histories = tracker.finish();
+    assert_eq!(histories.len(), 1);
+
+    let changes = &histories[0].changes;
+    assert_eq!(changes.len(), 2);
+    assert_eq!(changes[0].old_name, "a.txt");
+    assert_eq!(changes[0].new_name, None);
+    assert_eq!(changes[1].old_name, "b.txt");
+    assert_eq!(changes[1].usn, 300);
+}
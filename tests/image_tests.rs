@@ -0,0 +1,618 @@
+extern crate rusty_usn;
+mod common;
+use common::V2_RECORD;
+use std::io;
+use std::io::{Cursor, Read, Seek, SeekFrom, Write};
+use byteorder::{ByteOrder, LittleEndian};
+use rusty_usn::image::ntfs::{decode_runlist, BootSector, DataRun, NtfsVolume};
+use rusty_usn::image::vss::VssVolume;
+use rusty_usn::image::ewf::{segment_extension, EwfImage};
+use flate2::Compression;
+use flate2::write::ZlibEncoder;
+use rusty_usn::mapping::FolderMapping;
+use rusty_usn::usn::UsnParser;
+
+const CLUSTER_SIZE: usize = 4096;
+const RECORD_SIZE: usize = 1024;
+const MFT_LCN: usize = 4;
+const J_LCN: usize = 16;
+
+
+fn utf16(name: &str) -> Vec<u8> {
+    name.encode_utf16().flat_map(|c| c.to_le_bytes().to_vec()).collect()
+}
+
+fn align8(length: usize) -> usize {
+    (length + 7) & !7
+}
+
+fn resident_attribute(type_code: u32, value: &[u8]) -> Vec<u8> {
+    let length = align8(24 + value.len());
+    let mut attribute = vec![0u8; length];
+    LittleEndian::write_u32(&mut attribute[0..4], type_code);
+    LittleEndian::write_u32(&mut attribute[4..8], length as u32);
+    LittleEndian::write_u16(&mut attribute[10..12], 24);
+    LittleEndian::write_u32(&mut attribute[16..20], value.len() as u32);
+    LittleEndian::write_u16(&mut attribute[20..22], 24);
+    attribute[24..24 + value.len()].copy_from_slice(value);
+    attribute
+}
+
+fn non_resident_attribute(type_code: u32, name: &str, runlist: &[u8], clusters: u64, size: u64) -> Vec<u8> {
+    let name = utf16(name);
+    let runlist_offset = align8(64 + name.len());
+    let length = align8(runlist_offset + runlist.len() + 1);
+    let mut attribute = vec![0u8; length];
+    LittleEndian::write_u32(&mut attribute[0..4], type_code);
+    LittleEndian::write_u32(&mut attribute[4..8], length as u32);
+    attribute[8] = 1;
+    attribute[9] = (name.len() / 2) as u8;
+    LittleEndian::write_u16(&mut attribute[10..12], 64);
+    LittleEndian::write_u64(&mut attribute[24..32], clusters - 1);
+    LittleEndian::write_u16(&mut attribute[32..34], runlist_offset as u16);
+    LittleEndian::write_u64(&mut attribute[40..48], clusters * CLUSTER_SIZE as u64);
+    LittleEndian::write_u64(&mut attribute[48..56], size);
+    LittleEndian::write_u64(&mut attribute[56..64], size);
+    attribute[64..64 + name.len()].copy_from_slice(&name);
+    attribute[runlist_offset..runlist_offset + runlist.len()].copy_from_slice(runlist);
+    attribute
+}
+
+fn file_name_attribute(name: &str, parent_entry: u64, parent_sequence: u16) -> Vec<u8> {
+    let name = utf16(name);
+    let mut value = vec![0u8; 66 + name.len()];
+    LittleEndian::write_u64(&mut value[0..8], parent_entry | (u64::from(parent_sequence) << 48));
+    value[64] = (name.len() / 2) as u8;
+    value[65] = 1;
+    value[66..].copy_from_slice(&name);
+    resident_attribute(0x30, &value)
+}
+
+/// Build an MFT record with fixups applied for 512 byte sectors.
+fn mft_record(entry: u32, sequence: u16, flags: u16, attributes: &[Vec<u8>]) -> Vec<u8> {
+    let mut record = vec![0u8; RECORD_SIZE];
+    record[0..4].copy_from_slice(b"FILE");
+    LittleEndian::write_u16(&mut record[4..6], 0x30);
+    LittleEndian::write_u16(&mut record[6..8], 3);
+    LittleEndian::write_u16(&mut record[16..18], sequence);
+    LittleEndian::write_u16(&mut record[18..20], 1);
+    LittleEndian::write_u16(&mut record[20..22], 0x38);
+    LittleEndian::write_u16(&mut record[22..24], flags);
+    LittleEndian::write_u32(&mut record[28..32], RECORD_SIZE as u32);
+    LittleEndian::write_u32(&mut record[44..48], entry);
+
+    let mut offset = 0x38;
+    for attribute in attributes {
+        record[offset..offset + attribute.len()].copy_from_slice(attribute);
+        offset += attribute.len();
+    }
+    LittleEndian::write_u32(&mut record[offset..offset + 4], 0xFFFF_FFFF);
+    LittleEndian::write_u32(&mut record[24..28], (offset + 8) as u32);
+
+    // Move the last two bytes of each sector into the update sequence array
+    LittleEndian::write_u16(&mut record[0x30..0x32], 1);
+    for sector in 0..2 {
+        let end = (sector + 1) * 512;
+        let original = [record[end - 2], record[end - 1]];
+        record[0x32 + sector * 2..0x34 + sector * 2].copy_from_slice(&original);
+        record[end - 2..end].copy_from_slice(&[1, 0]);
+    }
+
+    record
+}
+
+/// An NTFS volume with a $MFT of 8 clusters at cluster 4 and a $UsnJrnl:$J of
+/// 2 sparse clusters followed by a cluster at cluster 16.
+fn ntfs_image() -> Vec<u8> {
+    let mut image = vec![0u8; (J_LCN + 1) * CLUSTER_SIZE];
+
+    image[3..11].copy_from_slice(b"NTFS    ");
+    LittleEndian::write_u16(&mut image[11..13], 512);
+    image[13] = 8;
+    let total_sectors = (image.len() / 512) as u64;
+    LittleEndian::write_u64(&mut image[40..48], total_sectors);
+    LittleEndian::write_u64(&mut image[48..56], MFT_LCN as u64);
+    image[64] = 0xF6;
+
+    let mft_clusters = 8;
+    let mft_size = (mft_clusters * CLUSTER_SIZE) as u64;
+    let records = vec![
+        (0, mft_record(0, 1, 0x01, &[
+            file_name_attribute("$MFT", 5, 5),
+            non_resident_attribute(0x80, "", &[0x11, mft_clusters as u8, MFT_LCN as u8, 0x00], mft_clusters as u64, mft_size)
+        ])),
+        (5, mft_record(5, 5, 0x03, &[file_name_attribute(".", 5, 5)])),
+        (11, mft_record(11, 11, 0x03, &[file_name_attribute("$Extend", 5, 5)])),
+        (30, mft_record(30, 2, 0x01, &[
+            file_name_attribute("$UsnJrnl", 11, 11),
+            non_resident_attribute(0x80, "$J", &[0x01, 0x02, 0x11, 0x01, J_LCN as u8, 0x00], 3, 3 * CLUSTER_SIZE as u64)
+        ])),
+    ];
+
+    for (entry, record) in records {
+        let offset = MFT_LCN * CLUSTER_SIZE + entry * RECORD_SIZE;
+        image[offset..offset + RECORD_SIZE].copy_from_slice(&record);
+    }
+
+    let offset = J_LCN * CLUSTER_SIZE;
+    image[offset..offset + V2_RECORD.len()].copy_from_slice(V2_RECORD);
+
+    image
+}
+
+const VSS_IDENTIFIER: &[u8] = &[
+    0x6B,0x87,0x08,0x38,0x76,0xC1,0x48,0x4E,0xB7,0xAE,0x04,0x04,0x6E,0x6C,0xC7,0x52
+];
+const VSS_BLOCK_SIZE: usize = 0x4000;
+// 2019-03-20 00:00:00 as a FILETIME
+const FILETIME: u64 = 131975136000000000;
+
+/// A shadow copy store and its block descriptors of original offset, relative
+/// offset, store offset, flags and sector bitmap.
+struct Store {
+    id: u8,
+    creation_time: u64,
+    block_list_offset: usize,
+    descriptors: Vec<(u64, u64, u64, u32, u32)>,
+}
+
+fn vss_block(image: &mut [u8], offset: usize, record_type: u32) -> &mut [u8] {
+    let block = &mut image[offset..offset + VSS_BLOCK_SIZE];
+    block[0..16].copy_from_slice(VSS_IDENTIFIER);
+    LittleEndian::write_u32(&mut block[16..20], 1);
+    LittleEndian::write_u32(&mut block[20..24], record_type);
+    block
+}
+
+/// Write a VSS volume header, a catalog block and the block list of each store.
+fn write_vss(image: &mut [u8], catalog_offset: usize, stores: &[Store]) {
+    let volume_size = image.len() as u64;
+
+    let header = &mut image[0x1E00..0x2000];
+    header[0..16].copy_from_slice(VSS_IDENTIFIER);
+    LittleEndian::write_u32(&mut header[16..20], 1);
+    LittleEndian::write_u32(&mut header[20..24], 1);
+    LittleEndian::write_u64(&mut header[48..56], catalog_offset as u64);
+
+    let catalog = vss_block(image, catalog_offset, 2);
+    for (index, store) in stores.iter().enumerate() {
+        let entry = &mut catalog[128 + index * 256..128 + index * 256 + 128];
+        LittleEndian::write_u64(&mut entry[0..8], 2);
+        LittleEndian::write_u64(&mut entry[8..16], volume_size);
+        entry[16..32].copy_from_slice(&[store.id; 16]);
+        LittleEndian::write_u64(&mut entry[48..56], store.creation_time);
+
+        let entry = &mut catalog[256 + index * 256..256 + index * 256 + 128];
+        LittleEndian::write_u64(&mut entry[0..8], 3);
+        LittleEndian::write_u64(&mut entry[8..16], store.block_list_offset as u64);
+        entry[16..32].copy_from_slice(&[store.id; 16]);
+    }
+
+    for store in stores {
+        let block_list = vss_block(image, store.block_list_offset, 3);
+        for (index, descriptor) in store.descriptors.iter().enumerate() {
+            let entry = &mut block_list[128 + index * 32..128 + index * 32 + 32];
+            LittleEndian::write_u64(&mut entry[0..8], descriptor.0);
+            LittleEndian::write_u64(&mut entry[8..16], descriptor.1);
+            LittleEndian::write_u64(&mut entry[16..24], descriptor.2);
+            LittleEndian::write_u32(&mut entry[24..28], descriptor.3);
+            LittleEndian::write_u32(&mut entry[28..32], descriptor.4);
+        }
+    }
+}
+
+#[test]
+fn decode_runlist_test() {
+    let runlist: &[u8] = &[0x21, 0x10, 0x00, 0x01, 0x11, 0x08, 0xF0, 0x01, 0x04, 0x00];
+
+    assert_eq!(decode_runlist(runlist, 0).unwrap(), vec![
+        DataRun { vcn: 0, lcn: Some(256), length: 16 },
+        DataRun { vcn: 16, lcn: Some(240), length: 8 },
+        DataRun { vcn: 24, lcn: None, length: 4 },
+    ]);
+}
+
+#[test]
+fn corrupt_runlist_test() {
+    // A negative lcn
+    assert!(decode_runlist(&[0x11, 0x01, 0xFF, 0x00], 0).is_err());
+
+    // An lcn that overflows
+    let runlist: &[u8] = &[
+        0x81, 0x01, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x7F,
+        0x11, 0x01, 0x01, 0x00
+    ];
+    assert!(decode_runlist(runlist, 0).is_err());
+
+    // A length that overflows the vcn
+    let runlist: &[u8] = &[0x08, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x00];
+    assert!(decode_runlist(runlist, 1).is_err());
+}
+
+#[test]
+fn boot_sector_test() {
+    let image = ntfs_image();
+    let boot_sector = BootSector::from_buffer(&image[0..512]).unwrap();
+
+    assert_eq!(boot_sector.cluster_size(), 4096);
+    assert_eq!(boot_sector.mft_lcn, 4);
+    assert_eq!(boot_sector.mft_record_size, 1024);
+
+    assert!(BootSector::from_buffer(&[0u8; 512]).is_err());
+}
+
+#[test]
+fn corrupt_boot_sector_test() {
+    // A sectors per cluster exponent of 112
+    let mut boot_sector = ntfs_image()[0..512].to_vec();
+    boot_sector[13] = 0x90;
+    assert!(BootSector::from_buffer(&boot_sector).is_err());
+
+    // A record size exponent of 100
+    let mut boot_sector = ntfs_image()[0..512].to_vec();
+    boot_sector[64] = -100i8 as u8;
+    assert!(BootSector::from_buffer(&boot_sector).is_err());
+}
+
+#[test]
+fn usn_journal_stream_test() {
+    let volume = NtfsVolume::new(Cursor::new(ntfs_image()), 0).unwrap();
+    let mut stream = volume.into_usn_journal_stream().unwrap();
+
+    assert_eq!(stream.size(), 3 * 4096);
+    assert_eq!(stream.data_regions().unwrap(), vec![8192..12288]);
+
+    // The sparse clusters read as zeros
+    let mut buffer = vec![0xFFu8; 8192];
+    stream.read_exact(&mut buffer).unwrap();
+    assert!(buffer.iter().all(|byte| *byte == 0));
+
+    let mut buffer = vec![0u8; V2_RECORD.len()];
+    stream.read_exact(&mut buffer).unwrap();
+    assert_eq!(buffer, V2_RECORD);
+
+    assert_eq!(stream.seek(SeekFrom::End(0)).unwrap(), 3 * 4096);
+    assert_eq!(stream.read(&mut buffer).unwrap(), 0);
+}
+
+#[test]
+fn usn_parser_image_test() {
+    // The volume starts after a 1 MB partition gap
+    let mut image = vec![0u8; 1048576];
+    image.extend(ntfs_image());
+
+    let volume = NtfsVolume::new(Cursor::new(image), 1048576).unwrap();
+    let stream = volume.into_usn_journal_stream().unwrap();
+
+    let mut parser = UsnParser::from_read_seek("Test Image".to_string(), stream).unwrap();
+    let records: Vec<_> = parser.records().collect();
+
+    assert_eq!(records.len(), 1);
+    assert_eq!(records[0].meta.offset, 8192);
+    assert_eq!(records[0].record.get_usn(), 20342374400);
+}
+
+#[test]
+fn image_folder_mapping_test() {
+    let volume = NtfsVolume::new(Cursor::new(ntfs_image()), 0).unwrap();
+    let mut mft_parser = volume.into_mft_parser().unwrap();
+
+    let mut mapping = FolderMapping::new();
+    mapping.build_folder_mapping(&mut mft_parser);
+
+    assert_eq!(mapping.enumerate_path(11, 11), Some("[root]/$Extend".to_string()));
+}
+
+#[test]
+fn vss_snapshot_test() {
+    let mut volume = vec![0u8; 0x40000];
+    for (offset, value) in [(0x20000, b'C'), (0x24000, b'C'), (0x28000, b'C'), (0x2C000, b'F'),
+        (0x30000, b'A'), (0x34000, b'a'), (0x38000, b'B')].iter() {
+        for byte in volume[*offset..*offset + VSS_BLOCK_SIZE].iter_mut() {
+            *byte = *value;
+        }
+    }
+
+    // The newer store is first in the catalog
+    write_vss(&mut volume, 0x4000, &[
+        Store { id: 2, creation_time: FILETIME + 10000000, block_list_offset: 0xC000, descriptors: vec![
+            (0x24000, 0, 0x38000, 0, 0),
+            // The block was moved
+            (0x28000, 0x2C000, 0, 1, 0),
+        ]},
+        Store { id: 1, creation_time: FILETIME, block_list_offset: 0x8000, descriptors: vec![
+            (0x20000, 0, 0x30000, 0, 0),
+            // Only the first sector
+            (0x24000, 0, 0x34000, 2, 1),
+        ]},
+    ]);
+
+    let vss_volume = VssVolume::new(Cursor::new(volume.clone()), 0).unwrap();
+    let identifiers: Vec<String> = vss_volume.shadow_copies().iter()
+        .map(|shadow_copy| shadow_copy.identifier.to_string())
+        .collect();
+    assert_eq!(identifiers, vec![
+        "01010101-0101-0101-0101-010101010101".to_string(),
+        "02020202-0202-0202-0202-020202020202".to_string(),
+    ]);
+
+    let read_block = |index: usize, offset: u64| -> Vec<u8> {
+        let mut snapshot = VssVolume::new(Cursor::new(volume.clone()), 0).unwrap()
+            .into_snapshot(index).unwrap();
+        let mut block = vec![0u8; VSS_BLOCK_SIZE];
+        snapshot.seek(SeekFrom::Start(offset)).unwrap();
+        snapshot.read_exact(&mut block).unwrap();
+        block
+    };
+
+    let oldest = read_block(0, 0x20000);
+    assert!(oldest.iter().all(|byte| *byte == b'A'));
+    // The overlay's sector, then the rest from the newer store
+    let oldest = read_block(0, 0x24000);
+    assert!(oldest[..512].iter().all(|byte| *byte == b'a'));
+    assert!(oldest[512..].iter().all(|byte| *byte == b'B'));
+    let oldest = read_block(0, 0x28000);
+    assert!(oldest.iter().all(|byte| *byte == b'F'));
+
+    let newest = read_block(1, 0x20000);
+    assert!(newest.iter().all(|byte| *byte == b'C'));
+    let newest = read_block(1, 0x24000);
+    assert!(newest.iter().all(|byte| *byte == b'B'));
+
+    // Reads across blocks
+    let mut snapshot = VssVolume::new(Cursor::new(volume), 0).unwrap()
+        .into_snapshot(0).unwrap();
+    let mut buffer = vec![0u8; 2];
+    snapshot.seek(SeekFrom::Start(0x1FFFF)).unwrap();
+    snapshot.read_exact(&mut buffer).unwrap();
+    assert_eq!(buffer, vec![0, b'A']);
+    assert_eq!(snapshot.seek(SeekFrom::End(0)).unwrap(), 0x40000);
+    assert!(snapshot.read(&mut buffer).unwrap() == 0);
+}
+
+#[test]
+fn vss_usn_journal_test() {
+    // The $J cluster is in the block at 0x10000, a copy with an older record is
+    // in the store
+    let mut volume = ntfs_image();
+    volume.resize(0x20000, 0);
+    let (current, store) = volume.split_at_mut(0x1C000);
+    store.copy_from_slice(&current[0x10000..0x14000]);
+    LittleEndian::write_u64(&mut volume[0x1C000 + 24..0x1C000 + 32], 20342374000);
+
+    write_vss(&mut volume, 0x14000, &[
+        Store { id: 1, creation_time: FILETIME, block_list_offset: 0x18000, descriptors: vec![
+            (0x10000, 0, 0x1C000, 0, 0),
+        ]},
+    ]);
+
+    // The volume starts after a 1 MB partition gap
+    let mut image = vec![0u8; 1048576];
+    image.extend(volume);
+
+    assert!(VssVolume::new(Cursor::new(ntfs_image()), 0).is_err());
+
+    let snapshot = VssVolume::new(Cursor::new(image.clone()), 1048576).unwrap()
+        .into_snapshot(0).unwrap();
+    let identifier = snapshot.identifier().to_string();
+    let stream = NtfsVolume::new(snapshot, 0).unwrap()
+        .into_usn_journal_stream().unwrap();
+
+    let mut parser = UsnParser::from_read_seek("Test Image".to_string(), stream).unwrap()
+        .with_snapshot(identifier.clone());
+    let records: Vec<_> = parser.records().collect();
+
+    assert_eq!(records.len(), 1);
+    assert_eq!(records[0].record.get_usn(), 20342374000);
+    assert_eq!(records[0].meta.snapshot, Some(identifier));
+    assert_eq!(
+        records[0].meta.to_json_value().unwrap()["meta__snapshot"],
+        "01010101-0101-0101-0101-010101010101"
+    );
+
+    // The current volume still has the newer record
+    let mut parser = UsnParser::from_read_seek(
+        "Test Image".to_string(),
+        NtfsVolume::new(Cursor::new(image), 1048576).unwrap().into_usn_journal_stream().unwrap()
+    ).unwrap();
+    let records: Vec<_> = parser.records().collect();
+    assert_eq!(records[0].record.get_usn(), 20342374400);
+    assert_eq!(records[0].meta.snapshot, None);
+}
+
+fn ewf_section(segment: &mut Vec<u8>, section_type: &str, data: &[u8], is_last: bool) {
+    let offset = segment.len();
+    let size = 76 + data.len();
+
+    let mut descriptor = vec![0u8; 76];
+    descriptor[..section_type.len()].copy_from_slice(section_type.as_bytes());
+    let next_offset = if is_last { offset } else { offset + size };
+    LittleEndian::write_u64(&mut descriptor[16..24], next_offset as u64);
+    LittleEndian::write_u64(&mut descriptor[24..32], size as u64);
+
+    segment.extend(descriptor);
+    segment.extend_from_slice(data);
+}
+
+/// Split media into EWF segments of 32 KB chunks, with every other chunk stored
+/// uncompressed.
+fn ewf_segments(media: &[u8], chunks_per_segment: usize) -> Vec<Vec<u8>> {
+    let chunk_size = 32768;
+    let chunks: Vec<&[u8]> = media.chunks(chunk_size).collect();
+    let segment_chunks: Vec<_> = chunks.chunks(chunks_per_segment).collect();
+
+    let mut segments = Vec::new();
+    for (index, chunks) in segment_chunks.iter().enumerate() {
+        let mut segment = b"EVF\x09\x0D\x0A\xFF\x00\x01".to_vec();
+        segment.extend(&((index + 1) as u16).to_le_bytes());
+        segment.extend(&[0, 0]);
+
+        if index == 0 {
+            let mut volume = vec![0u8; 1052];
+            LittleEndian::write_u32(&mut volume[4..8], (media.len() / chunk_size + 1) as u32);
+            LittleEndian::write_u32(&mut volume[8..12], 64);
+            LittleEndian::write_u32(&mut volume[12..16], 512);
+            LittleEndian::write_u64(&mut volume[16..24], (media.len() / 512) as u64);
+            ewf_section(&mut segment, "header", &[0u8; 32], false);
+            ewf_section(&mut segment, "volume", &volume, false);
+        }
+
+        // The table's offsets are relative to the start of the sectors section
+        let base_offset = segment.len();
+        let mut sectors = Vec::new();
+        let mut entries = Vec::new();
+        for (chunk_index, chunk) in chunks.iter().enumerate() {
+            let offset = (76 + sectors.len()) as u32;
+            if chunk_index % 2 == 0 {
+                let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+                encoder.write_all(chunk).unwrap();
+                sectors.extend(encoder.finish().unwrap());
+                entries.push(offset | 0x8000_0000);
+            } else {
+                sectors.extend_from_slice(chunk);
+                sectors.extend(&[0xAA, 0xBB, 0xCC, 0xDD]);
+                entries.push(offset);
+            }
+        }
+        ewf_section(&mut segment, "sectors", &sectors, false);
+
+        let mut table = vec![0u8; 24];
+        LittleEndian::write_u32(&mut table[0..4], entries.len() as u32);
+        LittleEndian::write_u64(&mut table[8..16], base_offset as u64);
+        for entry in entries {
+            table.extend(&entry.to_le_bytes());
+        }
+        table.extend(&[0, 0, 0, 0]);
+        ewf_section(&mut segment, "table", &table, false);
+        ewf_section(&mut segment, "table2", &table, false);
+
+        let is_last = index + 1 == segment_chunks.len();
+        ewf_section(&mut segment, if is_last { "done" } else { "next" }, &[], true);
+
+        segments.push(segment);
+    }
+
+    segments
+}
+
+#[test]
+fn ewf_segment_extension_test() {
+    assert_eq!(segment_extension("E01", 2), Some("E02".to_string()));
+    assert_eq!(segment_extension("E01", 99), Some("E99".to_string()));
+    assert_eq!(segment_extension("E01", 100), Some("EAA".to_string()));
+    assert_eq!(segment_extension("E01", 101), Some("EAB".to_string()));
+    assert_eq!(segment_extension("E01", 126), Some("EBA".to_string()));
+    assert_eq!(segment_extension("E01", 775), Some("EZZ".to_string()));
+    assert_eq!(segment_extension("e01", 776), Some("faa".to_string()));
+    assert_eq!(segment_extension("E01", 100 + 22 * 676), None);
+}
+
+#[test]
+fn ewf_image_test() {
+    // 17 clusters are 2 full chunks and a partial chunk
+    let media = ntfs_image();
+    let segments: Vec<_> = ewf_segments(&media, 2).into_iter()
+        .map(Cursor::new)
+        .collect();
+    assert_eq!(segments.len(), 2);
+
+    let mut image = EwfImage::new(segments).unwrap();
+    assert_eq!(image.size(), media.len() as u64);
+    assert_eq!(image.media().chunk_size(), 32768);
+
+    let mut buffer = Vec::new();
+    image.read_to_end(&mut buffer).unwrap();
+    assert!(buffer == media);
+
+    // Reads across the compressed and uncompressed chunks
+    let mut buffer = vec![0u8; 8];
+    image.seek(SeekFrom::Start(32764)).unwrap();
+    image.read_exact(&mut buffer).unwrap();
+    assert_eq!(buffer, &media[32764..32772]);
+
+    // The segments must be in order
+    let mut segments: Vec<_> = ewf_segments(&media, 2).into_iter()
+        .map(Cursor::new)
+        .collect();
+    segments.reverse();
+    assert!(EwfImage::new(segments).is_err());
+}
+
+#[test]
+fn ewf_corrupt_image_test() {
+    let open = |segment: Vec<u8>| EwfImage::new(vec![Cursor::new(segment)]);
+    // The header section is at 13, the volume section at 121 and the sectors
+    // section at 1249
+    let segment = ewf_segments(&ntfs_image(), 3).remove(0);
+    assert!(open(segment.clone()).is_ok());
+
+    // A chunk of 8 GB
+    let mut corrupt = segment.clone();
+    LittleEndian::write_u32(&mut corrupt[121 + 76 + 8..121 + 76 + 12], 0x0100_0000);
+    assert!(open(corrupt).is_err());
+
+    // A media size that overflows
+    let mut corrupt = segment.clone();
+    LittleEndian::write_u64(&mut corrupt[121 + 76 + 16..121 + 76 + 24], u64::MAX);
+    assert!(open(corrupt).is_err());
+
+    // A table base offset that overflows with the chunk offsets, the table
+    // section follows the sectors section
+    let sectors_size = LittleEndian::read_u64(&segment[1249 + 24..1249 + 32]) as usize;
+    let table = 1249 + sectors_size;
+    let mut corrupt = segment.clone();
+    LittleEndian::write_u64(&mut corrupt[table + 76 + 8..table + 76 + 16], u64::MAX);
+    assert!(open(corrupt).is_err());
+
+    // A volume section that is larger than the segment
+    let mut corrupt = segment.clone();
+    LittleEndian::write_u64(&mut corrupt[121 + 24..121 + 32], 1 << 40);
+    assert!(open(corrupt).is_err());
+
+    // A sectors section that makes the last chunk larger than a chunk can be
+    let mut corrupt = segment;
+    LittleEndian::write_u64(&mut corrupt[1249 + 24..1249 + 32], 1 << 40);
+    let mut image = open(corrupt).unwrap();
+    let mut buffer = vec![0u8; 8];
+    image.seek(SeekFrom::Start(2 * 32768)).unwrap();
+    assert_eq!(image.read_exact(&mut buffer).unwrap_err().kind(), io::ErrorKind::InvalidData);
+}
+
+#[test]
+fn ewf_usn_journal_test() {
+    let segments: Vec<_> = ewf_segments(&ntfs_image(), 1).into_iter()
+        .map(Cursor::new)
+        .collect();
+    let image = EwfImage::new(segments).unwrap();
+
+    let stream = NtfsVolume::new(image, 0).unwrap()
+        .into_usn_journal_stream().unwrap();
+    let mut parser = UsnParser::from_read_seek("Test E01".to_string(), stream).unwrap();
+    let records: Vec<_> = parser.records().collect();
+
+    assert_eq!(records.len(), 1);
+    assert_eq!(records[0].record.get_usn(), 20342374400);
+}
+
+#[test]
+fn ewf_from_path_test() {
+    let directory = std::env::temp_dir().join(format!("rusty_usn_ewf_{}", std::process::id()));
+    std::fs::create_dir_all(&directory).unwrap();
+
+    let media = ntfs_image();
+    for (index, segment) in ewf_segments(&media, 1).iter().enumerate() {
+        let path = directory.join(format!("image.E{:02}", index + 1));
+        std::fs::write(path, segment).unwrap();
+    }
+
+    let first_segment = directory.join("image.E01");
+    let first_segment = first_segment.to_str().unwrap();
+    assert!(rusty_usn::image::ewf::is_ewf_file(first_segment));
+
+    let mut image = rusty_usn::image::open_image(first_segment).unwrap();
+    let mut buffer = Vec::new();
+    image.read_to_end(&mut buffer).unwrap();
+
+    std::fs::remove_dir_all(&directory).unwrap();
+    assert!(buffer == media);
+}
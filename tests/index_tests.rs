@@ -0,0 +1,82 @@
+extern crate rusty_usn;
+use winstructs::ntfs::mft_reference::MftReference;
+use chrono::{TimeZone, Utc};
+use rusty_usn::index::{FileEntry, FileIndex, MacbTimes};
+use rusty_usn::mapping::EntryMapping;
+
+
+fn file_entry(entry: u64, sequence: u16, allocated: bool, names: &[(&str, MftReference)]) -> FileEntry {
+    FileEntry {
+        reference: MftReference::new(entry, sequence),
+        allocated,
+        is_dir: false,
+        names: names.iter()
+            .map(|(name, parent)| EntryMapping {
+                name: name.to_string(),
+                parent: *parent
+            })
+            .collect(),
+        standard_info: None,
+        file_name_times: None,
+        logical_size: None
+    }
+}
+
+fn test_index() -> FileIndex {
+    let root = MftReference::new(5, 5);
+    let users = MftReference::new(100, 1);
+
+    let mut index = FileIndex::new();
+    index.add_entry(file_entry(5, 5, true, &[(".", root)]));
+    index.add_entry(file_entry(100, 1, true, &[("Users", root)]));
+    index.add_entry(file_entry(200, 3, true, &[("report.docx", users), ("link.docx", root)]));
+    index.add_entry(file_entry(300, 2, false, &[("deleted.txt", users)]));
+    index
+}
+
+#[test]
+fn file_index_paths_test() {
+    let index = test_index();
+
+    let file_reference = MftReference::new(200, 3);
+    assert_eq!(index.full_path(&file_reference), Some("[root]/Users/report.docx".to_string()));
+    assert_eq!(index.full_paths(&file_reference), vec!["[root]/Users/report.docx", "[root]/link.docx"]);
+    assert_eq!(index.full_paths(&MftReference::new(5, 5)), vec!["[root]"]);
+}
+
+#[test]
+fn file_index_exists_test() {
+    let index = test_index();
+
+    assert!(index.exists(&MftReference::new(200, 3)));
+
+    // The entry has been reused by another file
+    assert!(!index.exists(&MftReference::new(200, 2)));
+    assert_eq!(index.full_path(&MftReference::new(200, 2)), None);
+
+    // The entry is no longer allocated, but its name is still known
+    assert!(!index.exists(&MftReference::new(300, 2)));
+    assert_eq!(index.full_path(&MftReference::new(300, 2)), Some("[root]/Users/deleted.txt".to_string()));
+}
+
+#[test]
+fn file_index_enrichment_test() {
+    let mut index = test_index();
+
+    let created = Utc.ymd(2019, 3, 20).and_hms(21, 35, 52);
+    let modified = Utc.ymd(2019, 3, 21).and_hms(8, 0, 0);
+    let mut entry = file_entry(400, 7, true, &[("lastalive0.dat", MftReference::new(100, 1))]);
+    entry.standard_info = Some(MacbTimes { created, modified, mft_modified: modified, accessed: modified });
+    entry.logical_size = Some(2048);
+    index.add_entry(entry);
+
+    let enrichment = index.get_enrichment(&MftReference::new(400, 7)).unwrap();
+    assert!(enrichment.mft_allocated);
+    assert_eq!(enrichment.mft_logical_size, Some(2048));
+    assert_eq!(enrichment.mft_si_created, Some(created));
+    assert_eq!(enrichment.mft_si_modified, Some(modified));
+    assert_eq!(enrichment.mft_fn_created, None);
+
+    // The entry must have the same sequence
+    assert!(index.get_enrichment(&MftReference::new(400, 6)).is_none());
+}
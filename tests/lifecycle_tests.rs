@@ -0,0 +1,83 @@
+extern crate rusty_usn;
+mod common;
+use common::{RecordBuilder, FILETIME, SECOND};
+use winstructs::ntfs::mft_reference::MftReference;
+use rusty_usn::flags::Reason;
+use rusty_usn::index::{FileEntry, FileIndex};
+use rusty_usn::lifecycle::LifecycleTracker;
+use rusty_usn::record::UsnEntry;
+
+
+/// Build a V2 record with a timestamp of `seconds` after FILETIME.
+fn entry(usn: u64, file_reference: MftReference, reason: Reason, name: &str, seconds: u64) -> UsnEntry {
+    RecordBuilder::new(name)
+        .file_reference(file_reference.entry, file_reference.sequence)
+        .usn(usn)
+        .timestamp(FILETIME + seconds * SECOND)
+        .reason(reason)
+        .entry("$J", usn)
+}
+
+#[test]
+fn lifecycle_test() {
+    let create = Reason::USN_REASON_FILE_CREATE;
+    let delete = Reason::USN_REASON_FILE_DELETE | Reason::USN_REASON_CLOSE;
+    let rename = Reason::USN_REASON_RENAME_NEW_NAME;
+    let extend = Reason::USN_REASON_DATA_EXTEND;
+
+    let temp_file = MftReference::new(50, 1);
+    let reused = MftReference::new(50, 2);
+    let old_file = MftReference::new(60, 4);
+
+    let lifetimes = LifecycleTracker::track(vec![
+        entry(100, temp_file, create, "~tmp1234.tmp", 0),
+        entry(200, old_file, extend, "existing.log", 1),
+        entry(300, temp_file, rename, "invoice.pdf", 2),
+        entry(400, temp_file, delete, "invoice.pdf", 90),
+        entry(500, reused, create, "new.txt", 100),
+    ].into_iter(), None);
+
+    assert_eq!(lifetimes.len(), 3);
+
+    let deleted = &lifetimes[0];
+    assert_eq!(deleted.file_reference, temp_file);
+    assert_eq!(deleted.names, vec!["~tmp1234.tmp".to_string(), "invoice.pdf".to_string()]);
+    assert_eq!(deleted.duration_seconds, Some(90.0));
+    assert!(deleted.entry_reused);
+
+    // Created before the journal starts and still exists
+    let existing = &lifetimes[1];
+    assert_eq!(existing.file_reference, old_file);
+    assert!(existing.created.is_none());
+    assert!(existing.deleted.is_none());
+    assert!(!existing.entry_reused);
+
+    let new_file = &lifetimes[2];
+    assert_eq!(new_file.file_reference, reused);
+    assert!(new_file.created.is_some());
+    assert!(!new_file.entry_reused);
+}
+
+#[test]
+fn lifecycle_file_index_test() {
+    let file_reference = MftReference::new(70, 1);
+
+    let mut file_index = FileIndex::new();
+    file_index.add_entry(FileEntry {
+        reference: MftReference::new(70, 3),
+        allocated: true,
+        is_dir: false,
+        names: Vec::new(),
+        standard_info: None,
+        file_name_times: None,
+        logical_size: None,
+    });
+
+    let lifetimes = LifecycleTracker::track(vec![
+        entry(100, file_reference, Reason::USN_REASON_FILE_CREATE, "a.txt", 0),
+        entry(200, file_reference, Reason::USN_REASON_FILE_DELETE, "a.txt", 1),
+    ].into_iter(), Some(&file_index));
+
+    assert_eq!(lifetimes.len(), 1);
+    assert!(lifetimes[0].entry_reused);
+}
@@ -0,0 +1,176 @@
+extern crate rusty_usn;
+mod common;
+use common::RecordBuilder;
+use std::io::Cursor;
+use byteorder::{ByteOrder, LittleEndian};
+use rusty_usn::flags::Reason;
+use rusty_usn::logfile::{apply_fixups, LogFileParser};
+
+const PAGE_SIZE: usize = 4096;
+const SEQ_NUMBER_BITS: u32 = 44;
+
+
+/// The LSN of a log record at a file offset.
+fn lsn(offset: usize) -> u64 {
+    (1 << (64 - SEQ_NUMBER_BITS)) | (offset as u64 >> 3)
+}
+
+/// Write the update sequence array of a page, the reverse of the fixups.
+fn protect(page: &mut [u8], usa_offset: usize) {
+    let usa_count = page.len() / 512 + 1;
+    LittleEndian::write_u16(&mut page[4..6], usa_offset as u16);
+    LittleEndian::write_u16(&mut page[6..8], usa_count as u16);
+    LittleEndian::write_u16(&mut page[usa_offset..usa_offset + 2], 0x0007);
+
+    for sector in 1..usa_count {
+        let sector_end = sector * 512;
+        let fixup = usa_offset + sector * 2;
+        page[fixup] = page[sector_end - 2];
+        page[fixup + 1] = page[sector_end - 1];
+        page[sector_end - 2] = 0x07;
+        page[sector_end - 1] = 0x00;
+    }
+}
+
+fn restart_page() -> Vec<u8> {
+    let mut page = vec![0u8; PAGE_SIZE];
+    page[0..4].copy_from_slice(b"RSTR");
+    LittleEndian::write_u32(&mut page[0x10..0x14], PAGE_SIZE as u32);
+    LittleEndian::write_u32(&mut page[0x14..0x18], PAGE_SIZE as u32);
+    LittleEndian::write_u16(&mut page[0x18..0x1A], 0x30);
+    LittleEndian::write_u32(&mut page[0x30 + 0x10..0x30 + 0x14], SEQ_NUMBER_BITS);
+    LittleEndian::write_u16(&mut page[0x30 + 0x24..0x30 + 0x26], 0x30);
+    LittleEndian::write_u16(&mut page[0x30 + 0x26..0x30 + 0x28], 0x40);
+    protect(&mut page, 0x1E);
+    page
+}
+
+fn usn_record(major_version: u16, usn: u64, name: &str) -> Vec<u8> {
+    RecordBuilder::new(name)
+        .major_version(major_version)
+        .usn(usn)
+        .reason(Reason::USN_REASON_FILE_CREATE)
+        .build()
+}
+
+/// Build a log record at a file offset whose redo data is a write of USN records.
+fn log_record(offset: usize, redo_data: &[u8]) -> Vec<u8> {
+    let client_data_length = 0x20 + redo_data.len();
+
+    let mut record = vec![0u8; (0x30 + client_data_length + 7) & !7];
+    LittleEndian::write_u64(&mut record[0x00..0x08], lsn(offset));
+    LittleEndian::write_u32(&mut record[0x18..0x1C], client_data_length as u32);
+    LittleEndian::write_u32(&mut record[0x20..0x24], 1);
+    // Update non-resident value
+    LittleEndian::write_u16(&mut record[0x30..0x32], 0x08);
+    LittleEndian::write_u16(&mut record[0x34..0x36], 0x20);
+    LittleEndian::write_u16(&mut record[0x36..0x38], redo_data.len() as u16);
+    record[0x50..0x50 + redo_data.len()].copy_from_slice(redo_data);
+    record
+}
+
+/// Build a $LogFile with two restart pages and four log record pages. The second
+/// log record spans the first and second pages, and the third page is torn.
+fn logfile() -> Vec<u8> {
+    let mut logfile = restart_page();
+    logfile.extend(restart_page());
+
+    let mut pages = vec![0u8; 4 * PAGE_SIZE];
+    let records = [
+        (0x40, usn_record(2, 1000, "a.txt")),
+        (0xF90, usn_record(3, 1072, "spanning.txt")),
+        (2 * PAGE_SIZE + 0x40, usn_record(2, 1200, "torn.txt")),
+        (3 * PAGE_SIZE + 0x40, usn_record(2, 1300, "after.txt")),
+    ];
+
+    // The page data areas, without the page headers
+    let mut stream = Vec::new();
+    for (position, usn_record) in records.iter() {
+        let record = log_record(2 * PAGE_SIZE + position, usn_record);
+        let page_position = position % PAGE_SIZE;
+        let page = position / PAGE_SIZE;
+        let stream_position = page * (PAGE_SIZE - 0x40) + page_position - 0x40;
+        stream.resize(stream_position, 0);
+        stream.extend(record);
+    }
+    stream.resize(4 * (PAGE_SIZE - 0x40), 0);
+
+    for (page, page_data) in stream.chunks(PAGE_SIZE - 0x40).enumerate() {
+        let page_buffer = &mut pages[page * PAGE_SIZE..(page + 1) * PAGE_SIZE];
+        page_buffer[0..4].copy_from_slice(b"RCRD");
+        page_buffer[0x40..].copy_from_slice(page_data);
+        protect(page_buffer, 0x28);
+    }
+
+    // Tear the third page
+    pages[2 * PAGE_SIZE + 1022] = 0x08;
+
+    logfile.extend(pages);
+    logfile
+}
+
+#[test]
+fn fixups_test() {
+    let mut page = vec![0u8; PAGE_SIZE];
+    page[0..4].copy_from_slice(b"RCRD");
+    page[510] = 0xAB;
+    page[511] = 0xCD;
+    protect(&mut page, 0x28);
+    assert_eq!(&page[510..512], &[0x07, 0x00]);
+
+    let mut torn_page = page.clone();
+    apply_fixups(&mut page).unwrap();
+    assert_eq!(&page[510..512], &[0xAB, 0xCD]);
+
+    torn_page[2047] = 0x01;
+    assert!(apply_fixups(&mut torn_page).is_err());
+}
+
+#[test]
+fn logfile_records_test() {
+    let mut parser = LogFileParser::from_read_seek(
+        "$LogFile".to_string(),
+        Cursor::new(logfile())
+    ).unwrap();
+
+    let layout = parser.layout();
+    assert_eq!(layout.log_page_size, PAGE_SIZE);
+    assert_eq!(layout.seq_number_bits, Some(SEQ_NUMBER_BITS));
+
+    let entries: Vec<_> = parser.records().collect();
+    let found: Vec<(String, u64, u64, Option<u64>)> = entries.iter()
+        .map(|entry| (
+            entry.record.get_file_name(),
+            entry.record.get_usn(),
+            entry.meta.offset,
+            entry.meta.lsn
+        ))
+        .collect();
+
+    // The record of the torn page is not recovered
+    assert_eq!(found, vec![
+        ("a.txt".to_string(), 1000, 0x2090, Some(lsn(0x2040))),
+        ("spanning.txt".to_string(), 1072, 0x2FE0, Some(lsn(0x2F90))),
+        ("after.txt".to_string(), 1300, 0x5090, Some(lsn(0x5040))),
+    ]);
+
+    assert_eq!(entries[1].record.get_major_version(), 3);
+}
+
+#[test]
+fn logfile_default_layout_test() {
+    // Without restart pages the default layout is used, and the LSNs are not
+    // checked against their offsets
+    let mut logfile = logfile();
+    for byte in logfile[..2 * PAGE_SIZE].iter_mut() {
+        *byte = 0;
+    }
+
+    let mut parser = LogFileParser::from_read_seek(
+        "$LogFile".to_string(),
+        Cursor::new(logfile)
+    ).unwrap();
+
+    assert_eq!(parser.layout().seq_number_bits, None);
+    assert_eq!(parser.records().count(), 3);
+}
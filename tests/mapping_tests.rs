@@ -0,0 +1,77 @@
+extern crate rusty_usn;
+mod common;
+use common::v2_entry;
+use winstructs::ntfs::mft_reference::MftReference;
+use chrono::{TimeZone, Utc};
+use rusty_usn::mapping::{DeltaKind, EntryMapping, FolderMapping, MappingDelta};
+
+
+fn delta(usn: u64, kind: DeltaKind, name: &str, parent: MftReference) -> MappingDelta {
+    MappingDelta {
+        usn,
+        timestamp: Some(Utc.timestamp(usn as i64, 0)),
+        kind,
+        mapping: EntryMapping {
+            name: name.to_string(),
+            parent
+        }
+    }
+}
+
+/// [root]/Users/NewName, where NewName was created as OldName at USN 100 and
+/// renamed at USN 1000 (old name) and 1010 (new name)
+fn renamed_folder_mapping() -> FolderMapping {
+    let users = MftReference::new(100, 1);
+    let folder = MftReference::new(200, 1);
+
+    let mut mapping = FolderMapping::new();
+    mapping.add_mapping(users, "Users".to_string(), MftReference::new(5, 5));
+    mapping.add_mapping(folder, "NewName".to_string(), users);
+    mapping.add_delta(folder, delta(1010, DeltaKind::After, "NewName", users));
+    mapping.add_delta(folder, delta(1000, DeltaKind::Before, "OldName", users));
+    mapping.add_delta(folder, delta(100, DeltaKind::After, "OldName", users));
+    mapping
+}
+
+#[test]
+fn enumerate_path_at_test() {
+    let mapping = renamed_folder_mapping();
+
+    assert_eq!(mapping.enumerate_path_at(200, 1, 50), "[root]/Users/OldName");
+    assert_eq!(mapping.enumerate_path_at(200, 1, 500), "[root]/Users/OldName");
+    assert_eq!(mapping.enumerate_path_at(200, 1, 1000), "[root]/Users/OldName");
+    assert_eq!(mapping.enumerate_path_at(200, 1, 1010), "[root]/Users/NewName");
+    assert_eq!(mapping.enumerate_path_at(200, 1, 5000), "[root]/Users/NewName");
+    assert_eq!(mapping.enumerate_path_at(300, 1, 1001), "[<unknown>]");
+
+    assert_eq!(mapping.enumerate_path_at_time(200, 1, Utc.timestamp(500, 0)), "[root]/Users/OldName");
+    assert_eq!(mapping.enumerate_path_at_time(200, 1, Utc.timestamp(2000, 0)), "[root]/Users/NewName");
+}
+
+#[test]
+fn enumerate_path_at_time_order_test() {
+    // The clock was set back before the rename at USN 2000, so its timestamp is
+    // before the create at USN 100
+    let users = MftReference::new(100, 1);
+    let folder = MftReference::new(200, 1);
+
+    let mut mapping = FolderMapping::new();
+    mapping.add_mapping(users, "Users".to_string(), MftReference::new(5, 5));
+    mapping.add_delta(folder, delta(100, DeltaKind::After, "OldName", users));
+    let mut renamed = delta(2000, DeltaKind::After, "NewName", users);
+    renamed.timestamp = Some(Utc.timestamp(50, 0));
+    mapping.add_delta(folder, renamed);
+
+    assert_eq!(mapping.enumerate_path_at(200, 1, 1000), "[root]/Users/OldName");
+    assert_eq!(mapping.enumerate_path_at_time(200, 1, Utc.timestamp(60, 0)), "[root]/Users/NewName");
+    assert_eq!(mapping.enumerate_path_at_time(200, 1, Utc.timestamp(1000, 0)), "[root]/Users/OldName");
+}
+
+#[test]
+fn add_journal_entry_test() {
+    let mut mapping = FolderMapping::new();
+
+    // A file record does not change the folder mapping
+    assert!(!mapping.add_journal_entry(&v2_entry()));
+    assert!(mapping.deltas.is_empty());
+}
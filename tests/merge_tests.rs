@@ -0,0 +1,98 @@
+extern crate rusty_usn;
+mod common;
+use common::V2_RECORD;
+use std::io::Cursor;
+use rusty_usn::merge::{MergeOrder, MergedRecords};
+use rusty_usn::record::{EntryMeta, UsnEntry};
+use rusty_usn::usn::UsnParser;
+
+
+/// A V2 record with the given USN and timestamp (as a FILETIME).
+fn record(usn: u64, filetime: u64) -> Vec<u8> {
+    let mut record_buffer = V2_RECORD.to_vec();
+    record_buffer[24..32].copy_from_slice(&usn.to_le_bytes());
+    record_buffer[32..40].copy_from_slice(&filetime.to_le_bytes());
+    record_buffer
+}
+
+fn entry(source: &str, usn: u64, filetime: u64) -> UsnEntry {
+    UsnEntry::new(
+        EntryMeta::new(source, usn),
+        2,
+        record(usn, filetime).as_slice()
+    ).unwrap()
+}
+
+/// A $J of one page with records back to back.
+fn journal(records: &[Vec<u8>]) -> Vec<u8> {
+    let mut page = vec![0u8; 4096];
+    let mut index = 0;
+    for record in records {
+        page[index..index + record.len()].copy_from_slice(record);
+        index += record.len();
+    }
+    page
+}
+
+#[test]
+fn merge_by_usn_test() {
+    let vss1 = vec![entry("vss1", 100, 0), entry("vss1", 300, 0), entry("vss1", 500, 0)];
+    let vss2 = vec![entry("vss2", 200, 0), entry("vss2", 300, 0), entry("vss2", 400, 0)];
+
+    let merged: Vec<(u64, String)> = MergedRecords::new(
+        vec![vss1.into_iter(), vss2.into_iter()],
+        MergeOrder::Usn
+    ).map(|entry| (entry.record.get_usn(), entry.meta.source))
+        .collect();
+
+    assert_eq!(merged, vec![
+        (100, "vss1".to_string()),
+        (200, "vss2".to_string()),
+        (300, "vss1".to_string()),
+        (300, "vss2".to_string()),
+        (400, "vss2".to_string()),
+        (500, "vss1".to_string()),
+    ]);
+}
+
+#[test]
+fn merge_by_timestamp_test() {
+    let first = vec![entry("first", 100, 131234567800000000), entry("first", 200, 131234567830000000)];
+    let second = vec![entry("second", 150, 131234567810000000), entry("second", 250, 131234567820000000)];
+
+    let usns: Vec<u64> = MergedRecords::new(
+        vec![first.into_iter(), second.into_iter()],
+        MergeOrder::Timestamp
+    ).map(|entry| entry.record.get_usn())
+        .collect();
+
+    assert_eq!(usns, vec![100, 150, 250, 200]);
+}
+
+#[test]
+fn merge_parsers_test() {
+    let mut parsers = [
+        UsnParser::from_read_seek(
+            "vss1".to_string(),
+            Cursor::new(journal(&[record(100, 0), record(300, 0)]))
+        ).unwrap(),
+        UsnParser::from_read_seek(
+            "vss2".to_string(),
+            Cursor::new(journal(&[record(200, 0)]))
+        ).unwrap(),
+    ];
+
+    let inputs = parsers.iter_mut()
+        .map(|parser| parser.records())
+        .collect();
+
+    let merged: Vec<(u64, String, u64)> = MergedRecords::new(inputs, MergeOrder::Usn)
+        .map(|entry| (entry.record.get_usn(), entry.meta.source, entry.meta.offset))
+        .collect();
+
+    assert_eq!(merged, vec![
+        (100, "vss1".to_string(), 0),
+        (200, "vss2".to_string(), 0),
+        (300, "vss1".to_string(), 96),
+    ]);
+}
@@ -0,0 +1,68 @@
+extern crate rusty_usn;
+mod common;
+use common::v2_entry;
+use serde_json::json;
+use rusty_usn::flags::FlagFormat;
+use rusty_usn::output::{JsonlWriter, OutputFormat, OutputWriter};
+
+
+fn write_output(format: OutputFormat) -> String {
+    let entry = v2_entry();
+    let mut buffer: Vec<u8> = Vec::new();
+
+    {
+        let mut writer = format.get_writer(&mut buffer, FlagFormat::Text);
+        writer.write_entry(&entry, Some(json!({"full_name": "[root]/Logs/BTDevManager.log"}))).unwrap();
+        writer.flush().unwrap();
+    }
+
+    String::from_utf8(buffer).unwrap()
+}
+
+#[test]
+fn csv_output_test() {
+    let output = write_output(OutputFormat::Csv);
+
+    assert_eq!(output, "meta__source,meta__offset,major_version,usn,timestamp,file_reference_entry,file_reference_sequence,parent_reference_entry,parent_reference_sequence,file_name,full_name,reason,file_attributes,source_info\n\
+        Test Buffer,0,2,20342374400,2013-10-19T12:16:53.276040Z,115,37224,141883,7,BTDevManager.log,[root]/Logs/BTDevManager.log,USN_REASON_DATA_EXTEND,FILE_ATTRIBUTE_ARCHIVE|FILE_ATTRIBUTE_NOT_CONTENT_INDEXED,\n");
+}
+
+#[test]
+fn tsv_output_test() {
+    let output = write_output(OutputFormat::Tsv);
+    let lines: Vec<&str> = output.lines().collect();
+
+    assert_eq!(lines.len(), 2);
+    assert_eq!(lines[0].split('\t').count(), 14);
+    assert!(lines[1].starts_with("Test Buffer\t0\t2\t20342374400\t"));
+}
+
+#[test]
+fn jsonl_output_test() {
+    let output = write_output(OutputFormat::Jsonl);
+
+    assert_eq!(output, "{\"file_attributes\":\"FILE_ATTRIBUTE_ARCHIVE | FILE_ATTRIBUTE_NOT_CONTENT_INDEXED\",\"file_name\":\"BTDevManager.log\",\"file_name_length\":32,\"file_name_offset\":60,\"file_reference\":{\"entry\":115,\"sequence\":37224},\"full_name\":\"[root]/Logs/BTDevManager.log\",\"major_version\":2,\"meta__offset\":0,\"meta__source\":\"Test Buffer\",\"minor_version\":0,\"parent_reference\":{\"entry\":141883,\"sequence\":7},\"reason\":\"USN_REASON_DATA_EXTEND\",\"record_length\":96,\"security_id\":0,\"source_info\":\"(empty)\",\"timestamp\":\"2013-10-19T12:16:53.276040Z\",\"usn\":20342374400}\n");
+}
+
+#[test]
+fn bodyfile_output_test() {
+    let output = write_output(OutputFormat::Bodyfile);
+
+    assert_eq!(output, "0|[root]/Logs/BTDevManager.log ($UsnJrnl: DATA_EXTEND)|115-37224|r/r|0|0|0|1382185013|1382185013|1382185013|1382185013\n");
+}
+
+#[test]
+fn jsonl_flag_format_test() {
+    let entry = v2_entry();
+    let mut buffer: Vec<u8> = Vec::new();
+
+    {
+        let mut writer = JsonlWriter::new(&mut buffer).with_flag_format(FlagFormat::Short);
+        writer.write_entry(&entry, None).unwrap();
+    }
+
+    let value: serde_json::Value = serde_json::from_slice(&buffer).unwrap();
+    assert_eq!(value["reason"], json!(["DATA_EXTEND"]));
+    assert_eq!(value["file_attributes"], json!(["ARCHIVE", "NOT_CONTENT_INDEXED"]));
+    assert_eq!(value["source_info"], json!([]));
+}
@@ -1,7 +1,7 @@
 extern crate rusty_usn;
 extern crate serde_json;
 use std::io::Cursor;
-use rusty_usn::record;
+use rusty_usn::{flags, record};
 
 
 #[test]
@@ -200,3 +200,83 @@ fn usn_record_v2_json_test() {
 
     assert_eq!(json_str, r#"{"record_length":96,"major_version":2,"minor_version":0,"file_reference":{"entry":115,"sequence":37224},"parent_reference":{"entry":141883,"sequence":7},"usn":20342374400,"timestamp":"2013-10-19T12:16:53.276040Z","reason":"USN_REASON_DATA_EXTEND","source_info":"(empty)","security_id":0,"file_attributes":"FILE_ATTRIBUTE_ARCHIVE | FILE_ATTRIBUTE_NOT_CONTENT_INDEXED","file_name_length":32,"file_name_offset":60,"file_name":"BTDevManager.log"}"#);
 }
+
+#[test]
+fn usn_record_v4_test() {
+    let record_buffer: &[u8] = &[
+        0x50,0x00,0x00,0x00,0x04,0x00,0x00,0x00,0xB9,0x8A,0x00,0x00,0x00,0x00,0x02,0x00,
+        0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0xC8,0x07,0x00,0x00,0x00,0x00,0x02,0x00,
+        0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x60,0x78,0xA2,0x9A,0x01,0x00,0x00,0x00,
+        0x01,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x01,0x00,0x10,0x00,
+        0x00,0x10,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x20,0x00,0x00,0x00,0x00,0x00,0x00
+    ];
+
+    let record = match record::UsnRecordV4::new(&mut Cursor::new(record_buffer)) {
+        Ok(record) => record,
+        Err(error) => panic!("{:?}", error)
+    };
+
+    assert_eq!(record.record_length, 80);
+    assert_eq!(record.major_version, 4);
+    assert_eq!(record.minor_version, 0);
+
+    let file_ref = record.file_reference.as_mft_reference();
+    assert_eq!(file_ref.entry, 35513);
+    assert_eq!(file_ref.sequence, 2);
+
+    let parent_ref = record.parent_reference.as_mft_reference();
+    assert_eq!(parent_ref.entry, 1992);
+    assert_eq!(parent_ref.sequence, 2);
+
+    assert_eq!(record.usn, 6889306208);
+    assert_eq!(record.reason.bits(), 1);
+    assert_eq!(record.source_info.bits(), 0);
+    assert_eq!(record.remaining_extents, 0);
+    assert_eq!(record.number_of_extents, 1);
+    assert_eq!(record.extent_size, 16);
+    assert_eq!(record.extents.len(), 1);
+    assert_eq!(record.extents[0].offset, 4096);
+    assert_eq!(record.extents[0].length, 8192);
+
+    let json_str = serde_json::to_string(&record).unwrap();
+    assert_eq!(json_str, r#"{"record_length":80,"major_version":4,"minor_version":0,"file_reference":{"u128":"562949953456825","entry":35513,"sequence":2},"parent_reference":{"u128":"562949953423304","entry":1992,"sequence":2},"usn":6889306208,"reason":"USN_REASON_DATA_OVERWRITE","source_info":"(empty)","remaining_extents":0,"number_of_extents":1,"extent_size":16,"extents":[{"offset":4096,"length":8192}]}"#);
+}
+
+#[test]
+fn usn_record_unknown_bits_test() {
+    // The v2 record with a reason of 0x41000002 and attributes of 0x40082020,
+    // 0x40000000 is not a defined reason or attribute.
+    let record_buffer: &[u8] = &[
+        0x60,0x00,0x00,0x00,0x02,0x00,0x00,0x00,0x73,0x00,0x00,0x00,0x00,0x00,0x68,0x91,
+        0x3B,0x2A,0x02,0x00,0x00,0x00,0x07,0x00,0x00,0x00,0x80,0xBC,0x04,0x00,0x00,0x00,
+        0x53,0xC7,0x8B,0x18,0xC5,0xCC,0xCE,0x01,0x02,0x00,0x00,0x41,0x00,0x00,0x00,0x00,
+        0x00,0x00,0x00,0x00,0x20,0x20,0x08,0x40,0x20,0x00,0x3C,0x00,0x42,0x00,0x54,0x00,
+        0x44,0x00,0x65,0x00,0x76,0x00,0x4D,0x00,0x61,0x00,0x6E,0x00,0x61,0x00,0x67,0x00,
+        0x65,0x00,0x72,0x00,0x2E,0x00,0x6C,0x00,0x6F,0x00,0x67,0x00,0x00,0x00,0x00,0x00
+    ];
+
+    let record = match record::UsnRecordV2::new(&mut Cursor::new(record_buffer)) {
+        Ok(record) => record,
+        Err(error) => panic!("{:?}", error)
+    };
+
+    assert_eq!(record.raw_reason, 0x4100_0002);
+    assert_eq!(record.reason.bits(), 0x4100_0002);
+    assert!(record.reason.contains(flags::Reason::USN_REASON_DESIRED_STORAGE_CLASS_CHANGE));
+    assert_eq!(record.raw_file_attributes, 0x4008_2020);
+    assert!(record.file_attributes.contains(flags::FileAttributes::FILE_ATTRIBUTE_PINNED));
+    assert_eq!(record.unknown_bits, record::UnknownBits {
+        reason: 0x4000_0000,
+        source_info: 0,
+        file_attributes: 0x4000_0000,
+    });
+
+    let json_value = serde_json::to_value(&record).unwrap();
+    assert_eq!(json_value["reason"], "USN_REASON_DATA_EXTEND | USN_REASON_DESIRED_STORAGE_CLASS_CHANGE | 0x40000000");
+    assert_eq!(json_value["unknown_bits"], serde_json::json!({
+        "reason": 0x4000_0000,
+        "source_info": 0,
+        "file_attributes": 0x4000_0000
+    }));
+    assert!(json_value.get("raw_reason").is_none());
+}
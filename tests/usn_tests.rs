@@ -0,0 +1,348 @@
+extern crate rusty_usn;
+mod common;
+use common::V2_RECORD;
+use std::io::Cursor;
+use rusty_usn::record::EntryMeta;
+use rusty_usn::usn::{CarveDiagnostic, DiagnosticCount, DiagnosticReason, IterRecords, IterRecordResults};
+use rusty_usn::usn::{IterRecordResultsByIndex, IterRecordsByIndex};
+use rusty_usn::usn::{ParseMode, ParseStatistics, UsnParser, UsnParserSettings};
+
+const V3_RECORD: &[u8] = &[
+    0x70,0x00,0x00,0x00,0x03,0x00,0x00,0x00,0xB9,0x8A,0x00,0x00,0x00,0x00,0x02,0x00,
+    0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0xC8,0x07,0x00,0x00,0x00,0x00,0x02,0x00,
+    0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x60,0x78,0xA2,0x9A,0x01,0x00,0x00,0x00,
+    0xE9,0xB6,0x4E,0x4D,0xE0,0x65,0xD5,0x01,0x02,0x00,0x00,0x00,0x00,0x00,0x00,0x00,
+    0x00,0x00,0x00,0x00,0x20,0x00,0x00,0x00,0x20,0x00,0x4C,0x00,0x43,0x00,0x49,0x00,
+    0x44,0x00,0x6F,0x00,0x77,0x00,0x6E,0x00,0x6C,0x00,0x6F,0x00,0x61,0x00,0x64,0x00,
+    0x65,0x00,0x72,0x00,0x2E,0x00,0x6C,0x00,0x6F,0x00,0x67,0x00,0x00,0x00,0x00,0x00
+];
+
+const V4_RECORD: &[u8] = &[
+    0x50,0x00,0x00,0x00,0x04,0x00,0x00,0x00,0xB9,0x8A,0x00,0x00,0x00,0x00,0x02,0x00,
+    0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0xC8,0x07,0x00,0x00,0x00,0x00,0x02,0x00,
+    0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x60,0x78,0xA2,0x9A,0x01,0x00,0x00,0x00,
+    0x01,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x01,0x00,0x10,0x00,
+    0x00,0x10,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x20,0x00,0x00,0x00,0x00,0x00,0x00
+];
+
+
+/// Build a zero padded page with the given records written back to back.
+fn build_page(records: &[&[u8]]) -> Vec<u8> {
+    let mut page = vec![0u8; 4096];
+    let mut index = 0;
+    for record in records {
+        page[index..index + record.len()].copy_from_slice(record);
+        index += record.len();
+    }
+    page
+}
+
+fn mixed_version_page() -> Vec<u8> {
+    build_page(&[V2_RECORD, V3_RECORD])
+}
+
+#[test]
+fn carve_v3_records_test() {
+    let page = mixed_version_page();
+    let search_size = page.len();
+
+    let records: Vec<_> = IterRecords::new(
+        String::from("Test Buffer"),
+        page,
+        0,
+        search_size
+    ).collect();
+
+    assert_eq!(records.len(), 2);
+
+    assert_eq!(records[0].meta.offset, 0);
+    assert_eq!(records[0].record.get_usn(), 20342374400);
+
+    assert_eq!(records[1].meta.offset, 96);
+    assert_eq!(records[1].record.get_usn(), 6889306208);
+    assert_eq!(records[1].record.get_file_name(), "CIDownloader.log");
+
+    let file_ref = records[1].record.get_file_reference();
+    assert_eq!(file_ref.entry, 35513);
+    assert_eq!(file_ref.sequence, 2);
+}
+
+#[test]
+fn parser_v3_records_test() {
+    let mut parser = UsnParser::from_read_seek(
+        String::from("Test Buffer"),
+        Cursor::new(mixed_version_page())
+    ).unwrap();
+
+    let usns: Vec<u64> = parser.records()
+        .map(|entry| entry.record.get_usn())
+        .collect();
+
+    assert_eq!(usns, vec![20342374400, 6889306208]);
+}
+
+#[test]
+fn carve_v4_records_test() {
+    let page = build_page(&[V3_RECORD, V4_RECORD]);
+    let search_size = page.len();
+
+    let records: Vec<_> = IterRecords::new(
+        String::from("Test Buffer"),
+        page,
+        0,
+        search_size
+    ).collect();
+
+    assert_eq!(records.len(), 2);
+    assert_eq!(records[1].meta.offset, 112);
+    assert_eq!(records[1].record.get_file_name(), "");
+
+    let json_value = records[1].to_json_value().unwrap();
+    assert_eq!(json_value["extents"].to_string(), r#"[{"length":8192,"offset":4096}]"#);
+}
+
+#[test]
+fn skip_leading_zeros_test() {
+    // 40 zero pages, like the sparse start of a $J, then the records
+    let mut data = vec![0u8; 40 * 4096];
+    data.extend(mixed_version_page());
+    data.extend(build_page(&[V3_RECORD]));
+
+    let mut parser = UsnParser::from_read_seek(
+        String::from("Test Buffer"),
+        Cursor::new(data)
+    ).unwrap().with_configuration(
+        UsnParserSettings::new().skip_leading_zeros(true)
+    );
+
+    assert_eq!(parser.find_first_data_page().unwrap(), 40 * 4096);
+
+    let offsets: Vec<u64> = parser.records()
+        .map(|entry| entry.meta.offset)
+        .collect();
+
+    assert_eq!(offsets, vec![40 * 4096, 40 * 4096 + 96, 41 * 4096]);
+}
+
+#[test]
+fn data_regions_test() {
+    let mut data = Vec::new();
+    for _ in 0..4 {
+        data.extend(build_page(&[V3_RECORD]));
+    }
+
+    // The first and third pages are treated as holes
+    let mut parser = UsnParser::from_read_seek(
+        String::from("Test Buffer"),
+        Cursor::new(data)
+    ).unwrap().with_data_regions(vec![4096..8192, 12288..16384]);
+
+    let offsets: Vec<u64> = parser.records()
+        .map(|entry| entry.meta.offset)
+        .collect();
+
+    assert_eq!(offsets, vec![4096, 12288]);
+}
+
+#[test]
+fn structured_parse_mode_test() {
+    let mut data = mixed_version_page();
+    data.extend(build_page(&[V3_RECORD]));
+
+    let mut parser = UsnParser::from_read_seek(
+        String::from("Test Buffer"),
+        Cursor::new(data)
+    ).unwrap().with_configuration(
+        UsnParserSettings::new().parse_mode(ParseMode::Structured)
+    );
+
+    let offsets: Vec<u64> = parser.records()
+        .map(|entry| entry.meta.offset)
+        .collect();
+
+    assert_eq!(offsets, vec![0, 96, 4096]);
+    assert_eq!(parser.statistics(), ParseStatistics {
+        structured_records: 3,
+        carved_records: 0,
+        carved_pages: 0
+    });
+}
+
+#[test]
+fn structured_damaged_page_test() {
+    // A record, 16 bytes of garbage, then a record the walk can not reach
+    let mut page = vec![0u8; 4096];
+    page[..V2_RECORD.len()].copy_from_slice(V2_RECORD);
+    page[96..112].copy_from_slice(&[0xAB; 16]);
+    page[112..112 + V3_RECORD.len()].copy_from_slice(V3_RECORD);
+
+    let mut parser = UsnParser::from_read_seek(
+        String::from("Test Buffer"),
+        Cursor::new(page)
+    ).unwrap().with_configuration(
+        UsnParserSettings::new().parse_mode(ParseMode::Structured)
+    );
+
+    let offsets: Vec<u64> = parser.records()
+        .map(|entry| entry.meta.offset)
+        .collect();
+
+    assert_eq!(offsets, vec![0, 112]);
+    assert_eq!(parser.statistics(), ParseStatistics {
+        structured_records: 1,
+        carved_records: 1,
+        carved_pages: 1
+    });
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn sparse_file_test() {
+    use std::fs::{self, OpenOptions};
+    use std::io::{Seek, SeekFrom, Write};
+    use rusty_usn::utils::get_file_data_regions;
+
+    let path = std::env::temp_dir().join(format!("rusty_usn_sparse_{}.J", std::process::id()));
+    {
+        let mut file = OpenOptions::new().create(true).write(true).truncate(true).open(&path).unwrap();
+        file.seek(SeekFrom::Start(1048576)).unwrap();
+        file.write_all(&mixed_version_page()).unwrap();
+    }
+
+    let file = fs::File::open(&path).unwrap();
+    if let Some(regions) = get_file_data_regions(&file) {
+        let last = regions.last().unwrap();
+        assert!(last.start <= 1048576 && last.end == 1048576 + 4096);
+    }
+
+    let mut parser = UsnParser::from_path(path.to_str().unwrap()).unwrap();
+    let offsets: Vec<u64> = parser.records()
+        .map(|entry| entry.meta.offset)
+        .collect();
+    fs::remove_file(&path).unwrap();
+
+    assert_eq!(offsets, vec![1048576, 1048576 + 96]);
+}
+
+
+#[test]
+fn record_results_test() {
+    let mut page = build_page(&[V2_RECORD, V2_RECORD, V2_RECORD, V2_RECORD]);
+    // A record length that is not 8 byte aligned
+    page[96] = 0x61;
+    // A name offset that is not 60
+    page[192 + 58] = 0x3E;
+    // A record header cut off by the end of the block
+    page[4080..4088].copy_from_slice(&V2_RECORD[..8]);
+    let search_size = page.len();
+
+    let results: Vec<Result<u64, CarveDiagnostic>> = IterRecordResults::new(
+        String::from("Test Buffer"),
+        page,
+        0,
+        search_size
+    ).map(|result| result.map(|entry| entry.meta.offset)).collect();
+
+    let diagnostic = |offset, reason| Err(CarveDiagnostic::new("Test Buffer", offset, reason));
+    assert_eq!(results, vec![
+        Ok(0),
+        diagnostic(96, DiagnosticReason::MisalignedLength),
+        diagnostic(192, DiagnosticReason::BadNameOffset),
+        Ok(288),
+        diagnostic(4080, DiagnosticReason::ShortRead),
+    ]);
+}
+
+#[test]
+fn record_results_by_index_test() {
+    // A misaligned record length between two records, followed by a V4 record
+    // cut off by the end of the buffer
+    let mut buffer = V2_RECORD.to_vec();
+    buffer.extend(&[0x61, 0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00]);
+    buffer.extend(V3_RECORD);
+    buffer.extend(&V4_RECORD[..16]);
+
+    let results: Vec<Result<u64, CarveDiagnostic>> = IterRecordResultsByIndex::new(
+        EntryMeta::new("Test Buffer", 0),
+        buffer.clone()
+    ).map(|result| result.map(|entry| entry.meta.offset)).collect();
+
+    let diagnostic = |offset, reason| Err(CarveDiagnostic::new("Test Buffer", offset, reason));
+    assert_eq!(results, vec![
+        Ok(0),
+        diagnostic(96, DiagnosticReason::MisalignedLength),
+        Ok(104),
+        diagnostic(216, DiagnosticReason::ShortRead),
+        diagnostic(224, DiagnosticReason::MisalignedLength),
+    ]);
+
+    let offsets: Vec<u64> = IterRecordsByIndex::new(EntryMeta::new("Test Buffer", 0), buffer)
+        .map(|entry| entry.meta.offset)
+        .collect();
+    assert_eq!(offsets, vec![0, 104]);
+}
+
+#[test]
+fn parse_report_test() {
+    // A record with a minor version of 1 stops the walk of the page
+    let mut page = build_page(&[V2_RECORD, V2_RECORD, V2_RECORD]);
+    page[96 + 6] = 0x01;
+
+    let mut parser = UsnParser::from_read_seek(
+        String::from("Test Buffer"),
+        Cursor::new(page)
+    ).unwrap().with_configuration(
+        UsnParserSettings::new().parse_mode(ParseMode::Structured)
+    );
+
+    let offsets: Vec<u64> = parser.records()
+        .map(|entry| entry.meta.offset)
+        .collect();
+
+    assert_eq!(offsets, vec![0, 192]);
+
+    let report = parser.report();
+    assert_eq!(report.statistics.records(), 2);
+    assert_eq!(report.rejected(), 1);
+    assert_eq!(
+        report.diagnostics.get(&DiagnosticReason::BadMinorVersion),
+        Some(&DiagnosticCount { count: 1, first_offset: 96 })
+    );
+}
+
+#[test]
+fn read_error_report_test() {
+    use std::io::{self, Read, Seek, SeekFrom};
+
+    /// A 64 KB source that fails to read.
+    struct FailingReader;
+
+    impl Read for FailingReader {
+        fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
+            Err(io::Error::new(io::ErrorKind::Other, "bad sector"))
+        }
+    }
+
+    impl Seek for FailingReader {
+        fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+            Ok(match pos {
+                SeekFrom::End(_) => 65536,
+                _ => 0
+            })
+        }
+    }
+
+    let mut parser = UsnParser::from_read_seek(
+        String::from("Test Buffer"),
+        FailingReader
+    ).unwrap();
+
+    assert_eq!(parser.records().count(), 0);
+
+    let report = parser.report();
+    assert_eq!(report.rejected(), 0);
+    assert_eq!(
+        report.diagnostics.get(&DiagnosticReason::ReadError),
+        Some(&DiagnosticCount { count: 1, first_offset: 0 })
+    );
+}